@@ -0,0 +1,91 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use flate2::Compression;
+use project_squirrel::archive::ParallelGzEncoder;
+use project_squirrel::ntfs::{DataRun, RunReader};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Write};
+use std::sync::Arc;
+
+const CLUSTER_SIZE: u64 = 4096;
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+const TEXT: &[u8] = b"C:\\Windows\\System32\\config\\SYSTEM ";
+
+// A volume image that compresses about as well as a system drive: runs of text like
+// data, some random bytes and some zeroes.
+fn image() -> Vec<u8> {
+    let mut data = Vec::with_capacity(IMAGE_SIZE);
+    let mut seed = 0x2545_F491_4F6C_DD1Du64;
+    while data.len() < IMAGE_SIZE {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        match seed % 4 {
+            0 => data.extend_from_slice(&[0; 4096]),
+            1 => data.extend((0..4096).flat_map(|x| (seed ^ x).to_le_bytes())),
+            _ => data.extend(TEXT.iter().cycle().take(4096)),
+        }
+    }
+    data.truncate(IMAGE_SIZE);
+    data
+}
+
+// Fragments the image into runs of 1 to 64 clusters in reverse order, with a hole
+// after every tenth one like a sparse file.
+fn runs(size: u64) -> Arc<[DataRun]> {
+    let clusters = size / CLUSTER_SIZE;
+    let mut runs = Vec::new();
+    let mut virt_offset = 0;
+    let mut end = clusters;
+    while end > 0 {
+        let len = (u64::try_from(runs.len()).unwrap() % 64 + 1).min(end);
+        let offset = if runs.len() % 10 == 9 {
+            None
+        } else {
+            Some((end - len) * CLUSTER_SIZE)
+        };
+        runs.push(DataRun {
+            offset,
+            virt_offset,
+            len: len * CLUSTER_SIZE,
+        });
+        virt_offset = virt_offset + len * CLUSTER_SIZE;
+        end = end - len;
+    }
+    runs.into()
+}
+
+fn run_reader(c: &mut Criterion) {
+    let image = image();
+    let size = u64::try_from(image.len()).unwrap();
+    let runs = runs(size);
+    let mut group = c.benchmark_group("RunReader");
+    group.throughput(Throughput::Bytes(size));
+    group.sample_size(10);
+    group.bench_function("fragmented", |b| {
+        b.iter(|| {
+            let mut reader = RunReader::new(Cursor::new(&image[..]), runs.clone());
+            io::copy(&mut reader, &mut io::sink()).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn parallel_gz(c: &mut Criterion) {
+    let image = image();
+    let mut group = c.benchmark_group("ParallelGzEncoder");
+    group.throughput(Throughput::Bytes(u64::try_from(image.len()).unwrap()));
+    group.sample_size(10);
+    for (name, level) in [("fast", Compression::fast()), ("best", Compression::best())] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut encoder = ParallelGzEncoder::new(io::sink(), level);
+                encoder.write_all(&image).unwrap();
+                encoder.finish().unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_reader, parallel_gz);
+criterion_main!(benches);
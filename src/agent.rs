@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use ed25519_dalek::VerifyingKey;
+use getopts::Options;
+use json::JsonValue;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+use crate::collector::Outcome;
+use crate::control;
+use crate::log;
+use crate::signing;
+
+// How long the server may hold a poll open before answering that there's no task.
+const POLL_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn set_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Show this help information.");
+    opts.optopt(
+        "",
+        "server",
+        "Where to poll for collection tasks and send the results.",
+        "URL",
+    );
+    opts.optopt(
+        "",
+        "interval",
+        "Seconds to wait between polls. Defaults to 60.",
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "public-key",
+        "Hex encoded Ed25519 key the tasks have to be signed with.",
+        "KEY",
+    );
+    opts
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Task {
+    pub id: String,
+    pub args: Vec<String>,
+}
+
+// Polls the server for tasks and runs each one as a collection with the task's
+// arguments, the results are transferred to <server>/tasks/<id>. Tasks that
+// aren't signed with the public key, are for another host or have expired are
+// refused.
+pub fn run(args: &[String], collect: fn(&[String]) -> Outcome) {
+    let opts = set_opts();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => panic!("{:?}", f),
+    };
+    let (server, key) = match (matches.opt_str("server"), matches.opt_str("public-key")) {
+        (Some(server), Some(key)) if !matches.opt_present("help") => (server, key),
+        _ => {
+            let usage = "Usage: squirrel agent --server URL --public-key KEY";
+            return print!("{}", opts.usage(usage));
+        }
+    };
+    let key = signing::parse_key(&key).expect("Invalid public key");
+    let server = server.trim_end_matches('/');
+    let interval = matches.opt_str("interval").map_or(60, |x| {
+        x.parse()
+            .unwrap_or_else(|e| panic!("Invalid number of seconds {}: {}", x, e))
+    });
+    let agent = ureq::AgentBuilder::new()
+        .timeout_read(POLL_TIMEOUT + Duration::from_secs(30))
+        .build();
+    let host = env::var("COMPUTERNAME").unwrap_or_default();
+    log::info(format!("Polling {} for tasks", server));
+    while !control::is_stopping() {
+        match poll(&agent, server, &host, &key) {
+            Ok(Some(task)) => {
+                execute(&agent, server, &task, collect);
+                continue;
+            }
+            Ok(None) => (),
+            Err(e) => log::warn(format!("Polling failed: {}", e)),
+        }
+        // In steps so a stopping service doesn't wait for the whole interval
+        for _ in 0..interval {
+            if control::is_stopping() {
+                break;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+fn poll(
+    agent: &ureq::Agent,
+    server: &str,
+    host: &str,
+    key: &VerifyingKey,
+) -> Result<Option<Task>, String> {
+    let resp = agent
+        .get(&format!("{}/tasks", server))
+        .query("host", host)
+        .query("timeout", &POLL_TIMEOUT.as_secs().to_string())
+        .call()
+        .map_err(|e| e.to_string())?;
+    if resp.status() == 204 {
+        return Ok(None);
+    }
+    let body = resp.into_string().map_err(|e| e.to_string())?;
+    let signed = json::parse(&body).map_err(|e| e.to_string())?;
+    let task = signing::verify(key, &signed)?;
+    parse_task(&task, host, Utc::now()).map(Some)
+}
+
+// Tasks are {"id": ID, "host": COMPUTERNAME, "expires": RFC 3339 time, "args": [...]}.
+// The host and expiry are signed with the rest, so a captured task can't be replayed
+// to other hosts or once it expired.
+pub fn parse_task(task: &JsonValue, host: &str, now: DateTime<Utc>) -> Result<Task, String> {
+    let id = task["id"].as_str().ok_or("Task without an id")?;
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("Invalid task id: {}", id));
+    }
+    let target = task["host"].as_str().ok_or("Task without a host")?;
+    if target.is_empty() || !target.eq_ignore_ascii_case(host) {
+        return Err(format!("Task {} is for host {}, not {}", id, target, host));
+    }
+    let expires = task["expires"].as_str().ok_or("Task without an expiry")?;
+    let expires = DateTime::parse_from_rfc3339(expires)
+        .map_err(|e| format!("Invalid expiry of task {}: {}", id, e))?;
+    if expires.with_timezone(&Utc) <= now {
+        return Err(format!("Task {} expired at {}", id, expires));
+    }
+    let mut args = Vec::new();
+    for arg in task["args"].members() {
+        let arg = arg.as_str().ok_or("Task arguments must be strings")?;
+        // The results always go back to the server that sent the task
+        let short_d = !arg.starts_with("--") && arg.starts_with('-') && arg.contains('d');
+        if short_d || arg.starts_with("--destination") {
+            return Err(String::from("Tasks can't set the destination"));
+        }
+        args.push(String::from(arg));
+    }
+    Ok(Task {
+        id: String::from(id),
+        args,
+    })
+}
+
+fn execute(agent: &ureq::Agent, server: &str, task: &Task, collect: fn(&[String]) -> Outcome) {
+    log::info(format!("Running task {}", task.id));
+    let destination = format!("{}/tasks/{}", server, task.id);
+    let mut args = vec![String::from("squirrel")];
+    args.extend(task.args.iter().cloned());
+    args.push(String::from("--destination"));
+    args.push(destination.clone());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| collect(&args)));
+    let mut status = JsonValue::new_object();
+    status["id"] = task.id.as_str().into();
+    status["status"] = match result {
+        Ok(Outcome::Done) => "done",
+        Ok(Outcome::Partial) => "partial",
+        Err(_) => "failed",
+    }
+    .into();
+    let sent = agent
+        .post(&format!("{}/status", destination))
+        .set("Content-Type", "application/json")
+        .send_string(&status.dump());
+    if let Err(e) = sent {
+        log::warn(format!(
+            "Failed to report the status of task {}: {}",
+            task.id, e
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(host: &str, expires: &str, args: &[&str]) -> JsonValue {
+        let mut task = JsonValue::new_object();
+        task["id"] = "42".into();
+        task["host"] = host.into();
+        task["expires"] = expires.into();
+        task["args"] = args.into();
+        task
+    }
+
+    #[test]
+    fn test_parse_task() {
+        let now = DateTime::parse_from_rfc3339("2021-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let parse = |task: &JsonValue| parse_task(task, "WS042", now);
+        let later = "2021-06-01T12:05:00Z";
+        assert_eq!(
+            parse(&task("ws042", later, &["-r", "--parse", "prefetch"])),
+            Ok(Task {
+                id: String::from("42"),
+                args: vec![
+                    String::from("-r"),
+                    String::from("--parse"),
+                    String::from("prefetch")
+                ],
+            })
+        );
+        assert!(parse(&task("WS042", later, &["-d", "http://evil"])).is_err());
+        assert!(parse(&task("WS042", later, &["-rdhttp://evil"])).is_err());
+        let mut invalid = task("WS042", later, &[]);
+        invalid["id"] = "../x".into();
+        assert!(parse(&invalid).is_err());
+        // Signed for another host, or expired (13:00 at +01:00 is noon UTC)
+        assert!(parse(&task("WS043", later, &[])).is_err());
+        assert!(parse(&task("WS042", "2021-06-01T13:00:00+01:00", &[])).is_err());
+        let mut unbound = task("WS042", later, &[]);
+        unbound.remove("expires");
+        assert!(parse(&unbound).is_err());
+        unbound.remove("host");
+        assert!(parse(&unbound).is_err());
+    }
+}
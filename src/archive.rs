@@ -1,37 +1,830 @@
-use flate2::{write::GzEncoder, Compression};
+//! Writers for the collection output: a gzipped tar archive, an (encrypted) ZIP
+//! archive, an AFF4 logical image or a directory.
+
+use age::stream::StreamWriter;
+use age::x25519::Recipient;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
 use std::{
-    io::{self, Read, Write},
-    path::Path,
+    convert::TryFrom,
+    fs::{self, File, FileTimes},
+    io::{self, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use tar::{Builder, Header};
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod};
+
+use crate::log;
+pub use crate::pipeline::ParallelGzEncoder;
+
+/// The timestamps and Windows file attributes (FILE_ATTRIBUTE_*) of an entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileMeta {
+    pub modified: SystemTime,
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub attributes: Option<u32>,
+}
+
+impl FileMeta {
+    /// For files the collection generates itself, like the manifest.
+    pub fn now() -> FileMeta {
+        FileMeta {
+            modified: SystemTime::now(),
+            accessed: None,
+            created: None,
+            attributes: None,
+        }
+    }
+
+    pub fn from_metadata(metadata: &fs::Metadata) -> FileMeta {
+        #[cfg(windows)]
+        let attributes = Some(metadata.file_attributes());
+        #[cfg(not(windows))]
+        let attributes = None;
+        FileMeta {
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            accessed: metadata.accessed().ok(),
+            created: metadata.created().ok(),
+            attributes,
+        }
+    }
+}
 
 pub trait ArchiveWrite {
-    fn add_file<P: AsRef<Path>, R: Read>(&mut self, path: P, size: u64, data: R) -> io::Result<()>;
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()>;
+
+    /// Adds a file with the current time as its modification time.
+    fn add_file<P: AsRef<Path>, R: Read>(&mut self, path: P, size: u64, data: R) -> io::Result<()> {
+        self.add_entry(path, size, &FileMeta::now(), data)
+    }
 
     fn finish(&mut self) -> io::Result<()>;
 }
 
-impl<W: Write> ArchiveWrite for TarGzWriter<W> {
-    fn add_file<P: AsRef<Path>, R: Read>(&mut self, path: P, size: u64, data: R) -> io::Result<()> {
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
+
+impl<W: Finish + Send + 'static> ArchiveWrite for TarGzWriter<W> {
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()> {
+        // Tar has no creation time or attributes, these go in a PAX header the way
+        // libarchive stores the creation time
+        let mut pax = Vec::new();
+        if let Some(created) = meta.created {
+            pax.push(("LIBARCHIVE.creationtime", unix_time(created).to_string()));
+        }
+        if let Some(attributes) = meta.attributes {
+            pax.push(("SQUIRREL.fileattributes", attributes.to_string()));
+        }
+        if !pax.is_empty() {
+            let pax = pax.iter().map(|(key, value)| (*key, value.as_bytes()));
+            self.inner.append_pax_extensions(pax)?;
+        }
         let mut header = Header::new_gnu();
         header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(unix_time(meta.modified));
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.set_atime(meta.accessed.map_or(0, unix_time));
+        gnu.set_ctime(meta.created.map_or(0, unix_time));
         header.set_cksum();
         self.inner.append_data(&mut header, path, data)
     }
 
     fn finish(&mut self) -> io::Result<()> {
-        self.inner.finish()
+        self.inner.finish()?;
+        self.inner.get_mut().finish()?.finish()
     }
 }
 
-pub struct TarGzWriter<W: Write> {
-    inner: Builder<GzEncoder<W>>,
+pub struct TarGzWriter<W: Finish + Send + 'static> {
+    inner: Builder<ParallelGzEncoder<W>>,
 }
 
-impl<W: Write> TarGzWriter<W> {
+impl<W: Finish + Send + 'static> TarGzWriter<W> {
     pub fn new(inner: W) -> TarGzWriter<W> {
+        TarGzWriter::with_level(inner, Compression::fast())
+    }
+
+    pub fn with_level(inner: W, level: Compression) -> TarGzWriter<W> {
         TarGzWriter {
-            inner: Builder::new(GzEncoder::new(inner, Compression::fast())),
+            inner: Builder::new(ParallelGzEncoder::new(inner, level)),
+        }
+    }
+}
+
+/// What the tar archive is written to. Finishing it is the last write, like the final
+/// chunk of the encryption, and that can fail just like the writes before it.
+pub trait Finish: Write {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write> Finish for BufWriter<W> {}
+
+impl Finish for io::Sink {}
+
+impl Finish for Vec<u8> {}
+
+impl<W: Finish + ?Sized> Finish for &mut W {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<W: Finish + ?Sized> Finish for Box<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Encrypts the archive to age (X25519) recipients as it's written, so only the
+/// examiners' private keys can read it. The last chunk is written by finish, which
+/// the archive writer calls when it's finished. Dropping it without finishing writes
+/// the last chunk as well, but can only log a failure.
+pub struct Encrypted<W: Finish> {
+    inner: Option<StreamWriter<W>>,
+}
+
+impl<W: Finish> Encrypted<W> {
+    pub fn new(inner: W, recipients: &[Recipient]) -> io::Result<Encrypted<W>> {
+        let recipients = recipients.iter().map(|x| x as &dyn age::Recipient);
+        let encryptor = age::Encryptor::with_recipients(recipients)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        Ok(Encrypted {
+            inner: Some(encryptor.wrap_output(inner)?),
+        })
+    }
+
+    fn writer(&mut self) -> io::Result<&mut StreamWriter<W>> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| io::Error::other("The encryption is finished"))
+    }
+}
+
+impl<W: Finish> Write for Encrypted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer()?.flush()
+    }
+}
+
+impl<W: Finish> Finish for Encrypted<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        match self.inner.take() {
+            Some(writer) => writer.finish()?.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Finish> Drop for Encrypted<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error(format!("Failed to finish the encrypted archive: {}", e));
+        }
+    }
+}
+
+/// The paths of the parts a SplitWriter created, in order.
+pub type Parts = Arc<Mutex<Vec<PathBuf>>>;
+
+/// Splits the archive into parts of at most `size` bytes named <path>.001, <path>.002
+/// and so on, for FAT32 media and upload endpoints with a size limit.
+pub struct SplitWriter {
+    path: PathBuf,
+    size: u64,
+    part: u32,
+    written: u64,
+    file: Option<BufWriter<File>>,
+    parts: Parts,
+}
+
+impl SplitWriter {
+    pub fn new(path: PathBuf, size: u64) -> SplitWriter {
+        assert!(size > 0, "The part size can't be zero");
+        SplitWriter {
+            path,
+            size,
+            part: 0,
+            written: 0,
+            file: None,
+            parts: Parts::default(),
+        }
+    }
+
+    pub fn part_path(path: &Path, part: u32) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{:03}", part));
+        PathBuf::from(name)
+    }
+
+    /// The parts this writer created, parts left by an earlier archive at the same path
+    /// aren't included. The list is shared and filled in as the parts are created, so
+    /// it can be read after the writer was handed to an archive.
+    pub fn parts(&self) -> Parts {
+        self.parts.clone()
+    }
+
+    fn next_part(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        self.part = self.part + 1;
+        let path = SplitWriter::part_path(&self.path, self.part);
+        self.file = Some(BufWriter::new(File::create(&path)?));
+        self.parts.lock().unwrap().push(path);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() || self.written == self.size {
+            self.next_part()?;
+        }
+        let max = usize::try_from(self.size - self.written)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        let n = self.file.as_mut().unwrap().write(&buf[..max])?;
+        self.written = self.written + u64::try_from(n).unwrap();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Finish for SplitWriter {}
+
+/// Writes the files into a directory instead, with the same <drive>\<path> layout
+/// as the archive (which is also how KAPE lays out its targets).
+pub struct DirWriter {
+    root: PathBuf,
+}
+
+impl DirWriter {
+    pub fn new(root: PathBuf) -> io::Result<DirWriter> {
+        fs::create_dir_all(&root)?;
+        Ok(DirWriter { root })
+    }
+}
+
+impl ArchiveWrite for DirWriter {
+    // Only the modification and access times are kept, attributes like read-only or
+    // hidden would get in the way of processing the output.
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        io::copy(&mut data.take(size), &mut file)?;
+        file.flush()?;
+        let mut times = FileTimes::new().set_modified(meta.modified);
+        if let Some(accessed) = meta.accessed {
+            times = times.set_accessed(accessed);
+        }
+        file.get_ref().set_times(times)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A ZIP archive, every entry is encrypted with AES-256 when there's a password so
+/// nothing collected is written to disk in the clear. The file names are not encrypted.
+pub struct ZipWriter<W: Write + Seek> {
+    inner: Option<zip::ZipWriter<W>>,
+    level: Compression,
+    password: Option<String>,
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    pub fn new(inner: W, level: Compression, password: Option<String>) -> ZipWriter<W> {
+        ZipWriter {
+            inner: Some(zip::ZipWriter::new(inner)),
+            level,
+            password,
+        }
+    }
+}
+
+impl<W: Write + Seek> ArchiveWrite for ZipWriter<W> {
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()> {
+        let writer = self.inner.as_mut().expect("The archive is finished");
+        // ZIP only has the DOS modification time, without a time zone
+        let modified = DateTime::<Utc>::from(meta.modified).naive_utc();
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(i64::from(self.level.level())))
+            .last_modified_time(zip::DateTime::try_from(modified).unwrap_or_default())
+            .large_file(size >= u64::from(u32::MAX));
+        if let Some(password) = &self.password {
+            options = options.with_aes_encryption(AesMode::Aes256, password);
+        }
+        // Entry names use forward slashes, the archive paths are Windows paths
+        let name = path.as_ref().to_string_lossy().replace('\\', "/");
+        writer.start_file(name, options)?;
+        io::copy(&mut data.take(size), writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.inner.take() {
+            Some(writer) => writer.finish()?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An AFF4 logical image (AFF4-L): a ZIP volume with every file stored as a segment
+/// and described, with its hash and timestamps, in information.turtle.
+pub struct Aff4Writer<W: Write + Seek> {
+    inner: Option<zip::ZipWriter<W>>,
+    volume: String,
+    level: Compression,
+    turtle: String,
+    contains: Vec<String>,
+}
+
+impl<W: Write + Seek> Aff4Writer<W> {
+    pub fn new(inner: W, level: Compression) -> Aff4Writer<W> {
+        Aff4Writer::with_volume(inner, level, format!("aff4://{}", new_uuid()))
+    }
+
+    fn with_volume(inner: W, level: Compression, volume: String) -> Aff4Writer<W> {
+        let mut inner = zip::ZipWriter::new(inner);
+        // Readers find the volume URN in the ZIP comment
+        inner.set_comment(volume.clone());
+        Aff4Writer {
+            inner: Some(inner),
+            volume,
+            level,
+            turtle: String::new(),
+            contains: Vec::new(),
+        }
+    }
+
+    fn add_text(&mut self, name: &str, text: &str) -> io::Result<()> {
+        let writer = self.inner.as_mut().expect("The volume is finished");
+        writer.start_file(name, SimpleFileOptions::default())?;
+        writer.write_all(text.as_bytes())
+    }
+}
+
+impl<W: Write + Seek> ArchiveWrite for Aff4Writer<W> {
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()> {
+        let path = path.as_ref().to_string_lossy().into_owned();
+        // The segment name is the image URN relative to the volume URN
+        let segment = aff4_segment(&path);
+        let urn = format!("{}/{}", self.volume, segment);
+        let writer = self.inner.as_mut().expect("The volume is finished");
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(i64::from(self.level.level())))
+            .large_file(size >= u64::from(u32::MAX));
+        writer.start_file(segment, options)?;
+        let mut hasher = Sha256::new();
+        let mut data = data.take(size);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = data.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            writer.write_all(&buf[..n])?;
+        }
+        let mut turtle = format!(
+            "<{}>\n    a aff4:FileImage, aff4:Image, aff4:ZipSegment ;\n    \
+             aff4:originalFileName \"{}\"^^xsd:string ;\n    \
+             aff4:size \"{}\"^^xsd:long ;\n    \
+             aff4:stored <{}> ;\n    \
+             aff4:hash \"{:x}\"^^aff4:SHA256 ;\n    \
+             aff4:lastWritten \"{}\"^^xsd:dateTime",
+            urn,
+            turtle_escape(&path),
+            size,
+            self.volume,
+            hasher.finalize(),
+            xsd_time(meta.modified)
+        );
+        if let Some(accessed) = meta.accessed {
+            turtle.push_str(&format!(
+                " ;\n    aff4:lastAccessed \"{}\"^^xsd:dateTime",
+                xsd_time(accessed)
+            ));
+        }
+        if let Some(created) = meta.created {
+            turtle.push_str(&format!(
+                " ;\n    aff4:birthTime \"{}\"^^xsd:dateTime",
+                xsd_time(created)
+            ));
+        }
+        turtle.push_str(" .\n\n");
+        self.turtle.push_str(&turtle);
+        self.contains.push(urn);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.inner.is_none() {
+            return Ok(());
+        }
+        let contains: Vec<String> = self.contains.iter().map(|x| format!("<{}>", x)).collect();
+        let mut turtle = String::from(
+            "@prefix aff4: <http://aff4.org/Schema#> .\n\
+             @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .\n\n",
+        );
+        turtle.push_str(&format!(
+            "<{}>\n    a aff4:ZipVolume ;\n    \
+             aff4:creationTime \"{}\"^^xsd:dateTime ;\n    \
+             aff4:interface aff4:Volume ;\n    \
+             aff4:tool \"squirrel {}\"",
+            self.volume,
+            xsd_time(SystemTime::now()),
+            env!("CARGO_PKG_VERSION")
+        ));
+        if !contains.is_empty() {
+            turtle.push_str(&format!(" ;\n    aff4:contains {}", contains.join(", ")));
+        }
+        turtle.push_str(" .\n\n");
+        turtle.push_str(&self.turtle);
+        let volume = self.volume.clone();
+        self.add_text("container.description", &volume)?;
+        self.add_text("version.txt", "major=1\nminor=0\ntool=squirrel\n")?;
+        self.add_text("information.turtle", &turtle)?;
+        self.inner.take().unwrap().finish()?.flush()
+    }
+}
+
+// Path segments are kept, anything else that isn't safe in a URN is percent-encoded.
+fn aff4_segment(path: &str) -> String {
+    let mut segment = String::new();
+    for byte in path.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                segment.push(char::from(byte))
+            }
+            _ => segment.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    segment
+}
+
+fn turtle_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xsd_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+// A UUID for the volume URN, unique per run rather than random: a SHA-256 of the
+// time, PID and computer name, with the version 4 and variant bits set so it has the
+// usual shape. That saves a dependency, but it's predictable, so nothing should
+// rely on it being secret.
+fn new_uuid() -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}", SystemTime::now()));
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(std::env::var("COMPUTERNAME").unwrap_or_default());
+    let mut bytes = hasher.finalize();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = format!("{:x}", bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// ArchiveWrite has generic methods so it can't be a trait object, this picks the
+/// output at runtime instead.
+pub enum Output<W: Finish + Send + 'static> {
+    Archive(TarGzWriter<W>),
+    Zip(ZipWriter<BufWriter<File>>),
+    Aff4(Aff4Writer<BufWriter<File>>),
+    Dir(DirWriter),
+}
+
+impl<W: Finish + Send + 'static> ArchiveWrite for Output<W> {
+    fn add_entry<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        meta: &FileMeta,
+        data: R,
+    ) -> io::Result<()> {
+        match self {
+            Output::Archive(x) => x.add_entry(path, size, meta, data),
+            Output::Zip(x) => x.add_entry(path, size, meta, data),
+            Output::Aff4(x) => x.add_entry(path, size, meta, data),
+            Output::Dir(x) => x.add_entry(path, size, meta, data),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Output::Archive(x) => x.finish(),
+            Output::Zip(x) => x.finish(),
+            Output::Aff4(x) => x.finish(),
+            Output::Dir(x) => x.finish(),
+        }
+    }
+}
+
+/// The tar header already has the size, so when reading fails or the file shrank
+/// the rest of the entry is filled with zeroes instead of corrupting the archive.
+pub struct ZeroFill<R> {
+    inner: R,
+    remaining: u64,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> ZeroFill<R> {
+    pub fn new(inner: R, size: u64) -> ZeroFill<R> {
+        ZeroFill {
+            inner,
+            remaining: size,
+            error: None,
+        }
+    }
+
+    /// Returns the first read error, if any.
+    pub fn finish(self) -> io::Result<R> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.inner),
+        }
+    }
+}
+
+impl<R: Read> Read for ZeroFill<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = usize::try_from(self.remaining)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        if max == 0 {
+            return Ok(0);
         }
+        let mut n = 0;
+        if self.error.is_none() {
+            match self.inner.read(&mut buf[..max]) {
+                Ok(0) => {
+                    self.error = Some(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "File is shorter than its size",
+                    ))
+                }
+                Ok(x) => n = x,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => return Err(e),
+                Err(e) => self.error = Some(e),
+            }
+        }
+        if n == 0 {
+            buf[..max].iter_mut().for_each(|x| *x = 0);
+            n = max;
+        }
+        self.remaining = self.remaining - u64::try_from(n).unwrap();
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Failing(usize);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("bad sector"));
+            }
+            let n = self.0.min(buf.len());
+            buf[..n].iter_mut().for_each(|x| *x = 1);
+            self.0 = self.0 - n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_dir_writer() {
+        let root = std::env::temp_dir().join("squirrel_test_dir_writer");
+        let mut writer = DirWriter::new(root.clone()).unwrap();
+        writer
+            .add_file("C/Windows/System32/config/SAM", 3, &b"abcdef"[..])
+            .unwrap();
+        writer.finish().unwrap();
+        let data = fs::read(root.join("C/Windows/System32/config/SAM")).unwrap();
+        assert_eq!(data, b"abc");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_tar_meta() {
+        let meta = FileMeta {
+            modified: UNIX_EPOCH + std::time::Duration::from_secs(1622557805),
+            accessed: Some(UNIX_EPOCH + std::time::Duration::from_secs(1622557806)),
+            created: Some(UNIX_EPOCH + std::time::Duration::from_secs(1600000000)),
+            attributes: Some(0x22),
+        };
+        let mut builder = TarGzWriter {
+            inner: Builder::new(ParallelGzEncoder::new(Vec::new(), Compression::fast())),
+        };
+        builder
+            .add_entry("C\\a.txt", 3, &meta, &b"abc"[..])
+            .unwrap();
+        builder.inner.finish().unwrap();
+        let data = builder.inner.get_mut().finish().unwrap();
+        let mut archive = tar::Archive::new(flate2::read::MultiGzDecoder::new(data.as_slice()));
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.header().mtime().unwrap(), 1622557805);
+        assert_eq!(
+            entry.header().as_gnu().unwrap().atime().unwrap(),
+            1622557806
+        );
+        let pax: Vec<_> = entry
+            .pax_extensions()
+            .unwrap()
+            .unwrap()
+            .map(|x| x.unwrap().value().unwrap().to_string())
+            .collect();
+        assert_eq!(pax, vec!["1600000000", "34"]);
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abc");
+    }
+
+    #[test]
+    fn test_zip_writer() {
+        let mut writer = ZipWriter::new(
+            io::Cursor::new(Vec::new()),
+            Compression::fast(),
+            Some(String::from("infected")),
+        );
+        writer
+            .add_file("C\\Windows\\System32\\config\\SAM", 3, &b"abcdef"[..])
+            .unwrap();
+        let data = writer.inner.take().unwrap().finish().unwrap().into_inner();
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).unwrap();
+        assert!(archive.by_index(0).is_err());
+        let mut file = archive.by_index_decrypt(0, b"infected").unwrap();
+        assert_eq!(file.name(), "C/Windows/System32/config/SAM");
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abc");
+    }
+
+    #[test]
+    fn test_aff4_writer() {
+        let volume = String::from("aff4://6dbbd5a5-7d4b-4b53-8f3c-3a0a4c0bd3f1");
+        let mut data = io::Cursor::new(Vec::new());
+        let mut writer = Aff4Writer::with_volume(&mut data, Compression::fast(), volume.clone());
+        let meta = FileMeta {
+            modified: UNIX_EPOCH + std::time::Duration::from_secs(1622557805),
+            accessed: None,
+            created: None,
+            attributes: None,
+        };
+        writer
+            .add_entry("C\\Program Files\\a.txt", 3, &meta, &b"abc"[..])
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let mut archive = zip::ZipArchive::new(data).unwrap();
+        assert_eq!(archive.comment(), volume.as_bytes());
+        let read = |archive: &mut zip::ZipArchive<_>, name| {
+            let mut content = String::new();
+            let mut file = archive.by_name(name).unwrap();
+            file.read_to_string(&mut content).unwrap();
+            content
+        };
+        assert_eq!(read(&mut archive, "C/Program%20Files/a.txt"), "abc");
+        assert_eq!(read(&mut archive, "container.description"), volume);
+        let turtle = read(&mut archive, "information.turtle");
+        let image = "<aff4://6dbbd5a5-7d4b-4b53-8f3c-3a0a4c0bd3f1/C/Program%20Files/a.txt>";
+        assert!(turtle.contains(&format!("aff4:contains {} .", image)));
+        assert!(
+            turtle.contains("aff4:originalFileName \"C\\\\Program Files\\\\a.txt\"^^xsd:string")
+        );
+        assert!(turtle.contains(
+            "\"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\"^^aff4:SHA256"
+        ));
+        assert!(turtle.contains("aff4:lastWritten \"2021-06-01T14:30:05Z\"^^xsd:dateTime"));
+        assert_eq!(new_uuid().len(), 36);
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let identity = age::x25519::Identity::generate();
+        let mut data = Vec::new();
+        let mut writer = Encrypted::new(&mut data, &[identity.to_public()]).unwrap();
+        writer.write_all(b"evidence").unwrap();
+        writer.finish().unwrap();
+        assert!(writer.write_all(b"more").is_err());
+        drop(writer);
+        assert!(data.starts_with(b"age-encryption.org/v1"));
+        let decryptor = age::Decryptor::new(data.as_slice()).unwrap();
+        let identities = [&identity as &dyn age::Identity];
+        let mut reader = decryptor.decrypt(identities.iter().copied()).unwrap();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"evidence");
+    }
+
+    #[test]
+    fn test_split_writer() {
+        let path = std::env::temp_dir().join("squirrel_test_split.tar.gz");
+        // Left by an earlier, bigger archive
+        let stale = SplitWriter::part_path(&path, 4);
+        fs::write(&stale, b"old").unwrap();
+        let mut writer = SplitWriter::new(path.clone(), 4);
+        let parts = writer.parts();
+        writer.write_all(b"0123456789").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let parts = parts.lock().unwrap().clone();
+        let data: Vec<Vec<u8>> = parts.iter().map(|x| fs::read(x).unwrap()).collect();
+        parts.iter().for_each(|x| fs::remove_file(x).unwrap());
+        fs::remove_file(stale).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts[2],
+            std::env::temp_dir().join("squirrel_test_split.tar.gz.003")
+        );
+        assert_eq!(data, vec![&b"0123"[..], &b"4567"[..], &b"89"[..]]);
+    }
+
+    #[test]
+    fn test_zero_fill() {
+        let mut fill = ZeroFill::new(Failing(10), 20);
+        let mut output = Vec::new();
+        fill.read_to_end(&mut output).unwrap();
+        assert_eq!(output.len(), 20);
+        assert_eq!(&output[..10], &[1; 10]);
+        assert_eq!(&output[10..], &[0; 10]);
+        assert_eq!(fill.finish().unwrap_err().to_string(), "bad sector");
+
+        let mut fill = ZeroFill::new(&b"longer than the size"[..], 6);
+        let mut output = Vec::new();
+        fill.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"longer");
+        assert!(fill.finish().is_ok());
     }
 }
@@ -0,0 +1,77 @@
+use std::process;
+use std::sync::{Mutex, Once};
+
+use crate::log;
+
+type Action = Box<dyn FnOnce() + Send>;
+
+// Cleanup that has to happen even when the collection is interrupted. The drop guards
+// (snapshots, mount points, the working dir) register what they'll remove and the
+// notification what it'll report, so that on Ctrl-C whatever is still registered runs
+// before exiting.
+static PENDING: Mutex<(u64, Vec<(u64, Action)>)> = Mutex::new((0, Vec::new()));
+static HANDLER: Once = Once::new();
+// run_all runs what other tests registered too
+#[cfg(test)]
+pub static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn register<F: FnOnce() + Send + 'static>(action: F) -> u64 {
+    let mut pending = PENDING.lock().unwrap();
+    pending.0 = pending.0 + 1;
+    let id = pending.0;
+    pending.1.push((id, Box::new(action)));
+    id
+}
+
+// Takes a registered action out, None when it already ran.
+pub fn take(id: u64) -> Option<Action> {
+    let mut pending = PENDING.lock().unwrap();
+    let idx = pending.1.iter().position(|(x, _)| *x == id)?;
+    Some(pending.1.remove(idx).1)
+}
+
+// Runs everything still registered, newest first so mount points go before the
+// shadow copies they point to.
+pub fn run_all() {
+    let actions = std::mem::take(&mut PENDING.lock().unwrap().1);
+    for (_, action) in actions.into_iter().rev() {
+        action();
+    }
+}
+
+// Cleans up and exits on Ctrl-C, panics already clean up as the guards are dropped.
+pub fn handle_ctrl_c() {
+    HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            log::warn("Interrupted, cleaning up");
+            run_all();
+            process::exit(130);
+        });
+        if let Err(e) = result {
+            log::warn(format!("Failed to set the Ctrl-C handler: {}", e));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_run_all() {
+        let _lock = TEST_LOCK.lock().unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let ids: Vec<u64> = (0..3)
+            .map(|x| {
+                let order = order.clone();
+                register(move || order.lock().unwrap().push(x))
+            })
+            .collect();
+        take(ids[1]).unwrap()();
+        assert!(take(ids[1]).is_none());
+        run_all();
+        assert!(take(ids[0]).is_none());
+        assert_eq!(*order.lock().unwrap(), vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,91 @@
+// Only the gRPC service follows and cancels collections, without it most of this is unused.
+#![cfg_attr(not(feature = "grpc"), allow(dead_code))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::log;
+
+// Lets an embedding controller follow and cancel a running collection.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+// Set when the service stops, the agent and gRPC server stop taking new collections.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static SUBSCRIBERS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+static MANIFEST: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn start() {
+    CANCELLED.store(false, Ordering::Relaxed);
+    *MANIFEST.lock().unwrap() = None;
+}
+
+// Subscribers see the end of their stream when the collection finishes.
+pub fn finish() {
+    SUBSCRIBERS.lock().unwrap().clear();
+}
+
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+// Cancels the running collection as well.
+pub fn stop() {
+    STOPPING.store(true, Ordering::Relaxed);
+    cancel();
+}
+
+pub fn is_stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+pub fn subscribe() -> Receiver<String> {
+    let (sender, receiver) = channel();
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+// Logs a progress message and passes it on to the subscribers.
+pub fn event<T: Into<String>>(msg: T) {
+    let msg = msg.into();
+    log::info(&msg);
+    broadcast(msg);
+}
+
+// Same as event but logged as an error.
+pub fn error<T: Into<String>>(msg: T) {
+    let msg = msg.into();
+    log::error(&msg);
+    broadcast(msg);
+}
+
+fn broadcast(msg: String) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|x| x.send(msg.clone()).is_ok());
+}
+
+pub fn set_manifest(manifest: String) {
+    *MANIFEST.lock().unwrap() = Some(manifest);
+}
+
+pub fn manifest() -> Option<String> {
+    MANIFEST.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let receiver = subscribe();
+        event("Copying SYSTEM");
+        finish();
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec!["Copying SYSTEM"]);
+    }
+}
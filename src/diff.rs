@@ -0,0 +1,214 @@
+use chrono::DateTime;
+use flate2::read::MultiGzDecoder;
+use getopts::Options;
+use json::JsonValue;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use tar::Archive;
+
+// New or changed files of these artifacts are listed separately as persistence.
+const PERSISTENCE: [&str; 3] = ["startup", "scheduled-tasks", "extensions"];
+
+fn set_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Show this help information.");
+    opts.optflag("", "html", "Write the report as HTML instead of JSON.");
+    opts.optopt("o", "output", "Write the report to FILE.", "FILE");
+    opts
+}
+
+// Compares the manifests of two collections (archives or --kape directories) of
+// the same host. `class` gives the artifact a collected path belongs to.
+pub fn run(args: &[String], class: fn(&str) -> &'static str) {
+    let opts = set_opts();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => panic!("{:?}", f),
+    };
+    if matches.opt_present("help") || matches.free.len() != 2 {
+        let usage = "Usage: squirrel diff [options] OLD NEW";
+        return print!("{}", opts.usage(usage));
+    }
+    let read = |path: &String| {
+        read_manifest(Path::new(path))
+            .unwrap_or_else(|e| panic!("Failed to read the manifest of {}: {}", path, e))
+    };
+    let old = read(&matches.free[0]);
+    let new = read(&matches.free[1]);
+    if old["hostname"] != new["hostname"] {
+        println!(
+            "Warning: comparing collections of {} and {}",
+            old["hostname"], new["hostname"]
+        );
+    }
+    let report = compare(&old, &new, class);
+    let output = if matches.opt_present("html") {
+        html(&report)
+    } else {
+        report.pretty(2)
+    };
+    match matches.opt_str("output") {
+        Some(path) => {
+            fs::write(&path, output).unwrap_or_else(|e| panic!("Failed to write {}: {}", path, e))
+        }
+        None => println!("{}", output),
+    }
+}
+
+fn read_manifest(path: &Path) -> io::Result<JsonValue> {
+    let mut data = String::new();
+    if path.is_dir() {
+        data = fs::read_to_string(path.join("manifest.json"))?;
+    } else {
+        let file = MultiGzDecoder::new(BufReader::new(File::open(path)?));
+        let mut archive = Archive::new(file);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.as_os_str() == "manifest.json" {
+                entry.read_to_string(&mut data)?;
+                break;
+            }
+        }
+    }
+    json::parse(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn files(manifest: &JsonValue) -> BTreeMap<String, &JsonValue> {
+    manifest["files"]
+        .members()
+        .filter_map(|x| Some((x["Path"].as_str()?.to_lowercase(), x)))
+        .collect()
+}
+
+fn compare(old: &JsonValue, new: &JsonValue, class: fn(&str) -> &'static str) -> JsonValue {
+    let mut report = JsonValue::new_object();
+    for (key, manifest) in [("old", old), ("new", new)].iter() {
+        report[*key]["hostname"] = manifest["hostname"].clone();
+        report[*key]["collection_time"] = manifest["collection_time"].clone();
+    }
+    let time = |x: &JsonValue| DateTime::parse_from_rfc3339(x["collection_time"].as_str()?).ok();
+    if let (Some(old_time), Some(new_time)) = (time(old), time(new)) {
+        report["interval_seconds"] = (new_time - old_time).num_seconds().into();
+    }
+    for key in ["added", "removed", "changed", "persistence"].iter() {
+        report[*key] = JsonValue::new_array();
+    }
+    let old_files = files(old);
+    let new_files = files(new);
+    for (path, entry) in new_files.iter() {
+        let change = match old_files.get(path) {
+            None => {
+                report["added"].push((*entry).clone()).unwrap();
+                "added"
+            }
+            Some(old_entry) if old_entry["SHA256"] != entry["SHA256"] => {
+                let mut changed = JsonValue::new_object();
+                changed["Path"] = entry["Path"].clone();
+                changed["OldSHA256"] = old_entry["SHA256"].clone();
+                changed["NewSHA256"] = entry["SHA256"].clone();
+                changed["OldSize"] = old_entry["Size"].clone();
+                changed["NewSize"] = entry["Size"].clone();
+                report["changed"].push(changed).unwrap();
+                "changed"
+            }
+            Some(_) => continue,
+        };
+        let artifact = class(entry["Path"].as_str().unwrap());
+        if PERSISTENCE.contains(&artifact) {
+            let mut persistence = JsonValue::new_object();
+            persistence["Path"] = entry["Path"].clone();
+            persistence["Artifact"] = artifact.into();
+            persistence["Change"] = change.into();
+            report["persistence"].push(persistence).unwrap();
+        }
+    }
+    for (path, entry) in old_files.iter() {
+        if !new_files.contains_key(path) {
+            report["removed"].push((*entry).clone()).unwrap();
+        }
+    }
+    report
+}
+
+fn html(report: &JsonValue) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Squirrel diff</title></head><body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>{} {} &rarr; {}</h1>\n",
+        escape(&report["new"]["hostname"].to_string()),
+        escape(&report["old"]["collection_time"].to_string()),
+        escape(&report["new"]["collection_time"].to_string())
+    ));
+    for key in ["persistence", "added", "removed", "changed"].iter() {
+        let rows = &report[*key];
+        html.push_str(&format!("<h2>{} ({})</h2>\n", key, rows.len()));
+        if rows.is_empty() {
+            continue;
+        }
+        let columns: Vec<&str> = rows[0].entries().map(|(x, _)| x).collect();
+        html.push_str("<table border=\"1\">\n<tr>");
+        for column in columns.iter() {
+            html.push_str(&format!("<th>{}</th>", escape(column)));
+        }
+        html.push_str("</tr>\n");
+        for row in rows.members() {
+            html.push_str("<tr>");
+            for column in columns.iter() {
+                html.push_str(&format!("<td>{}</td>", escape(&row[*column].to_string())));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(path: &str) -> &'static str {
+        if path.contains("Tasks") {
+            "scheduled-tasks"
+        } else {
+            "paths"
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = json::parse(
+            r#"{"hostname": "WS01", "collection_time": "2021-06-01T00:00:00+00:00", "files": [
+                {"Path": "C\\a.txt", "SHA256": "aa", "Size": 1},
+                {"Path": "C\\b.txt", "SHA256": "bb", "Size": 1},
+                {"Path": "C\\c.txt", "SHA256": "cc", "Size": 1}]}"#,
+        )
+        .unwrap();
+        let new = json::parse(
+            r#"{"hostname": "WS01", "collection_time": "2021-06-02T00:00:00+00:00", "files": [
+                {"Path": "C\\A.txt", "SHA256": "aa", "Size": 1},
+                {"Path": "C\\b.txt", "SHA256": "b2", "Size": 2},
+                {"Path": "C\\Windows\\System32\\Tasks\\evil", "SHA256": "dd", "Size": 1}]}"#,
+        )
+        .unwrap();
+        let report = compare(&old, &new, class);
+        assert_eq!(report["interval_seconds"], 86400);
+        assert_eq!(report["added"].len(), 1);
+        assert_eq!(report["removed"][0]["Path"], "C\\c.txt");
+        assert_eq!(report["changed"][0]["NewSHA256"], "b2");
+        assert_eq!(report["persistence"][0]["Artifact"], "scheduled-tasks");
+        assert_eq!(report["persistence"][0]["Change"], "added");
+        assert!(html(&report).contains("<td>C\\Windows\\System32\\Tasks\\evil</td>"));
+    }
+}
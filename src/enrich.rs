@@ -0,0 +1,149 @@
+use json::JsonValue;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::log;
+
+// Looks up each SHA-256 at the provider. Stops early when the provider can't be
+// reached.
+pub fn lookup(url: &str, api_key: Option<&str>, per_minute: u32, hashes: &[String]) -> JsonValue {
+    let mut results = JsonValue::new_array();
+    let interval = Duration::from_secs(60) / per_minute.max(1);
+    let mut last: Option<Instant> = None;
+    for hash in hashes.iter() {
+        let mut retried = false;
+        loop {
+            if let Some(last) = last {
+                let elapsed = last.elapsed();
+                if elapsed < interval {
+                    thread::sleep(interval - elapsed);
+                }
+            }
+            last = Some(Instant::now());
+            let mut entry = JsonValue::new_object();
+            entry["SHA256"] = hash.as_str().into();
+            // A URL with {hash} in it is VirusTotal style, the hash is put in its
+            // place and it's fetched with GET. Without, it's MalwareBazaar style and
+            // the hash is POSTed as query=get_info&hash=SHA256.
+            let sent = if url.contains("{hash}") {
+                let request = ureq::get(&url.replace("{hash}", hash));
+                match api_key {
+                    Some(key) => request.set("x-apikey", key),
+                    None => request,
+                }
+                .call()
+            } else {
+                let request = ureq::post(url);
+                match api_key {
+                    Some(key) => request.set("Auth-Key", key),
+                    None => request,
+                }
+                .send_form(&[("query", "get_info"), ("hash", hash)])
+            };
+            match sent {
+                Ok(resp) => {
+                    entry["Status"] = resp.status().into();
+                    let body = json::parse(&resp.into_string().unwrap_or_default())
+                        .unwrap_or(JsonValue::Null);
+                    // MalwareBazaar answers 200 with a query_status for unknown hashes
+                    let unknown = matches!(body["query_status"].as_str(), Some(x) if x != "ok");
+                    entry["Found"] = (!unknown).into();
+                    entry["Verdict"] = verdict(&body);
+                }
+                Err(ureq::Error::Status(429, _)) if !retried => {
+                    log::warn("Rate limited by the hash provider, waiting a minute");
+                    thread::sleep(Duration::from_secs(60));
+                    retried = true;
+                    continue;
+                }
+                Err(ureq::Error::Status(code, _)) => {
+                    entry["Status"] = code.into();
+                    entry["Found"] = false.into();
+                }
+                Err(e) => {
+                    log::warn(format!("Hash enrichment stopped: {}", e));
+                    return results;
+                }
+            }
+            results.push(entry).unwrap();
+            break;
+        }
+    }
+    results
+}
+
+// Picks the detection summary out of VirusTotal and MalwareBazaar style responses.
+fn verdict(body: &JsonValue) -> JsonValue {
+    let stats = &body["data"]["attributes"]["last_analysis_stats"];
+    if stats.is_object() {
+        return stats.clone();
+    }
+    let info = &body["data"][0];
+    if info.is_object() {
+        let mut verdict = JsonValue::new_object();
+        verdict["Signature"] = info["signature"].clone();
+        verdict["Tags"] = info["tags"].clone();
+        return verdict;
+    }
+    body["query_status"].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_lookup() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v1/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                request.push_str(&line);
+                line.clear();
+            }
+            let length = request
+                .lines()
+                .find_map(|x| x.strip_prefix("Content-Length: "))
+                .map_or(0, |x| x.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let found = r#"{"query_status":"ok","data":[{"signature":"Emotet"}]}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                found.len(),
+                found
+            );
+            reader.into_inner().write_all(response.as_bytes()).unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+        let results = lookup(&url, Some("abc123"), 60, &[String::from("ab12")]);
+        let (request, body) = server.join().unwrap();
+        assert!(request.starts_with("POST /api/v1/ "));
+        assert!(request.contains("Auth-Key: abc123\r\n"));
+        assert_eq!(body, "query=get_info&hash=ab12");
+        assert_eq!(results[0]["SHA256"], "ab12");
+        assert_eq!(results[0]["Found"], true);
+        assert_eq!(results[0]["Verdict"]["Signature"], "Emotet");
+    }
+
+    #[test]
+    fn test_verdict() {
+        let vt = json::parse(
+            r#"{"data":{"attributes":{"last_analysis_stats":{"malicious":3,"undetected":60}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(verdict(&vt)["malicious"], 3);
+        let mb =
+            json::parse(r#"{"query_status":"ok","data":[{"signature":"Emotet","tags":["exe"]}]}"#)
+                .unwrap();
+        assert_eq!(verdict(&mb)["Signature"], "Emotet");
+        let none = json::parse(r#"{"query_status":"hash_not_found"}"#).unwrap();
+        assert_eq!(verdict(&none), "hash_not_found");
+    }
+}
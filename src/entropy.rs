@@ -0,0 +1,80 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+use crate::pipeline::is_zero;
+
+// Packed or encrypted data is usually above this many bits per byte.
+pub const HIGH_ENTROPY: f64 = 7.2;
+
+pub struct EntropyReader<R> {
+    inner: R,
+    counts: [u64; 256],
+    total: u64,
+}
+
+impl<R: Read> EntropyReader<R> {
+    pub fn new(inner: R) -> EntropyReader<R> {
+        EntropyReader {
+            inner,
+            counts: [0; 256],
+            total: 0,
+        }
+    }
+
+    // Shannon entropy in bits per byte of everything read so far.
+    pub fn entropy(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let total = self.total as f64;
+        let entropy: f64 = self
+            .counts
+            .iter()
+            .filter(|x| **x > 0)
+            .map(|x| {
+                let p = *x as f64 / total;
+                -p * p.log2()
+            })
+            .sum();
+        (entropy * 1000.0).round() / 1000.0
+    }
+}
+
+impl<R: Read> Read for EntropyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if is_zero(&buf[..n]) {
+            self.counts[0] = self.counts[0] + u64::try_from(n).unwrap();
+        } else {
+            for byte in buf[..n].iter() {
+                self.counts[usize::from(*byte)] = self.counts[usize::from(*byte)] + 1;
+            }
+        }
+        self.total = self.total + u64::try_from(n).unwrap();
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entropy() {
+        let mut reader = EntropyReader::new(&[7u8; 1000][..]);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.entropy(), 0.0);
+
+        let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let mut reader = EntropyReader::new(data.as_slice());
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.entropy(), 8.0);
+
+        let mut data = vec![0u8; 8192];
+        data[8191] = 1;
+        let mut reader = EntropyReader::new(data.as_slice());
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.counts[0], 8191);
+        assert_eq!(reader.counts[1], 1);
+    }
+}
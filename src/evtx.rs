@@ -0,0 +1,132 @@
+use byteorder::{ByteOrder, ReadBytesExt, LE};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+const HEADER_SIZE: u64 = 4096;
+const CHUNK_SIZE: u64 = 65536;
+
+pub struct TrimReader<R> {
+    inner: R,
+    header: Cursor<Vec<u8>>,
+    chunks: Vec<u64>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl<R> TrimReader<R> {
+    pub fn size(&self) -> u64 {
+        HEADER_SIZE + u64::try_from(self.chunks.len()).unwrap() * CHUNK_SIZE
+    }
+}
+
+// Keeps only the chunks containing records written at or after `since` (a FILETIME).
+pub fn trim<R: Read + Seek>(mut inner: R, since: u64) -> io::Result<TrimReader<R>> {
+    let mut header = vec![0u8; usize::try_from(HEADER_SIZE).unwrap()];
+    inner.seek(SeekFrom::Start(0))?;
+    inner.read_exact(&mut header)?;
+    if &header[0..8] != b"ElfFile\0" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No EVTX file signature",
+        ));
+    }
+    let file_size = inner.seek(SeekFrom::End(0))?;
+    let mut chunks = Vec::new();
+    let mut offset = HEADER_SIZE;
+    while offset + CHUNK_SIZE <= file_size {
+        if last_record_time(&mut inner, offset)?.is_some_and(|time| time >= since) {
+            chunks.push(offset);
+        }
+        offset = offset + CHUNK_SIZE;
+    }
+    let count = u64::try_from(chunks.len()).unwrap();
+    LE::write_u64(&mut header[8..16], 0);
+    LE::write_u64(&mut header[16..24], count.saturating_sub(1));
+    LE::write_u16(&mut header[42..44], u16::try_from(count).unwrap());
+    // Clear the dirty flag, the trimmed file is consistent
+    header[120] = header[120] & !1;
+    let checksum = crc32fast::hash(&header[0..120]);
+    LE::write_u32(&mut header[124..128], checksum);
+    Ok(TrimReader {
+        inner,
+        header: Cursor::new(header),
+        chunks,
+        chunk: Cursor::new(Vec::new()),
+    })
+}
+
+fn last_record_time<R: Read + Seek>(inner: &mut R, chunk: u64) -> io::Result<Option<u64>> {
+    let mut sig = [0u8; 8];
+    inner.seek(SeekFrom::Start(chunk))?;
+    inner.read_exact(&mut sig)?;
+    if &sig != b"ElfChnk\0" {
+        return Ok(None);
+    }
+    inner.seek(SeekFrom::Start(chunk + 44))?;
+    let last_record = inner.read_u32::<LE>()?;
+    if last_record < 512 || u64::from(last_record) >= CHUNK_SIZE {
+        return Ok(None);
+    }
+    inner.seek(SeekFrom::Start(chunk + u64::from(last_record)))?;
+    inner.read_exact(&mut sig[0..4])?;
+    if &sig[0..4] != b"**\0\0" {
+        return Ok(None);
+    }
+    inner.seek(SeekFrom::Current(12))?;
+    Ok(Some(inner.read_u64::<LE>()?))
+}
+
+impl<R: Read + Seek> Read for TrimReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let nread = self.header.read(buf)?;
+        if nread > 0 {
+            return Ok(nread);
+        }
+        let nread = self.chunk.read(buf)?;
+        if nread > 0 || self.chunks.is_empty() {
+            return Ok(nread);
+        }
+        let offset = self.chunks.remove(0);
+        let mut chunk = vec![0u8; usize::try_from(CHUNK_SIZE).unwrap()];
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut chunk)?;
+        self.chunk = Cursor::new(chunk);
+        self.chunk.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(time: u64) -> Vec<u8> {
+        let mut chunk = vec![0u8; CHUNK_SIZE as usize];
+        chunk[0..8].copy_from_slice(b"ElfChnk\0");
+        LE::write_u32(&mut chunk[44..48], 512);
+        chunk[512..516].copy_from_slice(b"**\0\0");
+        LE::write_u64(&mut chunk[528..536], time);
+        chunk
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut file = vec![0u8; HEADER_SIZE as usize];
+        file[0..8].copy_from_slice(b"ElfFile\0");
+        LE::write_u16(&mut file[42..44], 3);
+        file.extend(chunk(100));
+        file.extend(chunk(300));
+        file.extend(chunk(200));
+        let mut rdr = trim(Cursor::new(file.clone()), 200).unwrap();
+        let size = rdr.size();
+        let mut trimmed = Vec::new();
+        rdr.read_to_end(&mut trimmed).unwrap();
+        assert_eq!(size, trimmed.len() as u64);
+        assert_eq!(LE::read_u16(&trimmed[42..44]), 2);
+        assert_eq!(
+            &trimmed[4096..4096 + 65536],
+            &file[4096 + 65536..4096 + 2 * 65536]
+        );
+        assert_eq!(&trimmed[4096 + 65536..], &file[4096 + 2 * 65536..]);
+        let checksum = LE::read_u32(&trimmed[124..128]);
+        assert_eq!(checksum, crc32fast::hash(&trimmed[0..120]));
+    }
+}
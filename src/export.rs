@@ -0,0 +1,161 @@
+use json::JsonValue;
+use std::io;
+
+// Requests are split so a large timeline doesn't end up in a single body.
+const BATCH_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // Splunk HTTP Event Collector, e.g. https://splunk:8088/services/collector/event
+    Splunk,
+    // Elasticsearch bulk API, e.g. https://elastic:9200/_bulk
+    Elastic,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Format, String> {
+        match name {
+            "splunk" => Ok(Format::Splunk),
+            "elastic" => Ok(Format::Elastic),
+            _ => Err(format!("Unknown export format: {}", name)),
+        }
+    }
+}
+
+// Sends the live artifacts and parser output (JSONL per artifact or parser) to the
+// endpoint so it's searchable before the archive is transferred. Stops at the first
+// request that fails.
+pub fn send(
+    url: &str,
+    format: Format,
+    token: Option<&str>,
+    host: &str,
+    outputs: &[(&str, &[u8])],
+) -> io::Result<usize> {
+    let mut events = 0;
+    for (name, data) in outputs.iter() {
+        for (count, body) in batches(format, host, name, data) {
+            let mut request = ureq::post(url);
+            request = match (format, token) {
+                (Format::Splunk, Some(token)) => {
+                    request.set("Authorization", &format!("Splunk {}", token))
+                }
+                (Format::Elastic, Some(token)) => {
+                    request.set("Authorization", &format!("ApiKey {}", token))
+                }
+                (_, None) => request,
+            };
+            let content_type = match format {
+                Format::Splunk => "application/json",
+                Format::Elastic => "application/x-ndjson",
+            };
+            request
+                .set("Content-Type", content_type)
+                .send_string(&body)
+                .map_err(io::Error::other)?;
+            events = events + count;
+        }
+    }
+    Ok(events)
+}
+
+// Splits the JSONL into request bodies in the endpoint's format, with the number of
+// events in each.
+fn batches(format: Format, host: &str, name: &str, data: &[u8]) -> Vec<(usize, String)> {
+    let mut batches = Vec::new();
+    let mut body = String::new();
+    let mut count = 0;
+    for line in String::from_utf8_lossy(data).lines() {
+        let event = match json::parse(line) {
+            Ok(event) => event,
+            Err(_) => continue,
+        };
+        match format {
+            Format::Splunk => {
+                let mut wrapped = JsonValue::new_object();
+                wrapped["event"] = event;
+                wrapped["host"] = host.into();
+                wrapped["source"] = "squirrel".into();
+                wrapped["sourcetype"] = format!("squirrel:{}", name).into();
+                body.push_str(&wrapped.dump());
+                body.push('\n');
+            }
+            Format::Elastic => {
+                let mut action = JsonValue::new_object();
+                action["index"]["_index"] = format!("squirrel-{}", name).into();
+                let mut event = event;
+                event["Host"] = host.into();
+                body.push_str(&action.dump());
+                body.push('\n');
+                body.push_str(&event.dump());
+                body.push('\n');
+            }
+        }
+        count = count + 1;
+        if body.len() >= BATCH_SIZE {
+            batches.push((count, body));
+            body = String::new();
+            count = 0;
+        }
+    }
+    if count > 0 {
+        batches.push((count, body));
+    }
+    batches
+}
+
+// A live artifact as JSONL, a line per entry of the list.
+pub fn jsonl(data: &JsonValue) -> Vec<u8> {
+    let mut lines = String::new();
+    let entries: Vec<&JsonValue> = match data {
+        JsonValue::Array(entries) => entries.iter().collect(),
+        JsonValue::Null => Vec::new(),
+        entry => vec![entry],
+    };
+    for entry in entries {
+        lines.push_str(&entry.dump());
+        lines.push('\n');
+    }
+    lines.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches() {
+        let data = b"{\"Path\":\"C\\\\a.pf\",\"RunCount\":3}\n{\"Path\":\"C\\\\b.pf\"}\n";
+        let splunk = batches(Format::Splunk, "WS01", "prefetch", data);
+        assert_eq!(splunk.len(), 1);
+        assert_eq!(splunk[0].0, 2);
+        let first = json::parse(splunk[0].1.lines().next().unwrap()).unwrap();
+        assert_eq!(first["event"]["RunCount"], 3);
+        assert_eq!(first["sourcetype"], "squirrel:prefetch");
+        assert_eq!(first["host"], "WS01");
+
+        let elastic = batches(Format::Elastic, "WS01", "prefetch", data);
+        let lines: Vec<&str> = elastic[0].1.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            json::parse(lines[0]).unwrap()["index"]["_index"],
+            "squirrel-prefetch"
+        );
+        assert_eq!(json::parse(lines[3]).unwrap()["Host"], "WS01");
+
+        assert!(batches(Format::Splunk, "WS01", "prefetch", b"").is_empty());
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let netstat = json::parse(r#"[{"LocalPort": 445}, {"LocalPort": 3389}]"#).unwrap();
+        assert_eq!(
+            jsonl(&netstat),
+            b"{\"LocalPort\":445}\n{\"LocalPort\":3389}\n"
+        );
+        // PowerShell returns a single entry as an object
+        let single = json::parse(r#"{"LocalPort": 445}"#).unwrap();
+        assert_eq!(jsonl(&single), b"{\"LocalPort\":445}\n");
+        assert!(jsonl(&JsonValue::Null).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use getopts::Options;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::collector::Outcome;
+use crate::control;
+use crate::log;
+
+mod proto {
+    tonic::include_proto!("squirrel");
+}
+
+use proto::collector_server::{Collector, CollectorServer};
+use proto::{
+    CancelResponse, CollectionId, ManifestResponse, ProgressEvent, StartRequest, StartResponse,
+};
+
+fn set_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Show this help information.");
+    opts.optopt(
+        "",
+        "listen",
+        "Address to serve the gRPC API on. Defaults to 127.0.0.1:50051.",
+        "ADDR",
+    );
+    opts
+}
+
+// Collections share the process wide settings and counters, so only one runs at a time.
+#[derive(Default)]
+struct State {
+    last_id: u64,
+    current: Option<String>,
+    running: bool,
+}
+
+struct Service {
+    collect: fn(&[String]) -> Outcome,
+    state: Arc<Mutex<State>>,
+}
+
+impl Service {
+    // Whether the collection is still running, None when `id` isn't the current one.
+    fn running(&self, id: &str) -> Option<bool> {
+        let state = self.state.lock().unwrap();
+        Some(state.running).filter(|_| state.current.as_deref() == Some(id))
+    }
+}
+
+fn unknown(id: &str) -> Status {
+    Status::not_found(format!("Unknown collection: {}", id))
+}
+
+#[tonic::async_trait]
+impl Collector for Service {
+    async fn start_collection(
+        &self,
+        request: Request<StartRequest>,
+    ) -> Result<Response<StartResponse>, Status> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            if state.running {
+                return Err(Status::already_exists("A collection is already running"));
+            }
+            state.last_id = state.last_id + 1;
+            state.current = Some(state.last_id.to_string());
+            state.running = true;
+            state.last_id.to_string()
+        };
+        control::start();
+        let mut args = vec![String::from("squirrel")];
+        args.extend(request.into_inner().args);
+        let collect = self.collect;
+        let state = self.state.clone();
+        tokio::task::spawn_blocking(move || {
+            match panic::catch_unwind(AssertUnwindSafe(|| collect(&args))) {
+                Ok(Outcome::Done) => (),
+                Ok(Outcome::Partial) => control::event("Collection partially transferred"),
+                Err(_) => control::event("Collection failed"),
+            }
+            state.lock().unwrap().running = false;
+            control::finish();
+        });
+        Ok(Response::new(StartResponse { id }))
+    }
+
+    type StreamProgressStream = ReceiverStream<Result<ProgressEvent, Status>>;
+
+    async fn stream_progress(
+        &self,
+        request: Request<CollectionId>,
+    ) -> Result<Response<Self::StreamProgressStream>, Status> {
+        let (sender, receiver) = mpsc::channel(64);
+        let id = &request.get_ref().id;
+        if self.running(id).ok_or_else(|| unknown(id))? {
+            let events = control::subscribe();
+            tokio::task::spawn_blocking(move || {
+                for message in events.iter() {
+                    if sender.blocking_send(Ok(ProgressEvent { message })).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<CollectionId>,
+    ) -> Result<Response<CancelResponse>, Status> {
+        let id = &request.get_ref().id;
+        let running = self.running(id).ok_or_else(|| unknown(id))?;
+        if running {
+            control::cancel();
+        }
+        Ok(Response::new(CancelResponse { cancelled: running }))
+    }
+
+    async fn get_manifest(
+        &self,
+        request: Request<CollectionId>,
+    ) -> Result<Response<ManifestResponse>, Status> {
+        let id = &request.get_ref().id;
+        if self.running(id).ok_or_else(|| unknown(id))? {
+            return Err(Status::unavailable("The collection is still running"));
+        }
+        match control::manifest() {
+            Some(json) => Ok(Response::new(ManifestResponse { json })),
+            None => Err(Status::not_found("The collection didn't write a manifest")),
+        }
+    }
+}
+
+// Serves the control API until the process or service is stopped, collections run
+// with the given arguments as if they were passed on the command line.
+pub fn serve(args: &[String], collect: fn(&[String]) -> Outcome) {
+    let opts = set_opts();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => panic!("{:?}", f),
+    };
+    if matches.opt_present("help") {
+        return print!("{}", opts.usage("Usage: squirrel grpc [--listen ADDR]"));
+    }
+    let listen = matches
+        .opt_str("listen")
+        .unwrap_or_else(|| String::from("127.0.0.1:50051"));
+    let addr: SocketAddr = listen
+        .parse()
+        .unwrap_or_else(|e| panic!("Invalid address {}: {}", listen, e));
+    let service = Service {
+        collect,
+        state: Arc::default(),
+    };
+    let (stopped, stopping) = oneshot::channel();
+    thread::spawn(move || {
+        while !control::is_stopping() {
+            thread::sleep(Duration::from_secs(1));
+        }
+        stopped.send(()).ok();
+    });
+    log::info(format!("Serving the gRPC API on {}", addr));
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(
+            Server::builder()
+                .add_service(CollectorServer::new(service))
+                .serve_with_shutdown(addr, async {
+                    stopping.await.ok();
+                }),
+        )
+        .unwrap();
+}
@@ -0,0 +1,75 @@
+use json::JsonValue;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+// Hashes everything read through it, so files are hashed during the one copy
+// into the archive instead of in a second pass. Unlike compression and entropy,
+// all-zero blocks can't be short-circuited here: the digests depend on every byte
+// and on the state before them, there's nothing to reuse between zero blocks.
+pub struct HashReader<R> {
+    inner: R,
+    sha256: Sha256,
+    md5: Option<Md5>,
+}
+
+impl<R: Read> HashReader<R> {
+    pub fn new(inner: R, md5: bool) -> HashReader<R> {
+        HashReader {
+            inner,
+            sha256: Sha256::new(),
+            md5: if md5 { Some(Md5::new()) } else { None },
+        }
+    }
+
+    // Adds the SHA256 (and MD5) fields to the entry and returns the inner reader.
+    pub fn finish(self, entry: &mut JsonValue) -> R {
+        entry["SHA256"] = format!("{:x}", self.sha256.finalize()).into();
+        if let Some(md5) = self.md5 {
+            entry["MD5"] = format!("{:x}", md5.finalize()).into();
+        }
+        self.inner
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.sha256.update(&buf[..n]);
+        if let Some(md5) = self.md5.as_mut() {
+            md5.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_reader() {
+        let mut reader = HashReader::new(&b"abc"[..], true);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        let mut entry = JsonValue::new_object();
+        reader.finish(&mut entry);
+        assert_eq!(
+            entry["SHA256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(entry["MD5"], "900150983cd24fb0d6963f7d28e17f72");
+
+        // Zero blocks are hashed like any other data
+        let zeroes = vec![0u8; 3 * 4096];
+        let mut reader = HashReader::new(zeroes.as_slice(), false);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        let mut entry = JsonValue::new_object();
+        reader.finish(&mut entry);
+        assert_eq!(entry["SHA256"], format!("{:x}", Sha256::digest(&zeroes)));
+
+        let reader = HashReader::new(&b""[..], false);
+        let mut entry = JsonValue::new_object();
+        reader.finish(&mut entry);
+        assert!(!entry.has_key("MD5"));
+    }
+}
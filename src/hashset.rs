@@ -0,0 +1,131 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::pipeline::{return_buffer, take_buffer};
+
+#[derive(Debug, Default)]
+pub struct KnownHashes {
+    hashes: HashSet<String>,
+    md5: bool,
+    sha1: bool,
+    sha256: bool,
+}
+
+impl KnownHashes {
+    // Accepts one hash per line or NSRL style CSV, any MD5, SHA-1 or SHA-256 field is used.
+    pub fn load(path: &Path) -> io::Result<KnownHashes> {
+        let mut known = KnownHashes::default();
+        for line in fs::read_to_string(path)?.lines() {
+            for field in line.split(',') {
+                let field = field.trim().trim_matches('"').to_lowercase();
+                if !field.chars().all(|x| x.is_ascii_hexdigit()) {
+                    continue;
+                }
+                match field.len() {
+                    32 => known.md5 = true,
+                    40 => known.sha1 = true,
+                    64 => known.sha256 = true,
+                    _ => continue,
+                }
+                known.hashes.insert(field);
+            }
+        }
+        Ok(known)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    // Hashes the data and returns the first hash that is in the set.
+    pub fn find<R: Read>(&self, data: R) -> io::Result<Option<String>> {
+        let mut reader = KnownReader::new(data, self);
+        let mut buf = take_buffer(64 * 1024);
+        buf.resize(64 * 1024, 0);
+        while reader.read(&mut buf)? > 0 {}
+        return_buffer(buf);
+        Ok(reader.finish().0)
+    }
+}
+
+// Hashes everything read through it with the algorithms the set has hashes of, so a
+// file can be checked during its copy instead of in a read of its own.
+pub struct KnownReader<'a, R> {
+    inner: R,
+    known: &'a KnownHashes,
+    md5: Md5,
+    sha1: Sha1,
+    sha256: Sha256,
+}
+
+impl<'a, R> KnownReader<'a, R> {
+    pub fn new(inner: R, known: &'a KnownHashes) -> KnownReader<'a, R> {
+        KnownReader {
+            inner,
+            known,
+            md5: Md5::new(),
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+        }
+    }
+
+    // The first hash that is in the set, if any, and the inner reader.
+    pub fn finish(self) -> (Option<String>, R) {
+        let known = self.known;
+        let hashes = [
+            (known.md5, format!("{:x}", self.md5.finalize())),
+            (known.sha1, format!("{:x}", self.sha1.finalize())),
+            (known.sha256, format!("{:x}", self.sha256.finalize())),
+        ];
+        let hash = hashes
+            .iter()
+            .find(|(used, hash)| *used && known.hashes.contains(hash))
+            .map(|(_, hash)| hash.clone());
+        (hash, self.inner)
+    }
+}
+
+impl<'a, R: Read> Read for KnownReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if self.known.md5 {
+            self.md5.update(&buf[..n]);
+        }
+        if self.known.sha1 {
+            self.sha1.update(&buf[..n]);
+        }
+        if self.known.sha256 {
+            self.sha256.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let mut known = KnownHashes {
+            sha1: true,
+            ..KnownHashes::default()
+        };
+        known
+            .hashes
+            .insert(String::from("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        assert_eq!(
+            known.find(&b"abc"[..]).unwrap().as_deref(),
+            Some("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+        assert_eq!(known.find(&b"abd"[..]).unwrap(), None);
+        let mut reader = KnownReader::new(&b"abc"[..], &known);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert!(reader.finish().0.is_some());
+    }
+}
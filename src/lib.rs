@@ -0,0 +1,62 @@
+//! Forensic artifact collection for Windows.
+//!
+//! The raw NTFS reader ([`ntfs`]), Volume Shadow Copy handling ([`snapshot`]) and
+//! the archive writers ([`archive`]) can be used on their own. [`Collector`] runs a
+//! whole collection the way the squirrel binary does.
+
+pub mod archive;
+pub mod ntfs;
+pub mod snapshot;
+
+mod agent;
+mod cleanup;
+mod collector;
+mod control;
+mod diff;
+mod enrich;
+mod entropy;
+mod evtx;
+mod export;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hashing;
+mod hashset;
+mod live;
+mod log;
+mod manifest;
+mod mft_dump;
+mod parse;
+mod pipeline;
+mod profile;
+mod service;
+mod signing;
+mod stats;
+mod syslog;
+mod timeline;
+mod tls;
+mod transfer;
+
+pub use crate::collector::{Collector, Outcome};
+
+/// Runs the squirrel command line, `args[0]` being the program name. Only a collection
+/// ends Partial, the subcommands are Done unless they panic.
+pub fn dispatch(args: &[String]) -> Outcome {
+    cleanup::handle_ctrl_c();
+    match args.get(1).map(|x| x.as_str()) {
+        Some("agent") => agent::run(&args[2..], collect),
+        #[cfg(feature = "grpc")]
+        Some("grpc") => grpc::serve(&args[2..], collect),
+        Some("service") => service::run(&args[2..], dispatch),
+        Some("diff") => diff::run(&args[2..], collector::file_class),
+        Some("mft-dump") => mft_dump::run(&args[2..]),
+        Some("timeline") => timeline::run(&args[2..]),
+        _ => return collect(args),
+    }
+    Outcome::Done
+}
+
+// Invalid arguments panic like the other errors of a run, so the agent and gRPC
+// server report them the same way.
+fn collect(args: &[String]) -> Outcome {
+    collector::run(args).unwrap_or_else(|e| panic!("{}", e))
+}
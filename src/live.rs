@@ -0,0 +1,581 @@
+use json::JsonValue;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+pub const COMMANDS: [(&str, &str); 8] = [
+    (
+        "processes",
+        "@(Get-CimInstance Win32_Process | ForEach-Object { \
+             $owner = Invoke-CimMethod -InputObject $_ -MethodName GetOwner; \
+             [PSCustomObject]@{ \
+                 ProcessId = $_.ProcessId; \
+                 ParentProcessId = $_.ParentProcessId; \
+                 Name = $_.Name; \
+                 ExecutablePath = $_.ExecutablePath; \
+                 CommandLine = $_.CommandLine; \
+                 User = if ($owner.User) { \"$($owner.Domain)\\$($owner.User)\" }; \
+                 CreationDate = if ($_.CreationDate) { $_.CreationDate.ToUniversalTime().ToString('o') }; \
+                 SHA256 = if ($_.ExecutablePath) { \
+                     (Get-FileHash -Algorithm SHA256 -LiteralPath $_.ExecutablePath \
+                      -ErrorAction SilentlyContinue).Hash \
+                 } \
+             } \
+         })",
+    ),
+    (
+        "netstat",
+        "@(Get-NetTCPConnection | ForEach-Object { \
+             [PSCustomObject]@{ \
+                 Protocol = 'TCP'; \
+                 LocalAddress = $_.LocalAddress; \
+                 LocalPort = $_.LocalPort; \
+                 RemoteAddress = $_.RemoteAddress; \
+                 RemotePort = $_.RemotePort; \
+                 State = \"$($_.State)\"; \
+                 OwningProcess = $_.OwningProcess; \
+                 CreationTime = if ($_.CreationTime) { $_.CreationTime.ToUniversalTime().ToString('o') } \
+             } \
+         }) + @(Get-NetUDPEndpoint | ForEach-Object { \
+             [PSCustomObject]@{ \
+                 Protocol = 'UDP'; \
+                 LocalAddress = $_.LocalAddress; \
+                 LocalPort = $_.LocalPort; \
+                 OwningProcess = $_.OwningProcess; \
+                 CreationTime = if ($_.CreationTime) { $_.CreationTime.ToUniversalTime().ToString('o') } \
+             } \
+         })",
+    ),
+    (
+        "dnscache",
+        "@(if (Get-Command Get-DnsClientCache -ErrorAction SilentlyContinue) { \
+             Get-DnsClientCache | ForEach-Object { \
+                 [PSCustomObject]@{ \
+                     Entry = $_.Entry; \
+                     Name = $_.Name; \
+                     Type = \"$($_.Type)\"; \
+                     Status = \"$($_.Status)\"; \
+                     Section = \"$($_.Section)\"; \
+                     TimeToLive = $_.TimeToLive; \
+                     Data = $_.Data \
+                 } \
+             } \
+         } else { \
+             [PSCustomObject]@{ DisplayDns = (ipconfig /displaydns | Out-String) } \
+         })",
+    ),
+    (
+        "services",
+        "@(@(Get-CimInstance Win32_Service) + @(Get-CimInstance Win32_SystemDriver) | ForEach-Object { \
+             $bin = $_.PathName -replace '^\"([^\"]*)\".*$', '$1' \
+                 -replace '^(.*?\\.(exe|sys)).*$', '$1' \
+                 -replace '^\\\\SystemRoot', $env:SystemRoot \
+                 -replace '^\\\\\\?\\?\\\\', '' \
+                 -replace '^(?i)system32', \"$env:SystemRoot\\System32\"; \
+             $sig = if ($bin) { \
+                 Get-AuthenticodeSignature -LiteralPath $bin -ErrorAction SilentlyContinue \
+             }; \
+             [PSCustomObject]@{ \
+                 Type = $_.CimClass.CimClassName; \
+                 Name = $_.Name; \
+                 DisplayName = $_.DisplayName; \
+                 PathName = $_.PathName; \
+                 State = $_.State; \
+                 StartMode = $_.StartMode; \
+                 StartName = $_.StartName; \
+                 SignatureStatus = if ($sig) { \"$($sig.Status)\" }; \
+                 Signer = if ($sig.SignerCertificate) { $sig.SignerCertificate.Subject } \
+             } \
+         })",
+    ),
+    (
+        "network-tables",
+        "[PSCustomObject]@{ \
+             Neighbors = @(Get-NetNeighbor | ForEach-Object { \
+                 [PSCustomObject]@{ \
+                     InterfaceAlias = $_.InterfaceAlias; \
+                     IPAddress = $_.IPAddress; \
+                     LinkLayerAddress = $_.LinkLayerAddress; \
+                     State = \"$($_.State)\" \
+                 } \
+             }); \
+             Routes = @(Get-NetRoute | ForEach-Object { \
+                 [PSCustomObject]@{ \
+                     InterfaceAlias = $_.InterfaceAlias; \
+                     DestinationPrefix = $_.DestinationPrefix; \
+                     NextHop = $_.NextHop; \
+                     RouteMetric = $_.RouteMetric; \
+                     Protocol = \"$($_.Protocol)\" \
+                 } \
+             }); \
+             Interfaces = @(Get-CimInstance Win32_NetworkAdapterConfiguration -Filter 'IPEnabled=True' | \
+                 ForEach-Object { \
+                     [PSCustomObject]@{ \
+                         Description = $_.Description; \
+                         MACAddress = $_.MACAddress; \
+                         IPAddress = $_.IPAddress; \
+                         IPSubnet = $_.IPSubnet; \
+                         DefaultIPGateway = $_.DefaultIPGateway; \
+                         DNSServerSearchOrder = $_.DNSServerSearchOrder; \
+                         DHCPServer = $_.DHCPServer \
+                     } \
+                 }) \
+         }",
+    ),
+    (
+        "tasks",
+        "@($service = New-Object -ComObject Schedule.Service; \
+           $service.Connect(); \
+           function Get-TaskFolders($folder) { \
+               $folder; \
+               foreach ($sub in $folder.GetFolders(0)) { Get-TaskFolders $sub } \
+           }; \
+           Get-TaskFolders $service.GetFolder('\\') | ForEach-Object { $_.GetTasks(1) } | ForEach-Object { \
+               [PSCustomObject]@{ \
+                   Path = $_.Path; \
+                   Enabled = $_.Enabled; \
+                   State = $_.State; \
+                   Hidden = $_.Definition.Settings.Hidden; \
+                   Author = $_.Definition.RegistrationInfo.Author; \
+                   LastRunTime = $_.LastRunTime.ToUniversalTime().ToString('o'); \
+                   NextRunTime = $_.NextRunTime.ToUniversalTime().ToString('o'); \
+                   LastTaskResult = $_.LastTaskResult; \
+                   Triggers = @($_.Definition.Triggers | ForEach-Object { \
+                       [PSCustomObject]@{ \
+                           Type = $_.Type; \
+                           Enabled = $_.Enabled; \
+                           StartBoundary = $_.StartBoundary; \
+                           EndBoundary = $_.EndBoundary \
+                       } \
+                   }); \
+                   Actions = @($_.Definition.Actions | ForEach-Object { \
+                       [PSCustomObject]@{ \
+                           Type = $_.Type; \
+                           Path = $_.Path; \
+                           Arguments = $_.Arguments; \
+                           WorkingDirectory = $_.WorkingDirectory; \
+                           ClassId = $_.ClassId \
+                       } \
+                   }) \
+               } \
+           })",
+    ),
+    (
+        "software",
+        "@(@('HKLM:\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\*', \
+                 'HKLM:\\SOFTWARE\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\*', \
+                 'Registry::HKEY_USERS\\*\\Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\*') | \
+              ForEach-Object { Get-ItemProperty -Path $_ -ErrorAction SilentlyContinue } | \
+              Where-Object { $_.DisplayName } | \
+              ForEach-Object { \
+                  [PSCustomObject]@{ \
+                      Source = $_.PSParentPath -replace '^.*Registry::', ''; \
+                      DisplayName = $_.DisplayName; \
+                      DisplayVersion = $_.DisplayVersion; \
+                      Publisher = $_.Publisher; \
+                      InstallDate = $_.InstallDate; \
+                      InstallLocation = $_.InstallLocation; \
+                      UninstallString = $_.UninstallString; \
+                      WindowsInstaller = [bool]$_.WindowsInstaller \
+                  } \
+              }) + @(Get-ItemProperty -Path 'HKLM:\\SOFTWARE\\Classes\\Installer\\Products\\*' -ErrorAction SilentlyContinue | \
+              ForEach-Object { \
+                  [PSCustomObject]@{ \
+                      Source = 'MSI'; \
+                      DisplayName = $_.ProductName; \
+                      ProductCode = $_.PSChildName; \
+                      PackageCode = $_.PackageCode; \
+                      WindowsInstaller = $true \
+                  } \
+              })",
+    ),
+    (
+        "env",
+        "$(function Get-Variables($key) { \
+                  $vars = [ordered]@{}; \
+                  $item = Get-Item -LiteralPath $key -ErrorAction SilentlyContinue; \
+                  if ($item) { \
+                      foreach ($name in $item.GetValueNames()) { \
+                          $vars[$name] = $item.GetValue($name, $null, 'DoNotExpandEnvironmentNames') \
+                      } \
+                  }; \
+                  [PSCustomObject]$vars \
+              }; \
+              $process = [ordered]@{}; \
+              Get-ChildItem Env: | ForEach-Object { $process[$_.Name] = $_.Value }; \
+              [PSCustomObject]@{ \
+                  System = Get-Variables 'HKLM:\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment'; \
+                  Users = @(Get-ChildItem 'Registry::HKEY_USERS' | \
+                      Where-Object { Test-Path \"$($_.PSPath)\\Environment\" } | \
+                      ForEach-Object { \
+                          [PSCustomObject]@{ \
+                              SID = $_.PSChildName; \
+                              Variables = Get-Variables \"$($_.PSPath)\\Environment\" \
+                          } \
+                      }); \
+                  Process = [PSCustomObject]$process; \
+                  Path = @($env:Path -split ';' | Where-Object { $_ }) \
+              })",
+    ),
+];
+
+pub const SESSIONS: &str = "@(Get-CimInstance Win32_LogonSession | ForEach-Object { \
+         $user = Get-CimAssociatedInstance -InputObject $_ -Association Win32_LoggedOnUser; \
+         [PSCustomObject]@{ \
+             LogonId = $_.LogonId; \
+             LogonType = $_.LogonType; \
+             AuthenticationPackage = $_.AuthenticationPackage; \
+             User = if ($user) { \"$($user.Domain)\\$($user.Name)\" }; \
+             StartTime = if ($_.StartTime) { $_.StartTime.ToUniversalTime().ToString('o') } \
+         } \
+     }) + @(Get-SmbSession -ErrorAction SilentlyContinue | ForEach-Object { \
+         [PSCustomObject]@{ \
+             LogonId = $_.SessionId; \
+             LogonType = 'SMB'; \
+             User = $_.ClientUserName; \
+             ClientAddress = $_.ClientComputerName; \
+             Dialect = $_.Dialect \
+         } \
+     }) + @(Add-Type -TypeDefinition ' \
+          using System; \
+          using System.Collections.Generic; \
+          using System.Runtime.InteropServices; \
+          public class SquirrelSession { \
+              public int LogonId { get; set; } \
+              public string LogonType { get; set; } \
+              public string User { get; set; } \
+              public string State { get; set; } \
+              public string ClientName { get; set; } \
+              public string ClientAddress { get; set; } \
+          } \
+          public static class SquirrelSessions { \
+              [StructLayout(LayoutKind.Sequential)] \
+              struct SessionInfo { \
+                  public int SessionId; \
+                  public IntPtr WinStationName; \
+                  public int State; \
+              } \
+              [StructLayout(LayoutKind.Sequential)] \
+              struct ClientAddress { \
+                  public int AddressFamily; \
+                  [MarshalAs(UnmanagedType.ByValArray, SizeConst = 20)] \
+                  public byte[] Address; \
+              } \
+              [DllImport(\"wtsapi32.dll\")] \
+              static extern bool WTSEnumerateSessionsW(IntPtr server, int reserved, int version, \
+                  out IntPtr info, out int count); \
+              [DllImport(\"wtsapi32.dll\")] \
+              static extern bool WTSQuerySessionInformationW(IntPtr server, int session, int cls, \
+                  out IntPtr buf, out int len); \
+              [DllImport(\"wtsapi32.dll\")] \
+              static extern void WTSFreeMemory(IntPtr memory); \
+              static string QueryString(int session, int cls) { \
+                  IntPtr buf; \
+                  int len; \
+                  if (!WTSQuerySessionInformationW(IntPtr.Zero, session, cls, out buf, out len)) { return null; } \
+                  try { \
+                      string value = Marshal.PtrToStringUni(buf); \
+                      return String.IsNullOrEmpty(value) ? null : value; \
+                  } finally { \
+                      WTSFreeMemory(buf); \
+                  } \
+              } \
+              /* WTSClientProtocolType, 2 is RDP */ \
+              static bool IsRdp(int session) { \
+                  IntPtr buf; \
+                  int len; \
+                  if (!WTSQuerySessionInformationW(IntPtr.Zero, session, 16, out buf, out len)) { return false; } \
+                  try { \
+                      return Marshal.ReadInt16(buf) == 2; \
+                  } finally { \
+                      WTSFreeMemory(buf); \
+                  } \
+              } \
+              /* WTSClientAddress, an IPv4 address is in bytes 2 to 5 */ \
+              static string QueryAddress(int session) { \
+                  IntPtr buf; \
+                  int len; \
+                  if (!WTSQuerySessionInformationW(IntPtr.Zero, session, 14, out buf, out len)) { return null; } \
+                  try { \
+                      ClientAddress address = (ClientAddress)Marshal.PtrToStructure(buf, typeof(ClientAddress)); \
+                      if (address.AddressFamily != 2) { return null; } \
+                      byte[] a = address.Address; \
+                      return String.Format(\"{0}.{1}.{2}.{3}\", a[2], a[3], a[4], a[5]); \
+                  } finally { \
+                      WTSFreeMemory(buf); \
+                  } \
+              } \
+              public static List<SquirrelSession> Get() { \
+                  string[] states = { \"Active\", \"Connected\", \"ConnectQuery\", \"Shadow\", \
+                      \"Disconnected\", \"Idle\", \"Listen\", \"Reset\", \"Down\", \"Init\" }; \
+                  List<SquirrelSession> result = new List<SquirrelSession>(); \
+                  IntPtr info; \
+                  int count; \
+                  if (!WTSEnumerateSessionsW(IntPtr.Zero, 0, 1, out info, out count)) { return result; } \
+                  try { \
+                      int size = Marshal.SizeOf(typeof(SessionInfo)); \
+                      for (int i = 0; i < count; i++) { \
+                          IntPtr entry = new IntPtr(info.ToInt64() + i * size); \
+                          SessionInfo session = (SessionInfo)Marshal.PtrToStructure(entry, typeof(SessionInfo)); \
+                          if (!IsRdp(session.SessionId)) { continue; } \
+                          string user = QueryString(session.SessionId, 5); \
+                          string domain = QueryString(session.SessionId, 7); \
+                          result.Add(new SquirrelSession { \
+                              LogonId = session.SessionId, \
+                              LogonType = \"RDP\", \
+                              User = domain == null ? user : domain + \"\\\\\" + user, \
+                              State = session.State < states.Length ? states[session.State] : null, \
+                              ClientName = QueryString(session.SessionId, 10), \
+                              ClientAddress = QueryAddress(session.SessionId) \
+                          }); \
+                      } \
+                  } finally { \
+                      WTSFreeMemory(info); \
+                  } \
+                  return result; \
+              } \
+          }'; \
+          [SquirrelSessions]::Get())";
+
+pub const SYSINFO: &str = "$($os = Get-CimInstance Win32_OperatingSystem; \
+     $cs = Get-CimInstance Win32_ComputerSystem; \
+     $bios = Get-CimInstance Win32_BIOS; \
+     $product = Get-CimInstance Win32_ComputerSystemProduct; \
+     [PSCustomObject]@{ \
+         OS = $os.Caption; \
+         Version = $os.Version; \
+         BuildNumber = $os.BuildNumber; \
+         Architecture = $os.OSArchitecture; \
+         InstallDate = $os.InstallDate.ToUniversalTime().ToString('o'); \
+         LastBootUpTime = $os.LastBootUpTime.ToUniversalTime().ToString('o'); \
+         UptimeSeconds = [int64]((Get-Date) - $os.LastBootUpTime).TotalSeconds; \
+         TimeZone = (Get-TimeZone).Id; \
+         UTCOffsetMinutes = $os.CurrentTimeZone; \
+         Hostname = $cs.DNSHostName; \
+         Domain = $cs.Domain; \
+         PartOfDomain = $cs.PartOfDomain; \
+         Manufacturer = $cs.Manufacturer; \
+         Model = $cs.Model; \
+         SerialNumber = $bios.SerialNumber; \
+         UUID = $product.UUID; \
+         Locale = (Get-Culture).Name; \
+         UILanguage = (Get-UICulture).Name \
+     })";
+
+const HANDLES: &str = "@(Add-Type -TypeDefinition ' \
+          using System; \
+          using System.Collections.Generic; \
+          using System.Runtime.InteropServices; \
+          using System.Threading; \
+          public class SquirrelHandle { \
+              public long Handle { get; set; } \
+              public string Type { get; set; } \
+              public string Name { get; set; } \
+          } \
+          public static class SquirrelHandles { \
+              [StructLayout(LayoutKind.Sequential)] \
+              struct Entry { \
+                  public IntPtr Object; \
+                  public IntPtr UniqueProcessId; \
+                  public IntPtr HandleValue; \
+                  public uint GrantedAccess; \
+                  public ushort CreatorBackTraceIndex; \
+                  public ushort ObjectTypeIndex; \
+                  public uint HandleAttributes; \
+                  public uint Reserved; \
+              } \
+              [DllImport(\"ntdll.dll\")] \
+              static extern int NtQuerySystemInformation(int cls, IntPtr info, int len, out int retLen); \
+              [DllImport(\"ntdll.dll\")] \
+              static extern int NtQueryObject(IntPtr handle, int cls, IntPtr info, int len, out int retLen); \
+              [DllImport(\"kernel32.dll\")] \
+              static extern IntPtr OpenProcess(uint access, bool inherit, int pid); \
+              [DllImport(\"kernel32.dll\")] \
+              static extern bool DuplicateHandle(IntPtr srcProcess, IntPtr src, IntPtr dstProcess, \
+                  out IntPtr dst, uint access, bool inherit, uint options); \
+              [DllImport(\"kernel32.dll\")] \
+              static extern IntPtr GetCurrentProcess(); \
+              [DllImport(\"kernel32.dll\")] \
+              static extern bool CloseHandle(IntPtr handle); \
+              static string QueryString(IntPtr handle, int cls) { \
+                  IntPtr buf = Marshal.AllocHGlobal(0x10000); \
+                  try { \
+                      int retLen; \
+                      if (NtQueryObject(handle, cls, buf, 0x10000, out retLen) != 0) { return null; } \
+                      int len = (ushort)Marshal.ReadInt16(buf); \
+                      IntPtr str = Marshal.ReadIntPtr(buf, IntPtr.Size); \
+                      return len == 0 ? null : Marshal.PtrToStringUni(str, len / 2); \
+                  } finally { \
+                      Marshal.FreeHGlobal(buf); \
+                  } \
+              } \
+              /* Name queries on synchronous pipes can block forever, so they get a deadline. */ \
+              static string QueryName(IntPtr handle) { \
+                  string name = null; \
+                  Thread thread = new Thread(() => { name = QueryString(handle, 1); }); \
+                  thread.IsBackground = true; \
+                  thread.Start(); \
+                  return thread.Join(200) ? name : null; \
+              } \
+              public static List<SquirrelHandle> Get(int pid) { \
+                  List<SquirrelHandle> result = new List<SquirrelHandle>(); \
+                  int len = 0x100000; \
+                  int retLen; \
+                  IntPtr buf; \
+                  while (true) { \
+                      buf = Marshal.AllocHGlobal(len); \
+                      int status = NtQuerySystemInformation(64, buf, len, out retLen); \
+                      if (status == 0) { break; } \
+                      Marshal.FreeHGlobal(buf); \
+                      if (status != unchecked((int)0xC0000004)) { \
+                          throw new Exception(\"NtQuerySystemInformation failed: \" + status); \
+                      } \
+                      len = Math.Max(len * 2, retLen + 0x10000); \
+                  } \
+                  IntPtr process = OpenProcess(0x40, false, pid); \
+                  try { \
+                      if (process == IntPtr.Zero) { throw new Exception(\"Cannot open process \" + pid); } \
+                      long count = Marshal.ReadIntPtr(buf).ToInt64(); \
+                      int size = Marshal.SizeOf(typeof(Entry)); \
+                      for (long i = 0; i < count; i++) { \
+                          IntPtr ptr = new IntPtr(buf.ToInt64() + IntPtr.Size * 2 + i * size); \
+                          Entry entry = (Entry)Marshal.PtrToStructure(ptr, typeof(Entry)); \
+                          if (entry.UniqueProcessId.ToInt64() != pid) { continue; } \
+                          IntPtr dup; \
+                          if (!DuplicateHandle(process, entry.HandleValue, GetCurrentProcess(), out dup, 0, false, 2)) { \
+                              continue; \
+                          } \
+                          try { \
+                              string type = QueryString(dup, 2); \
+                              if (type != \"File\" && type != \"Key\") { continue; } \
+                              string name = type == \"File\" ? QueryName(dup) : QueryString(dup, 1); \
+                              if (name == null) { continue; } \
+                              if (name.StartsWith(@\"\\Device\\NamedPipe\\\")) { type = \"NamedPipe\"; } \
+                              result.Add(new SquirrelHandle { \
+                                  Handle = entry.HandleValue.ToInt64(), Type = type, Name = name \
+                              }); \
+                          } finally { \
+                              CloseHandle(dup); \
+                          } \
+                      } \
+                  } finally { \
+                      if (process != IntPtr.Zero) { CloseHandle(process); } \
+                      Marshal.FreeHGlobal(buf); \
+                  } \
+                  return result; \
+              } \
+          }'; \
+          [SquirrelHandles]::Get({pid}))";
+
+// The dumps are written with EFS, so only the account squirrel runs as can read them
+// while they wait in the working dir to be added to the archive. A working dir that
+// can't encrypt them gets no dumps. A full dump is about the size of the private bytes
+// or the working set, whichever is larger, so processes over --dump-max-size by that
+// estimate are skipped before anything is written.
+const DUMP: &str = "@($native = [PSObject].Assembly \
+         .GetType('System.Management.Automation.WindowsErrorReporting') \
+         .GetNestedType('NativeMethods', 'NonPublic'); \
+     $dump = $native.GetMethod('MiniDumpWriteDump', [Reflection.BindingFlags] 'NonPublic, Static'); \
+     $procs = if ('{target}' -match '^\\d+$') { Get-Process -Id '{target}' } else { Get-Process -Name '{target}' }; \
+     $procs | ForEach-Object { \
+         $proc = $_; \
+         $path = Join-Path '{dir}' \"$($proc.ProcessName)_$($proc.Id).dmp\"; \
+         $ok = $false; $err = $null; \
+         $size = [Math]::Max($proc.PrivateMemorySize64, $proc.WorkingSet64); \
+         $skipped = $size -gt {max}; \
+         if (-not $skipped) { try { \
+             $file = New-Object IO.FileStream($path, [IO.FileMode]::Create, \
+                 [IO.FileAccess]::ReadWrite, [IO.FileShare]::None, 4096, [IO.FileOptions]::Encrypted); \
+             try { \
+                 if (-not ((Get-Item -LiteralPath $path).Attributes -band [IO.FileAttributes]::Encrypted)) { \
+                     throw 'The working dir does not support EFS encryption' \
+                 }; \
+                 $ok = $dump.Invoke($null, @($proc.Handle, [uint32]$proc.Id, $file.SafeFileHandle, [uint32]2, \
+                     [IntPtr]::Zero, [IntPtr]::Zero, [IntPtr]::Zero)) \
+             } finally { $file.Close() }; \
+         } catch { \
+             $err = $_.Exception.Message; \
+             Remove-Item -LiteralPath $path -ErrorAction SilentlyContinue \
+         } }; \
+         [PSCustomObject]@{ \
+             ProcessId = $proc.Id; Name = $proc.ProcessName; Path = $path; Success = $ok; \
+             Error = $err; Skipped = $skipped; EstimatedSize = $size \
+         } \
+     })";
+
+pub fn dump_process(target: &str, dir: &Path, max_size: u64) -> io::Result<JsonValue> {
+    if !target
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    {
+        let msg = format!("Invalid process: {}", target);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    let dir = dir.to_str().unwrap().replace("'", "''");
+    let query = DUMP
+        .replace("{target}", target)
+        .replace("{dir}", &dir)
+        .replace("{max}", &max_size.to_string());
+    run(&format!("dump of {}", target), &query)
+}
+
+const AUTHENTICODE: &str = "@(Get-Content -LiteralPath '{list}' -Encoding UTF8 | ForEach-Object { \
+         $sig = Get-AuthenticodeSignature -LiteralPath $_; \
+         [PSCustomObject]@{ \
+             Path = $_; \
+             Status = [string]$sig.Status; \
+             StatusMessage = $sig.StatusMessage; \
+             SignatureType = [string]$sig.SignatureType; \
+             Signer = $sig.SignerCertificate.Subject; \
+             Issuer = $sig.SignerCertificate.Issuer; \
+             Thumbprint = $sig.SignerCertificate.Thumbprint; \
+             IsOSBinary = $sig.IsOSBinary \
+         } \
+     })";
+
+// Checks embedded and catalog signatures, the paths are absolute (on the drive or a
+// mounted shadow copy) and the file list is written to `dir`.
+pub fn authenticode(paths: &[String], dir: &Path) -> io::Result<JsonValue> {
+    let list = dir.join("authenticode.txt");
+    fs::write(&list, paths.join("\r\n"))?;
+    let list_str = list.to_str().unwrap().replace("'", "''");
+    let result = run(
+        "Authenticode signatures",
+        &AUTHENTICODE.replace("{list}", &list_str),
+    );
+    fs::remove_file(&list)?;
+    result
+}
+
+pub fn handles(pid: u32) -> io::Result<JsonValue> {
+    let query = HANDLES.replace("{pid}", &pid.to_string());
+    run(&format!("handles of {}", pid), &query)
+}
+
+pub fn collect(name: &str) -> io::Result<JsonValue> {
+    let (_, query) = COMMANDS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .unwrap_or_else(|| panic!("Unknown live artifact: {}", name));
+    run(name, query)
+}
+
+// Runs a query and parses the JSON it outputs. A failed query is an error for the
+// caller to record, the collection goes on without it.
+pub fn run(name: &str, query: &str) -> io::Result<JsonValue> {
+    let command = format!(
+        "ConvertTo-Json -Depth 4 -InputObject ({})",
+        query.trim_end()
+    );
+    let output = Command::new("powershell")
+        .arg("-Command")
+        .arg(command)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    json::parse(&stdout).map_err(|_| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let msg = format!("Collecting {} failed, stderr: {}", name, stderr.trim());
+        io::Error::other(msg)
+    })
+}
@@ -0,0 +1,110 @@
+use chrono::{SecondsFormat, Utc};
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+
+// How much is printed, the log file and the log in the archive always get everything.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+struct Log {
+    console: Level,
+    file: Option<File>,
+    lines: Vec<u8>,
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log {
+    console: Level::Info,
+    file: None,
+    lines: Vec::new(),
+});
+
+// Starts a new log for a collection, the file is written as the collection goes so
+// it survives a crash.
+pub fn start(console: Level, file: Option<File>) {
+    let mut log = LOG.lock().unwrap();
+    log.console = console;
+    log.file = file;
+    log.lines.clear();
+}
+
+pub fn error<T: AsRef<str>>(msg: T) {
+    write(Level::Error, msg.as_ref());
+}
+
+pub fn warn<T: AsRef<str>>(msg: T) {
+    write(Level::Warn, msg.as_ref());
+}
+
+pub fn info<T: AsRef<str>>(msg: T) {
+    write(Level::Info, msg.as_ref());
+}
+
+pub fn debug<T: AsRef<str>>(msg: T) {
+    write(Level::Debug, msg.as_ref());
+}
+
+// Everything logged since the start, to be added to the archive.
+pub fn lines() -> Vec<u8> {
+    LOG.lock().unwrap().lines.clone()
+}
+
+fn write(level: Level, msg: &str) {
+    let mut log = LOG.lock().unwrap();
+    if level <= log.console {
+        if level <= Level::Warn {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
+        }
+    }
+    let line = format_line(
+        &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        level,
+        msg,
+    );
+    log.lines.extend_from_slice(line.as_bytes());
+    let failed = match log.file.as_mut() {
+        Some(file) => file.write_all(line.as_bytes()).is_err(),
+        None => false,
+    };
+    if failed {
+        eprintln!("Failed to write to the log file");
+        log.file = None;
+    }
+}
+
+fn format_line(time: &str, level: Level, msg: &str) -> String {
+    format!("{} {:5} {}\n", time, level.name(), msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line("2021-06-01T14:30:05.000Z", Level::Info, "Copying SYSTEM"),
+            "2021-06-01T14:30:05.000Z INFO  Copying SYSTEM\n"
+        );
+        assert!(Level::Error < Level::Warn);
+        assert!(Level::Debug > Level::Info);
+    }
+}
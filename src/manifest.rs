@@ -0,0 +1,71 @@
+use chrono::Utc;
+use json::JsonValue;
+use std::env;
+
+pub struct Manifest {
+    inner: JsonValue,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        let mut inner = JsonValue::new_object();
+        inner["collection_time"] = Utc::now().to_rfc3339().into();
+        inner["hostname"] = env::var("COMPUTERNAME").ok().into();
+        Manifest { inner }
+    }
+    pub fn set<T: Into<JsonValue>>(&mut self, key: &str, value: T) {
+        self.inner[key] = value.into();
+    }
+    pub fn push<T: Into<JsonValue>>(&mut self, key: &str, value: T) {
+        if self.inner[key].is_null() {
+            self.inner[key] = JsonValue::new_array();
+        }
+        self.inner[key].push(value).unwrap();
+    }
+    pub fn get(&self, key: &str) -> &JsonValue {
+        &self.inner[key]
+    }
+    // Every distinct string stored under the key anywhere in the manifest.
+    pub fn values(&self, key: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        collect_values(&self.inner, key, &mut values);
+        values.sort();
+        values.dedup();
+        values
+    }
+    pub fn to_json(&self) -> String {
+        self.inner.pretty(2)
+    }
+}
+
+fn collect_values(value: &JsonValue, key: &str, values: &mut Vec<String>) {
+    if let JsonValue::Object(obj) = value {
+        if let Some(x) = obj.get(key).and_then(|x| x.as_str()) {
+            values.push(String::from(x));
+        }
+    }
+    for (_, entry) in value.entries() {
+        collect_values(entry, key, values);
+    }
+    for entry in value.members() {
+        collect_values(entry, key, values);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values() {
+        let mut manifest = Manifest::new();
+        let mut entry = JsonValue::new_object();
+        entry["SHA256"] = "b".into();
+        manifest.push("dumps", entry.clone());
+        manifest.push("files", entry);
+        let mut entry = JsonValue::new_object();
+        entry["SHA256"] = "a".into();
+        manifest.push("files", entry);
+        assert_eq!(manifest.values("SHA256"), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,113 @@
+use getopts::Options;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::log;
+use crate::ntfs::MFT;
+use crate::parse::mft::{join, Row, HEADER};
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Bodyfile,
+}
+
+fn set_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Show this help information.");
+    opts.optopt("", "volume", "Read the MFT of VOLUME, like C:.", "VOLUME");
+    opts.optopt("o", "output", "Write to FILE instead of stdout.", "FILE");
+    opts.optopt(
+        "",
+        "format",
+        "Write csv (the default) or a bodyfile for mactime.",
+        "FORMAT",
+    );
+    opts.optflag(
+        "",
+        "ignore-fixup-errors",
+        "Dump MFT records with torn sectors instead of skipping them.",
+    );
+    opts
+}
+
+// Dumps every MFT entry with a name, deleted ones included, as a filesystem timeline.
+pub fn run(args: &[String]) {
+    let opts = set_opts();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => panic!("{:?}", f),
+    };
+    let volume = match matches.opt_str("volume") {
+        Some(volume) if !matches.opt_present("help") && matches.free.is_empty() => volume,
+        _ => {
+            let usage = "Usage: squirrel mft-dump [options] --volume VOLUME";
+            return print!("{}", opts.usage(usage));
+        }
+    };
+    let format = match matches.opt_str("format").as_deref() {
+        None | Some("csv") => Format::Csv,
+        Some("bodyfile") => Format::Bodyfile,
+        Some(x) => panic!("Invalid format: {}", x),
+    };
+    let (device, prefix) = device(&volume);
+    let mut mft = MFT::open(device, matches.opt_present("ignore-fixup-errors"))
+        .unwrap_or_else(|e| panic!("Failed to open the MFT of {}: {}", volume, e));
+    let output: Box<dyn Write> = match matches.opt_str("output") {
+        Some(path) => Box::new(
+            File::create(&path).unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e)),
+        ),
+        None => Box::new(io::stdout()),
+    };
+    let mut output = BufWriter::new(output);
+    dump(&mut mft, &prefix, format, &mut output)
+        .unwrap_or_else(|e| panic!("Failed to dump the MFT of {}: {}", volume, e));
+    for idx in mft.take_fixup_errors() {
+        log::warn(format!("MFT entry {} is torn, the fixups don't match", idx));
+    }
+}
+
+// The device to open and what paths start with. A drive letter is opened as \\.\C:,
+// anything else is taken to be a device path already.
+fn device(volume: &str) -> (String, String) {
+    let letter = volume.trim_end_matches(':');
+    if letter.len() == 1 && letter.chars().all(|x| x.is_ascii_alphabetic()) {
+        let letter = letter.to_uppercase();
+        (format!("\\\\.\\{}:", letter), format!("{}:", letter))
+    } else {
+        (
+            String::from(volume),
+            String::from(volume.trim_end_matches('\\')),
+        )
+    }
+}
+
+fn dump(mft: &mut MFT, prefix: &str, format: Format, output: &mut dyn Write) -> io::Result<()> {
+    if let Format::Csv = format {
+        output.write_all(HEADER.as_bytes())?;
+    }
+    mft.walk(|idx, entry, name, dir| {
+        let row = Row::new(idx, entry, name, dir.map(|x| join(prefix, &x)));
+        let text = match format {
+            Format::Csv => row.csv(),
+            Format::Bodyfile => row.bodyfile(),
+        };
+        output.write_all(text.as_bytes())
+    })?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device() {
+        assert_eq!(
+            device("c:"),
+            (String::from("\\\\.\\C:"), String::from("C:"))
+        );
+        let path = "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\";
+        assert_eq!(device(path).1, path.trim_end_matches('\\'));
+    }
+}
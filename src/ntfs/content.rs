@@ -3,15 +3,32 @@ use std::fs::File;
 use std::io::{self, Cursor};
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
+use super::lznt1;
+
+/// The read buffer size of volumes when it isn't set.
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_BUFFER_SIZE);
+
+/// The size of the read buffer of volumes opened from now on, at least 4 KiB.
+pub fn set_buffer_size(bytes: usize) {
+    BUFFER_SIZE.store(bytes.max(4096), Ordering::Relaxed);
+}
+
+/// A buffered raw volume. Reads from the device have to be aligned to sectors, seeking
+/// only moves the buffer along so that they are.
 pub struct Volume<T> {
     pub inner: BufReader<T>,
 }
 
+/// Opens a device like `\\.\C:`, which needs administrator rights, or an image of one.
 pub fn open_volume<P: AsRef<Path>>(path: P) -> io::Result<Volume<File>> {
+    let capacity = BUFFER_SIZE.load(Ordering::Relaxed);
     Ok(Volume {
-        inner: BufReader::with_capacity(1024 * 1024, File::open(path)?),
+        inner: BufReader::with_capacity(capacity, File::open(path)?),
     })
 }
 
@@ -67,9 +84,10 @@ impl<T: Seek + Read> Seek for Volume<T> {
     }
 }
 
+/// A run of clusters, holes in sparse files have no offset.
 #[derive(Debug, PartialEq)]
 pub struct DataRun {
-    pub offset: u64,
+    pub offset: Option<u64>,
     pub virt_offset: u64,
     pub len: u64,
 }
@@ -77,23 +95,40 @@ pub struct DataRun {
 #[derive(Debug)]
 pub enum Content {
     Resident {
-        data: Rc<[u8]>,
+        data: Arc<[u8]>,
     },
     NonResident {
         run_start_vcn: u64,
-        run_end_vcn: u64,
-        alloc_size: u64,
         size: u64,
-        runs: Rc<[DataRun]>,
+        runs: Arc<[DataRun]>,
+        // In bytes, 0 when the attribute isn't compressed
+        compression_unit: u64,
     },
 }
 
 impl Content {
+    /// Appends the runs of the next extent of a non-resident attribute.
+    pub fn extend(&mut self, more: &[DataRun]) {
+        if let Content::NonResident { runs, size, .. } = self {
+            let all = runs.iter().chain(more).map(|x| (x.offset, x.len));
+            *runs = load_runs(all, *size).into();
+        }
+    }
     pub fn reader<T: Read + Seek>(&self, volume: T) -> ContentReader<T> {
         match self {
             Content::Resident { data } => ContentReader::Resident {
                 inner: Cursor::new(data.clone()),
             },
+            Content::NonResident {
+                runs,
+                compression_unit,
+                ..
+            } if *compression_unit > 0 => ContentReader::Compressed {
+                inner: CompressedReader::new(
+                    RunReader::new(volume, runs.clone()),
+                    *compression_unit,
+                ),
+            },
             Content::NonResident { runs, .. } => ContentReader::NonResident {
                 inner: RunReader::new(volume, runs.clone()),
             },
@@ -101,17 +136,21 @@ impl Content {
     }
 }
 
+/// Reads an attribute, resident or not, sparse and compressed ones included.
 #[derive(Debug)]
 pub enum ContentReader<T> {
-    Resident { inner: Cursor<Rc<[u8]>> },
+    Resident { inner: Cursor<Arc<[u8]>> },
     NonResident { inner: RunReader<T> },
+    Compressed { inner: CompressedReader<T> },
 }
 
 impl<T> ContentReader<T> {
+    /// The size of the content, not of the clusters it takes up.
     pub fn size(&self) -> u64 {
         match self {
             ContentReader::Resident { inner } => inner.get_ref().len().try_into().unwrap(),
             ContentReader::NonResident { inner } => inner.size,
+            ContentReader::Compressed { inner } => inner.inner.size,
         }
     }
 }
@@ -121,6 +160,7 @@ impl<T: Read + Seek> Read for ContentReader<T> {
         match self {
             ContentReader::Resident { inner } => inner.read(buf),
             ContentReader::NonResident { inner } => inner.read(buf),
+            ContentReader::Compressed { inner } => inner.read(buf),
         }
     }
 }
@@ -130,17 +170,19 @@ impl<T: Seek + Read> Seek for ContentReader<T> {
         match self {
             ContentReader::Resident { inner } => inner.seek(seek_pos),
             ContentReader::NonResident { inner } => inner.seek(seek_pos),
+            ContentReader::Compressed { inner } => inner.seek(seek_pos),
         }
     }
     fn stream_position(&mut self) -> io::Result<u64> {
         match self {
             ContentReader::Resident { inner } => inner.stream_position(),
             ContentReader::NonResident { inner } => inner.stream_position(),
+            ContentReader::Compressed { inner } => inner.stream_position(),
         }
     }
 }
 
-pub fn load_runs<T: IntoIterator<Item = (u64, u64)>>(iter: T, size: u64) -> Vec<DataRun> {
+pub fn load_runs<T: IntoIterator<Item = (Option<u64>, u64)>>(iter: T, size: u64) -> Vec<DataRun> {
     let mut runs = Vec::new();
     let mut virt_offset = 0;
     for (offset, len) in iter {
@@ -151,23 +193,32 @@ pub fn load_runs<T: IntoIterator<Item = (u64, u64)>>(iter: T, size: u64) -> Vec<
         });
         virt_offset = virt_offset + len;
     }
-    let slack = virt_offset - size;
-    let last_run = runs.len() - 1;
-    let fixed_len = runs[last_run].len - slack;
-    runs[last_run].len = fixed_len;
+    // The clusters past the end of the data, which can be more than the last run
+    let mut slack = virt_offset.saturating_sub(size);
+    while slack > 0 {
+        let last = runs.last_mut().unwrap();
+        if last.len > slack {
+            last.len = last.len - slack;
+            break;
+        }
+        slack = slack - last.len;
+        runs.pop();
+    }
     runs
 }
 
+/// Reads the clusters of a non-resident attribute from the volume, holes read as
+/// zeroes.
 #[derive(Debug)]
 pub struct RunReader<T> {
     volume: T,
     state: State,
-    pub runs: Rc<[DataRun]>,
+    pub runs: Arc<[DataRun]>,
     pub size: u64,
 }
 
 impl<T> RunReader<T> {
-    pub fn new(volume: T, runs: Rc<[DataRun]>) -> RunReader<T> {
+    pub fn new(volume: T, runs: Arc<[DataRun]>) -> RunReader<T> {
         RunReader {
             size: runs.iter().map(|x| x.len).sum(),
             state: State { run: 0, pos: 0 },
@@ -201,8 +252,8 @@ impl<T> RunReader<T> {
     fn run_remaining(&self) -> u64 {
         self.runs[self.state.run].len - self.state.pos
     }
-    fn position(&self) -> u64 {
-        self.runs[self.state.run].offset + self.state.pos
+    fn position(&self) -> Option<u64> {
+        Some(self.runs[self.state.run].offset? + self.state.pos)
     }
     fn virt_position(&self) -> u64 {
         self.runs[self.state.run].virt_offset + self.state.pos
@@ -212,8 +263,13 @@ impl<T> RunReader<T> {
 impl<T: Seek> Seek for RunReader<T> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let offset = self.seek_offset(pos);
+        if self.runs.is_empty() {
+            return Ok(offset);
+        }
         self.state = self.state_for(offset);
-        self.volume.seek(SeekFrom::Start(self.position()))?;
+        if let Some(position) = self.position() {
+            self.volume.seek(SeekFrom::Start(position))?;
+        }
         Ok(self.virt_position())
     }
     fn stream_position(&mut self) -> io::Result<u64> {
@@ -223,20 +279,133 @@ impl<T: Seek> Seek for RunReader<T> {
 
 impl<T: Read + Seek> Read for RunReader<T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.runs.is_empty() {
+            return Ok(0);
+        }
         let vpos = self.virt_position();
         if vpos == 0 || (self.run_remaining() == 0 && vpos < self.size) {
             self.seek(SeekFrom::Start(vpos))?;
         }
         let remaining = self.run_remaining();
-        let nread = {
+        let nread = if self.runs[self.state.run].offset.is_some() {
             let mut rdr = (&mut self.volume).take(remaining);
             rdr.read(buf)?
+        } else {
+            // A hole reads as zeros
+            let len = buf
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            for x in buf[..len].iter_mut() {
+                *x = 0;
+            }
+            len
         };
         self.state.pos = self.state.pos + u64::try_from(nread).unwrap();
         Ok(nread)
     }
 }
 
+/// Reads a compressed attribute one compression unit at a time. A unit with holes at
+/// the end holds LZNT1 compressed data in its clusters, one without is stored as is.
+#[derive(Debug)]
+pub struct CompressedReader<T> {
+    inner: RunReader<T>,
+    unit_size: u64,
+    pos: u64,
+    // The index and the data of the last unit read
+    unit: Option<(u64, Vec<u8>)>,
+}
+
+impl<T> CompressedReader<T> {
+    pub fn new(inner: RunReader<T>, unit_size: u64) -> CompressedReader<T> {
+        CompressedReader {
+            inner,
+            unit_size,
+            pos: 0,
+            unit: None,
+        }
+    }
+    // The bytes between start and end that are in clusters and not in holes.
+    fn allocated(&self, start: u64, end: u64) -> u64 {
+        self.inner
+            .runs
+            .iter()
+            .filter(|x| x.offset.is_some())
+            .map(|x| {
+                let run_end = x.virt_offset + x.len;
+                run_end.min(end).saturating_sub(x.virt_offset.max(start))
+            })
+            .sum()
+    }
+}
+
+impl<T: Read + Seek> CompressedReader<T> {
+    fn load(&mut self, idx: u64) -> io::Result<()> {
+        let start = idx * self.unit_size;
+        let end = (start + self.unit_size).min(self.inner.size);
+        let allocated = self.allocated(start, end);
+        let data = if allocated == 0 {
+            vec![0u8; usize::try_from(end - start).unwrap()]
+        } else {
+            let mut raw = vec![0u8; usize::try_from(allocated).unwrap()];
+            self.inner.seek(SeekFrom::Start(start))?;
+            self.inner.read_exact(&mut raw)?;
+            if allocated == end - start {
+                raw
+            } else {
+                lznt1::decompress(&raw, usize::try_from(self.unit_size).unwrap())?
+            }
+        };
+        self.unit = Some((idx, data));
+        Ok(())
+    }
+}
+
+impl<T: Read + Seek> Read for CompressedReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.inner.size {
+            return Ok(0);
+        }
+        let idx = self.pos / self.unit_size;
+        if self.unit.as_ref().is_none_or(|(x, _)| *x != idx) {
+            self.load(idx)?;
+        }
+        let data = &self.unit.as_ref().unwrap().1;
+        let offset = self.pos - idx * self.unit_size;
+        let available = (u64::try_from(data.len()).unwrap() - offset.min(self.unit_size))
+            .min(self.inner.size - self.pos);
+        let len = buf
+            .len()
+            .min(usize::try_from(available).unwrap_or(usize::MAX));
+        let offset = usize::try_from(offset).unwrap();
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        self.pos = self.pos + u64::try_from(len).unwrap();
+        Ok(len)
+    }
+}
+
+impl<T> Seek for CompressedReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(x) => (x, 0),
+            SeekFrom::Current(x) => (self.pos, x),
+            SeekFrom::End(x) => (self.inner.size, x),
+        };
+        // Like a file, seeking before the start is an error and past the end isn't
+        self.pos = i64::try_from(base)
+            .ok()
+            .and_then(|x| x.checked_add(offset))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "Seek to a negative position")
+            })?;
+        Ok(self.pos)
+    }
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
 #[derive(Debug)]
 struct State {
     run: usize,
@@ -272,30 +441,77 @@ mod tests {
     fn test_run_reader_seek() {
         let mut rdr = RunReader::new(
             Cursor::new(vec![0u8; 10000]),
-            load_runs(vec![(1000, 1000), (3000, 2000), (0, 1000)], 4000).into(),
+            load_runs(
+                vec![(Some(1000), 1000), (Some(3000), 2000), (Some(0), 1000)],
+                4000,
+            )
+            .into(),
         );
         let pos = rdr.seek(SeekFrom::Start(500)).unwrap();
         assert_eq!(pos, 500);
-        assert_eq!(rdr.position(), 1500);
+        assert_eq!(rdr.position(), Some(1500));
         let pos = rdr.seek(SeekFrom::Current(500)).unwrap();
         assert_eq!(pos, 1000);
-        assert_eq!(rdr.position(), 3000);
+        assert_eq!(rdr.position(), Some(3000));
         let pos = rdr.seek(SeekFrom::Current(-1)).unwrap();
         assert_eq!(pos, 999);
-        assert_eq!(rdr.position(), 1999);
+        assert_eq!(rdr.position(), Some(1999));
         let pos = rdr.seek(SeekFrom::End(-100)).unwrap();
         assert_eq!(pos, 3900);
-        assert_eq!(rdr.position(), 900);
+        assert_eq!(rdr.position(), Some(900));
     }
 
     #[test]
     fn test_run_reader_read() {
         let mut rdr = RunReader::new(
             Cursor::new(b"4560123XXX789"),
-            load_runs(vec![(3, 4), (0, 3), (10, 3)], 10).into(),
+            load_runs(vec![(Some(3), 4), (Some(0), 3), (Some(10), 3)], 10).into(),
         );
         let mut buf = [0u8; 10];
         rdr.read_exact(&mut buf).unwrap();
         assert_eq!(&buf, b"0123456789");
     }
+
+    #[test]
+    fn test_compressed_reader() {
+        // A stored unit, a compressed one (abcabcabcabc) and one that's a hole
+        let mut volume = b"0123456789abcdef".to_vec();
+        volume.extend_from_slice(&[0x05, 0xB0, 0x08, b'a', b'b', b'c', 0x06, 0x20, 0, 0]);
+        let runs = vec![(Some(0), 16), (Some(16), 10), (None, 6), (None, 16)];
+        let mut rdr = CompressedReader::new(
+            RunReader::new(Cursor::new(volume), load_runs(runs, 44).into()),
+            16,
+        );
+        let mut buf = Vec::new();
+        rdr.read_to_end(&mut buf).unwrap();
+        let mut expected = b"0123456789abcdefabcabcabcabc".to_vec();
+        expected.resize(44, 0);
+        assert_eq!(buf, expected);
+        rdr.seek(SeekFrom::Start(19)).unwrap();
+        let mut buf = [0u8; 4];
+        rdr.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abca");
+        let err = rdr.seek(SeekFrom::Current(-24)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(rdr.stream_position().unwrap(), 23);
+        assert_eq!(rdr.seek(SeekFrom::End(-4)).unwrap(), 40);
+    }
+
+    #[test]
+    fn test_run_reader_sparse() {
+        let mut rdr = RunReader::new(
+            Cursor::new(b"0123456789"),
+            load_runs(vec![(Some(2), 3), (None, 4), (Some(7), 3), (None, 8)], 12).into(),
+        );
+        let mut buf = Vec::new();
+        rdr.read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf, b"234\x00\x00\x00\x00789\x00\x00");
+        rdr.seek(SeekFrom::Start(5)).unwrap();
+        assert_eq!(rdr.position(), None);
+        let mut buf = [1u8; 4];
+        rdr.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x00\x0078");
+        let mut empty = RunReader::new(Cursor::new(b""), load_runs(vec![(None, 8)], 0).into());
+        assert_eq!(empty.read(&mut buf).unwrap(), 0);
+    }
 }
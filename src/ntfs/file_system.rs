@@ -1,4 +1,5 @@
 use byteorder::{ReadBytesExt, LE};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io;
@@ -6,59 +7,412 @@ use std::io::Cursor;
 use std::io::{Read, Seek, SeekFrom};
 
 use super::content::{open_volume, ContentReader, Volume};
-use super::metadata::{parse_mft_entry, MFTEntry};
+use super::index::{read_index, IndexEntry};
+use super::metadata::{parse_mft_entry, FileName, MFTEntry, Timestamps};
 
+// The root directory is always this entry.
+const ROOT: u64 = 5;
+// $Bitmap, which has a bit for every cluster that's in use.
+const BITMAP: i64 = 6;
+
+// The lowercase names in a directory, with the entry and sequence number in their
+// reference.
+type Listing = HashMap<String, (u64, u16)>;
+
+/// The MFT of a volume, read from the raw device (`\\.\C:` or a shadow copy device).
 pub struct MFT {
+    /// The data of $MFT itself, the records one after the other.
     pub data: ContentReader<Volume<File>>,
     pub boot: Boot,
+    volume: String,
+    names: Option<Names>,
+    // What entry_by_path has read, so every directory index is read once. The listing is
+    // None when the entry isn't a directory.
+    dirs: HashMap<u64, Option<Listing>>,
+    sequences: HashMap<u64, u16>,
+    torn: Fixups,
 }
 
 impl MFT {
-    pub fn open<T: Into<String>>(volume: T) -> io::Result<MFT> {
+    /// With `ignore_fixup_errors` torn records are parsed anyway instead of failing,
+    /// either way they're kept for take_fixup_errors.
+    pub fn open<T: Into<String>>(volume: T, ignore_fixup_errors: bool) -> io::Result<MFT> {
         let vol_path = volume.into();
         let mut vol = open_volume(&vol_path)?;
         let boot = parse_boot(&mut vol)?;
         go_to_mft(&boot, &mut vol)?;
-        let mut buf = [0u8; 1024];
+        let mut buf = vec![0u8; usize::try_from(boot.record_size).unwrap()];
         vol.read_exact(&mut buf)?;
-        let entry = parse_mft_entry(boot.sector_size, boot.cluster_size, vol, Cursor::new(buf))?;
-        let data = entry.into_data().unwrap();
+        let entry = parse_mft_entry(boot.cluster_size, boot.record_size, vol, Cursor::new(buf))?;
+        let mut torn = Fixups {
+            ignore: ignore_fixup_errors,
+            errors: Vec::new(),
+        };
+        let entry = torn.check(0, entry)?;
+        let data = entry
+            .into_data()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No $DATA in $MFT"))?;
         Ok(MFT {
             data,
             boot,
+            volume: vol_path,
+            names: None,
+            dirs: HashMap::new(),
+            sequences: HashMap::new(),
+            torn,
         })
     }
+    /// The entry numbers of the torn records that came up since the last call.
+    pub fn take_fixup_errors(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.torn.errors)
+    }
+    /// Like take_fixup_errors, without clearing them.
+    pub fn fixup_errors(&self) -> &[i64] {
+        &self.torn.errors
+    }
+    /// Opens an entry with the attributes in its extension records, when it has an
+    /// $ATTRIBUTE_LIST.
     pub fn open_entry<T>(&mut self, volume: T, idx: i64) -> io::Result<MFTEntry<T>> {
-        self.data
-            .seek(SeekFrom::Start(u64::try_from(idx).unwrap() * 1024))?;
-        parse_mft_entry(
-            self.boot.sector_size,
+        let mut entry = self.open_record(volume, idx)?;
+        if entry.has_attribute_list() {
+            let records = entry.extension_records(open_volume(&self.volume)?)?;
+            let mut extensions = Vec::new();
+            for record in records {
+                let record = i64::try_from(record).unwrap();
+                if record != idx {
+                    extensions.push(self.open_record((), record)?);
+                }
+            }
+            entry.add_extensions(extensions);
+        }
+        Ok(entry)
+    }
+    fn open_record<T>(&mut self, volume: T, idx: i64) -> io::Result<MFTEntry<T>> {
+        self.data.seek(SeekFrom::Start(
+            u64::try_from(idx).unwrap() * self.boot.record_size,
+        ))?;
+        let entry = parse_mft_entry(
             self.boot.cluster_size,
+            self.boot.record_size,
             volume,
             &mut self.data,
         )
+        .map_err(|e| io::Error::new(e.kind(), format!("MFT entry {}: {}", idx, e)))?;
+        self.torn.check(idx, entry)
+    }
+    /// Finds the entry of a path relative to the root of the volume, like
+    /// Windows\System32\config\SYSTEM. Names are matched case insensitively. A reference
+    /// is only followed when its sequence number matches, otherwise the index is stale and
+    /// the record was reused.
+    pub fn entry_by_path(&mut self, path: &str) -> io::Result<Option<i64>> {
+        let mut entry = ROOT;
+        for part in path.split(['\\', '/']) {
+            if part.is_empty() || part == "." {
+                continue;
+            }
+            let found = self
+                .listing(entry)?
+                .as_ref()
+                .and_then(|x| x.get(&part.to_lowercase()))
+                .copied();
+            match found {
+                Some((child, sequence)) if self.sequence(child)? == sequence => entry = child,
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(i64::try_from(entry).unwrap()))
+    }
+    fn listing(&mut self, idx: u64) -> io::Result<&Option<Listing>> {
+        if !self.dirs.contains_key(&idx) {
+            let volume = open_volume(&self.volume)?;
+            let mut entry = self.open_entry(volume, i64::try_from(idx).unwrap())?;
+            self.sequences.insert(idx, entry.sequence());
+            let listing = if entry.is_dir() {
+                let mut listing = HashMap::new();
+                for child in read_index(&mut entry, self.torn.ignore)? {
+                    let name = child.file_name.name.to_lowercase();
+                    listing.insert(name, (child.entry, child.sequence));
+                }
+                Some(listing)
+            } else {
+                None
+            };
+            self.dirs.insert(idx, listing);
+        }
+        Ok(&self.dirs[&idx])
+    }
+    fn sequence(&mut self, idx: u64) -> io::Result<u16> {
+        if !self.sequences.contains_key(&idx) {
+            let volume = open_volume(&self.volume)?;
+            let entry = self.open_entry(volume, i64::try_from(idx).unwrap())?;
+            self.sequences.insert(idx, entry.sequence());
+        }
+        Ok(self.sequences[&idx])
+    }
+    /// Lists a directory from its $I30 index.
+    pub fn read_dir(&mut self, idx: i64) -> io::Result<Vec<IndexEntry>> {
+        let volume = open_volume(&self.volume)?;
+        let mut dir = self.open_entry(volume, idx)?;
+        read_index(&mut dir, self.torn.ignore)
+    }
+    /// The path of an entry relative to the root of the volume, from the parent references
+    /// of its long name. None when a parent is gone, as with deleted files.
+    pub fn path_of(&mut self, idx: i64) -> io::Result<Option<String>> {
+        Ok(self.names()?.path(u64::try_from(idx).unwrap()))
+    }
+    /// Calls `f` with every entry that has a name, in use or not, with its long name and
+    /// the path of the directory that's in. Records that can't be parsed are skipped.
+    pub fn walk<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(i64, &MFTEntry<()>, &FileName, Option<String>) -> io::Result<()>,
+    {
+        self.names()?;
+        let names = self.names.as_ref().unwrap();
+        scan(&mut self.data, &self.boot, &mut self.torn, |idx, entry| {
+            let name = match preferred_name(&entry) {
+                Some(name) => name,
+                None => return Ok(()),
+            };
+            let dir = names.dir_path(&name);
+            f(i64::try_from(idx).unwrap(), &entry, &name, dir)
+        })
+    }
+    /// Lists the files in entries that are no longer in use, records that can't be parsed
+    /// or are torn are skipped.
+    pub fn deleted(&mut self) -> io::Result<Vec<Deleted>> {
+        let mut bitmap = Vec::new();
+        self.open_entry(open_volume(&self.volume)?, BITMAP)?
+            .into_data()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No $DATA in $Bitmap"))?
+            .read_to_end(&mut bitmap)?;
+        let cluster_size = u64::from(self.boot.cluster_size);
+        let mut found = Vec::new();
+        scan(&mut self.data, &self.boot, &mut self.torn, |idx, entry| {
+            if entry.is_torn() || entry.in_use() || entry.is_dir() {
+                return Ok(());
+            }
+            let name = match preferred_name(&entry) {
+                Some(name) => name,
+                None => return Ok(()),
+            };
+            let (size, runs) = match entry.data_runs() {
+                Some(data) => data,
+                None => return Ok(()),
+            };
+            // Recoverable when none of its clusters were given to another file
+            let recoverable = runs.iter().all(|run| match run.offset {
+                Some(offset) => {
+                    let end = (offset + run.len).div_ceil(cluster_size);
+                    (offset / cluster_size..end).all(|x| is_free(&bitmap, x))
+                }
+                None => true,
+            });
+            let times = entry.standard_information().map(|x| x.times);
+            found.push((idx, name, size, recoverable, times));
+            Ok(())
+        })?;
+        let names = self.names()?;
+        let mut deleted = Vec::new();
+        for (idx, name, size, recoverable, times) in found {
+            let path = names.dir_path(&name).map(|x| match x.as_str() {
+                "" => name.name.clone(),
+                _ => format!("{}\\{}", x, name.name),
+            });
+            deleted.push(Deleted {
+                entry: i64::try_from(idx).unwrap(),
+                name: name.name,
+                path,
+                size,
+                recoverable,
+                times,
+            });
+        }
+        Ok(deleted)
+    }
+    fn names(&mut self) -> io::Result<&Names> {
+        if self.names.is_none() {
+            self.names = Some(self.read_names()?);
+        }
+        Ok(self.names.as_ref().unwrap())
+    }
+    // Reads the names of every entry in use. Names are only linked to their parent when
+    // the sequence number in the reference matches, otherwise the parent was deleted and
+    // its record reused.
+    fn read_names(&mut self) -> io::Result<Names> {
+        let mut names = Vec::new();
+        let mut sequences = HashMap::new();
+        scan(&mut self.data, &self.boot, &mut self.torn, |idx, entry| {
+            if !entry.in_use() {
+                return Ok(());
+            }
+            sequences.insert(idx, entry.sequence());
+            // The root is its own parent
+            if idx != ROOT {
+                names.extend(entry.file_names().into_iter().map(|x| (idx, x)));
+            }
+            Ok(())
+        })?;
+        let mut parents = HashMap::new();
+        for (idx, name) in names {
+            if sequences.get(&name.parent) != Some(&name.parent_sequence) {
+                continue;
+            }
+            if !name.is_dos() || !parents.contains_key(&idx) {
+                parents.insert(idx, (name.parent, name.name));
+            }
+        }
+        Ok(Names { parents, sequences })
     }
 }
 
+// Calls `f` with every record of the MFT that can be parsed. Torn records are recorded
+// and skipped, unless fixup errors are ignored.
+fn scan<F>(
+    data: &mut ContentReader<Volume<File>>,
+    boot: &Boot,
+    torn: &mut Fixups,
+    mut f: F,
+) -> io::Result<()>
+where
+    F: FnMut(u64, MFTEntry<()>) -> io::Result<()>,
+{
+    let mut buf = vec![0u8; usize::try_from(boot.record_size).unwrap()];
+    data.seek(SeekFrom::Start(0))?;
+    for idx in 0..data.size() / boot.record_size {
+        data.read_exact(&mut buf)?;
+        if &buf[0..4] != b"FILE" {
+            continue;
+        }
+        let entry =
+            match parse_mft_entry(boot.cluster_size, boot.record_size, (), Cursor::new(&buf)) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+        let entry = match torn.check(i64::try_from(idx).unwrap(), entry) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        f(idx, entry)?;
+    }
+    Ok(())
+}
+
+// The long name of an entry, its DOS name when that's all it has.
+fn preferred_name<T>(entry: &MFTEntry<T>) -> Option<FileName> {
+    entry.file_names().into_iter().min_by_key(|x| x.is_dos())
+}
+
+// The names of the entries in use, from the $FILE_NAME attributes of the whole MFT.
+struct Names {
+    // Entry to parent and long name
+    parents: HashMap<u64, (u64, String)>,
+    sequences: HashMap<u64, u16>,
+}
+
+impl Names {
+    fn path(&self, idx: u64) -> Option<String> {
+        let mut entry = idx;
+        let mut parts = Vec::new();
+        while entry != ROOT {
+            match self.parents.get(&entry) {
+                // A broken MFT could have a loop
+                Some((parent, name)) if parts.len() < 1024 => {
+                    parts.push(name.as_str());
+                    entry = *parent;
+                }
+                _ => return None,
+            }
+        }
+        parts.reverse();
+        Some(parts.join("\\"))
+    }
+    // The path of the directory a name is in, only while that directory hasn't been
+    // deleted as well.
+    fn dir_path(&self, name: &FileName) -> Option<String> {
+        if self.sequences.get(&name.parent) != Some(&name.parent_sequence) {
+            return None;
+        }
+        self.path(name.parent)
+    }
+}
+
+/// A file in an MFT entry that isn't in use. Its data is recoverable as long as none of
+/// its clusters were reused, it's read like any other entry.
+#[derive(Debug, PartialEq)]
+pub struct Deleted {
+    pub entry: i64,
+    pub name: String,
+    pub path: Option<String>,
+    pub size: u64,
+    pub recoverable: bool,
+    pub times: Option<Timestamps>,
+}
+
+// Clusters past the end of the bitmap don't exist, so they can't be free.
+fn is_free(bitmap: &[u8], cluster: u64) -> bool {
+    usize::try_from(cluster / 8)
+        .ok()
+        .and_then(|x| bitmap.get(x))
+        .is_some_and(|x| x >> (cluster % 8) & 1 == 0)
+}
+
+// The torn records of an MFT, which are an error unless they're ignored.
+struct Fixups {
+    ignore: bool,
+    errors: Vec<i64>,
+}
+
+impl Fixups {
+    fn check<T>(&mut self, idx: i64, entry: MFTEntry<T>) -> io::Result<MFTEntry<T>> {
+        if !entry.is_torn() {
+            return Ok(entry);
+        }
+        if !self.errors.contains(&idx) {
+            self.errors.push(idx);
+        }
+        if self.ignore {
+            return Ok(entry);
+        }
+        let msg = format!("MFT entry {} is torn, the fixups don't match", idx);
+        Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+}
+
+/// The layout of the volume from its boot sector, the sizes are in bytes.
 #[derive(Debug)]
 pub struct Boot {
     pub sector_size: u16,
-    pub cluster_size: u16,
+    pub cluster_size: u32,
+    /// Where the first record of the MFT is.
     pub mft_start: u64,
+    /// Usually 1024, 4096 on some disks with 4K sectors.
+    pub record_size: u64,
 }
 
 fn parse_boot<T: Seek + Read>(vol: &mut T) -> io::Result<Boot> {
     vol.seek(SeekFrom::Current(11))?;
     let sector_size = vol.read_u16::<LE>()?;
-    let sectors_per_cluster = vol.read_u16::<LE>()?;
-    let cluster_size: u16 = (sector_size * sectors_per_cluster).into();
-    vol.seek(SeekFrom::Current(33))?;
+    // More than 128 sectors per cluster is stored as a negative power of two
+    let sectors_per_cluster = match vol.read_u8()? {
+        x if x > 0x80 => 1 << (256 - u32::from(x)),
+        x => u32::from(x),
+    };
+    let cluster_size = u32::from(sector_size) * sectors_per_cluster;
+    vol.seek(SeekFrom::Current(34))?;
     let mft_start_cluster = vol.read_u64::<LE>()?;
     let mft_start = mft_start_cluster * u64::from(cluster_size);
+    vol.seek(SeekFrom::Current(8))?;
+    // In clusters, or when negative the size is 2 to the power of minus the value
+    let record_clusters = vol.read_i8()?;
+    let record_size = match u64::try_from(record_clusters) {
+        Ok(clusters) => clusters * u64::from(cluster_size),
+        Err(_) => 1 << -i32::from(record_clusters),
+    };
     Ok(Boot {
         sector_size,
         cluster_size,
         mft_start,
+        record_size,
     })
 }
 
@@ -68,8 +422,491 @@ fn go_to_mft<T: Read + Seek>(boot: &Boot, vol: &mut T) -> io::Result<()> {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
+    use std::path::PathBuf;
+
+    // Builders for MFT records and a small volume image to test with, clusters are
+    // one sector.
+    pub fn record(flags: u16, attrs: &[Vec<u8>]) -> Vec<u8> {
+        record_sized(1024, flags, attrs)
+    }
+
+    pub fn record_sized(size: usize, flags: u16, attrs: &[Vec<u8>]) -> Vec<u8> {
+        let sectors = size / 512;
+        // The attributes follow the fixup array
+        let attr_offset = (50 + sectors * 2).next_multiple_of(8);
+        let mut rec = vec![0u8; size];
+        rec[0..4].copy_from_slice(b"FILE");
+        rec[4..6].copy_from_slice(&48u16.to_le_bytes());
+        rec[6..8].copy_from_slice(&u16::try_from(sectors + 1).unwrap().to_le_bytes());
+        rec[16..18].copy_from_slice(&1u16.to_le_bytes());
+        rec[20..22].copy_from_slice(&u16::try_from(attr_offset).unwrap().to_le_bytes());
+        rec[22..24].copy_from_slice(&flags.to_le_bytes());
+        let mut pos = attr_offset;
+        for attr in attrs {
+            rec[pos..pos + attr.len()].copy_from_slice(attr);
+            pos = pos + attr.len();
+        }
+        rec[pos..pos + 4].copy_from_slice(&[0xFF; 4]);
+        rec[24..28].copy_from_slice(&u32::try_from(pos + 8).unwrap().to_le_bytes());
+        rec[28..32].copy_from_slice(&u32::try_from(size).unwrap().to_le_bytes());
+        rec[48..50].copy_from_slice(&[1, 0]);
+        for sector in 0..sectors {
+            let end = (sector + 1) * 512;
+            let orig = 50 + sector * 2;
+            let (head, tail) = rec.split_at_mut(end - 2);
+            head[orig..orig + 2].copy_from_slice(&tail[0..2]);
+            tail[0..2].copy_from_slice(&[1, 0]);
+        }
+        rec
+    }
+
+    pub fn resident(attr_type: u32, data: &[u8]) -> Vec<u8> {
+        resident_named(attr_type, "", data)
+    }
+
+    pub fn resident_named(attr_type: u32, name: &str, data: &[u8]) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let offset = (24 + name.len() * 2).next_multiple_of(8);
+        let length = (offset + data.len()).next_multiple_of(8);
+        let mut attr = vec![0u8; length];
+        attr[0..4].copy_from_slice(&attr_type.to_le_bytes());
+        attr[4..8].copy_from_slice(&u32::try_from(length).unwrap().to_le_bytes());
+        attr[9] = u8::try_from(name.len()).unwrap();
+        attr[10..12].copy_from_slice(&24u16.to_le_bytes());
+        attr[16..20].copy_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+        attr[20..22].copy_from_slice(&u16::try_from(offset).unwrap().to_le_bytes());
+        for (i, x) in name.iter().enumerate() {
+            attr[24 + i * 2..26 + i * 2].copy_from_slice(&x.to_le_bytes());
+        }
+        attr[offset..offset + data.len()].copy_from_slice(data);
+        attr
+    }
+
+    pub fn non_resident(attr_type: u32, runs: &[u8], size: u64) -> Vec<u8> {
+        non_resident_named(attr_type, "", runs, size)
+    }
+
+    pub fn non_resident_named(attr_type: u32, name: &str, runs: &[u8], size: u64) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let run_offset = (64 + name.len() * 2).next_multiple_of(8);
+        let length = (run_offset + runs.len() + 1).next_multiple_of(8);
+        let mut attr = vec![0u8; length];
+        attr[0..4].copy_from_slice(&attr_type.to_le_bytes());
+        attr[4..8].copy_from_slice(&u32::try_from(length).unwrap().to_le_bytes());
+        attr[8] = 1;
+        attr[9] = u8::try_from(name.len()).unwrap();
+        attr[10..12].copy_from_slice(&64u16.to_le_bytes());
+        attr[32..34].copy_from_slice(&u16::try_from(run_offset).unwrap().to_le_bytes());
+        attr[40..48].copy_from_slice(&size.to_le_bytes());
+        attr[48..56].copy_from_slice(&size.to_le_bytes());
+        attr[56..64].copy_from_slice(&size.to_le_bytes());
+        for (i, x) in name.iter().enumerate() {
+            attr[64 + i * 2..66 + i * 2].copy_from_slice(&x.to_le_bytes());
+        }
+        attr[run_offset..run_offset + runs.len()].copy_from_slice(runs);
+        attr
+    }
+
+    pub fn file_name(parent: u64, name: &str) -> Vec<u8> {
+        named(parent, 1, name)
+    }
+
+    pub fn named(parent: u64, namespace: u8, name: &str) -> Vec<u8> {
+        resident(48, &file_name_data(parent, namespace, name))
+    }
+
+    pub fn file_name_data(parent: u64, namespace: u8, name: &str) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let mut data = vec![0u8; 66];
+        // Sequence number 1
+        data[0..8].copy_from_slice(&(parent | 1 << 48).to_le_bytes());
+        data[64] = u8::try_from(name.len()).unwrap();
+        data[65] = namespace;
+        for x in name {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data
+    }
+
+    // An index node with its entries starting at `start`, the keys are $FILE_NAMEs.
+    pub fn index_node(start: usize, entries: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut node = vec![0u8; start];
+        for (entry, key) in entries {
+            let length = (16 + key.len()).next_multiple_of(8);
+            let mut bytes = vec![0u8; length];
+            bytes[0..8].copy_from_slice(&(entry | 1 << 48).to_le_bytes());
+            bytes[8..10].copy_from_slice(&u16::try_from(length).unwrap().to_le_bytes());
+            bytes[10..12].copy_from_slice(&u16::try_from(key.len()).unwrap().to_le_bytes());
+            bytes[16..16 + key.len()].copy_from_slice(key);
+            node.extend_from_slice(&bytes);
+        }
+        let mut last = [0u8; 16];
+        last[8] = 16;
+        last[12] = 2;
+        node.extend_from_slice(&last);
+        let end = u32::try_from(node.len()).unwrap();
+        node[0..4].copy_from_slice(&u32::try_from(start).unwrap().to_le_bytes());
+        node[4..8].copy_from_slice(&end.to_le_bytes());
+        node[8..12].copy_from_slice(&end.to_le_bytes());
+        node
+    }
+
+    pub fn index_root(entries: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0u8; 16];
+        data[0] = 48;
+        data[4] = 1;
+        data[8..12].copy_from_slice(&4096u32.to_le_bytes());
+        data[12] = 8;
+        data.extend_from_slice(&index_node(16, entries));
+        resident_named(0x90, "$I30", &data)
+    }
+
+    // A volume with a 16 entry MFT at cluster 4, the root directory with a Windows directory
+    // in it and Windows\SYSTEM with resident data.
+    pub fn volume(name: &str, root: &[(u64, Vec<u8>)], records: Vec<(usize, Vec<u8>)>) -> PathBuf {
+        volume_sized(name, 512, 1024, root, records)
+    }
+
+    pub fn volume_sized(
+        name: &str,
+        sector_size: usize,
+        record_size: usize,
+        root: &[(u64, Vec<u8>)],
+        mut records: Vec<(usize, Vec<u8>)>,
+    ) -> PathBuf {
+        let mft_start = 4 * sector_size;
+        let mut image = vec![0u8; mft_start + 16 * record_size];
+        image[11..13].copy_from_slice(&u16::try_from(sector_size).unwrap().to_le_bytes());
+        image[13] = 1;
+        image[48..56].copy_from_slice(&4u64.to_le_bytes());
+        image[64] = 0u8.wrapping_sub(u8::try_from(record_size.trailing_zeros()).unwrap());
+        let clusters = u8::try_from(16 * record_size / sector_size).unwrap();
+        let mft_size = u64::try_from(16 * record_size).unwrap();
+        let mft = non_resident(128, &[0x11, clusters, 0x04], mft_size);
+        records.push((0, record_sized(record_size, 1, &[mft])));
+        let mut root = root.to_vec();
+        root.push((6, file_name_data(5, 1, "Windows")));
+        let root = [file_name(5, "."), index_root(&root)];
+        records.push((5, record_sized(record_size, 3, &root)));
+        let windows = [
+            file_name(5, "Windows"),
+            index_root(&[(7, file_name_data(6, 1, "SYSTEM"))]),
+        ];
+        records.push((6, record_sized(record_size, 3, &windows)));
+        let system = [file_name(6, "SYSTEM"), resident(128, b"regf")];
+        records.push((7, record_sized(record_size, 1, &system)));
+        for (idx, rec) in records {
+            let start = mft_start + idx * record_size;
+            image[start..start + record_size].copy_from_slice(&rec);
+        }
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, image).unwrap();
+        path
+    }
+
+    // A volume with a USN journal, $Extend\$UsnJrnl (12) has an $ATTRIBUTE_LIST and its
+    // $J stream is split over entries 13 and 14. The stream starts with a cluster that's
+    // a hole, the rest is clusters 40 to 42 with 512 bytes of 1, 2 and 3.
+    pub fn usnjrnl_volume(name: &str) -> PathBuf {
+        let mut list = Vec::new();
+        let listed: [(u32, u64, u64, &str); 3] =
+            [(48, 0, 12, ""), (128, 0, 13, "$J"), (128, 2, 14, "$J")];
+        for (attr_type, vcn, record, name) in listed.iter() {
+            let length = (26 + name.len() * 2).next_multiple_of(8);
+            let mut entry = vec![0u8; length];
+            entry[0..4].copy_from_slice(&attr_type.to_le_bytes());
+            entry[4..6].copy_from_slice(&u16::try_from(length).unwrap().to_le_bytes());
+            entry[6] = u8::try_from(name.len()).unwrap();
+            entry[7] = 26;
+            entry[8..16].copy_from_slice(&vcn.to_le_bytes());
+            entry[16..24].copy_from_slice(&(record | 1 << 48).to_le_bytes());
+            for (i, x) in name.encode_utf16().enumerate() {
+                entry[26 + i * 2..28 + i * 2].copy_from_slice(&x.to_le_bytes());
+            }
+            list.extend(entry);
+        }
+        let usnjrnl = [resident(0x20, &list), file_name(11, "$UsnJrnl")];
+        // A hole and cluster 40, then clusters 41 and 42 from VCN 2 on
+        let first = non_resident_named(128, "$J", &[0x01, 0x01, 0x11, 0x01, 40], 4 * 512);
+        let mut second = non_resident_named(128, "$J", &[0x11, 0x02, 41], 0);
+        second[16..24].copy_from_slice(&2u64.to_le_bytes());
+        second[24..32].copy_from_slice(&3u64.to_le_bytes());
+        let extend = [
+            file_name(5, "$Extend"),
+            index_root(&[(12, file_name_data(11, 1, "$UsnJrnl"))]),
+        ];
+        let records = vec![
+            (11, record(3, &extend)),
+            (12, record(1, &usnjrnl)),
+            (13, record(1, &[first])),
+            (14, record(1, &[second])),
+        ];
+        let root = [(11, file_name_data(5, 1, "$Extend"))];
+        let path = volume(name, &root, records);
+        let mut image = std::fs::read(&path).unwrap();
+        image.resize(40 * 512, 0);
+        for x in 1..4 {
+            image.extend_from_slice(&[x; 512]);
+        }
+        std::fs::write(&path, image).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_entry_by_path() {
+        let path = volume("squirrel-test-entry-by-path", &[], Vec::new());
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(mft.entry_by_path("windows\\System").unwrap(), Some(7));
+        assert_eq!(mft.entry_by_path("Windows").unwrap(), Some(6));
+        assert_eq!(mft.entry_by_path("Windows\\SAM").unwrap(), None);
+        let vol = open_volume(&path).unwrap();
+        let mut data = String::new();
+        let entry = mft.open_entry(vol, 7).unwrap();
+        entry
+            .into_data()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "regf");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stale_index_entry() {
+        // The root still lists entry 3 with sequence number 1, it has since been reused
+        let mut reused = record(1, &[file_name(5, "new.txt")]);
+        reused[16..18].copy_from_slice(&2u16.to_le_bytes());
+        let root = [(3, file_name_data(5, 1, "old.txt"))];
+        let path = volume("squirrel-test-stale-index-entry", &root, vec![(3, reused)]);
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(mft.entry_by_path("old.txt").unwrap(), None);
+        assert_eq!(mft.entry_by_path("Windows\\SYSTEM").unwrap(), Some(7));
+        // Directories are only read once
+        assert_eq!(mft.dirs.len(), 2);
+        mft.dirs.get_mut(&6).unwrap().as_mut().unwrap().clear();
+        assert_eq!(mft.entry_by_path("windows\\system").unwrap(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_large_records() {
+        let path = volume_sized("squirrel-test-large-records", 512, 4096, &[], Vec::new());
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(mft.boot.record_size, 4096);
+        assert_eq!(mft.entry_by_path("Windows\\SYSTEM").unwrap(), Some(7));
+        assert_eq!(
+            mft.path_of(7).unwrap(),
+            Some(String::from("Windows\\SYSTEM"))
+        );
+        let mut data = String::new();
+        let entry = mft.open_entry(open_volume(&path).unwrap(), 7).unwrap();
+        entry
+            .into_data()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "regf");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_4k_sectors() {
+        // The fixups still cover 512 bytes each, not a whole sector
+        let path = volume_sized("squirrel-test-4k-sectors", 4096, 4096, &[], Vec::new());
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(mft.boot.sector_size, 4096);
+        assert_eq!(mft.entry_by_path("Windows\\SYSTEM").unwrap(), Some(7));
+        let mut data = String::new();
+        let entry = mft.open_entry(open_volume(&path).unwrap(), 7).unwrap();
+        assert!(!entry.is_torn());
+        entry
+            .into_data()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "regf");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_attribute_list() {
+        let path = usnjrnl_volume("squirrel-test-attribute-list");
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(mft.entry_by_path("$Extend\\$UsnJrnl").unwrap(), Some(12));
+        let entry = mft.open_entry(open_volume(&path).unwrap(), 12).unwrap();
+        assert_eq!(entry.file_names()[0].name, "$UsnJrnl");
+        let (size, runs) = entry.stream_runs("$J").unwrap();
+        assert_eq!(size, 2048);
+        let runs: Vec<(Option<u64>, u64, u64)> = runs
+            .iter()
+            .map(|x| (x.offset, x.virt_offset, x.len))
+            .collect();
+        assert_eq!(
+            runs,
+            vec![
+                (None, 0, 512),
+                (Some(40 * 512), 512, 512),
+                (Some(41 * 512), 1024, 1024)
+            ]
+        );
+        let mut data = Vec::new();
+        entry
+            .into_stream("$J")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data[511..514], [0, 1, 1]);
+        assert_eq!(data[1535..1537], [2, 3]);
+        assert_eq!(data.len(), 2048);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_entry() {
+        let mut torn = record(1, &[file_name(5, "torn.txt")]);
+        torn[1022..1024].copy_from_slice(&[0, 0]);
+        let path = volume("squirrel-test-torn-entry", &[], vec![(3, torn)]);
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        let err = mft.open_entry((), 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(mft.path_of(3).unwrap(), None);
+        assert_eq!(mft.take_fixup_errors(), vec![3]);
+        let mut mft = MFT::open(path.to_str().unwrap(), true).unwrap();
+        assert!(mft.open_entry((), 3).unwrap().is_torn());
+        assert_eq!(mft.take_fixup_errors(), vec![3]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_deleted() {
+        // Cluster 68 is past the MFT and free, cluster 4 is where the MFT starts
+        let gone = [file_name(8, "gone.txt"), resident(128, b"gone")];
+        let kept = [
+            file_name(5, "kept.txt"),
+            non_resident(128, &[0x11, 1, 68], 6),
+        ];
+        let over = [
+            file_name(5, "over.txt"),
+            non_resident(128, &[0x11, 1, 4], 6),
+        ];
+        let orphan = [file_name(30, "orphan.txt"), resident(128, b"")];
+        let records = vec![
+            (8, record(3, &[file_name(5, "Temp")])),
+            (9, record(0, &gone)),
+            (10, record(0, &kept)),
+            (11, record(0, &over)),
+            (12, record(0, &orphan)),
+            (13, record(2, &[file_name(5, "Old")])),
+        ];
+        let path = volume("squirrel-test-deleted", &[], records);
+        let mut image = std::fs::read(&path).unwrap();
+        // $Bitmap takes the place of Windows, with the boot sector and the MFT in use
+        let mut bitmap = vec![0u8; 64];
+        for cluster in 0..36 {
+            bitmap[cluster / 8] |= 1 << (cluster % 8);
+        }
+        let bitmap = record(1, &[file_name(5, "$Bitmap"), resident(128, &bitmap)]);
+        image[2048 + 6 * 1024..2048 + 7 * 1024].copy_from_slice(&bitmap);
+        image.resize(68 * 512, 0);
+        image.extend_from_slice(b"secret");
+        image.resize(69 * 512, 0);
+        std::fs::write(&path, image).unwrap();
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        let deleted = mft.deleted().unwrap();
+        let found: Vec<(i64, Option<&str>, u64, bool)> = deleted
+            .iter()
+            .map(|x| (x.entry, x.path.as_deref(), x.size, x.recoverable))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (9, Some("Temp\\gone.txt"), 4, true),
+                (10, Some("kept.txt"), 6, true),
+                (11, Some("over.txt"), 6, false),
+                (12, None, 0, true),
+            ]
+        );
+        let mut data = String::new();
+        let entry = mft.open_entry(open_volume(&path).unwrap(), 10).unwrap();
+        entry
+            .into_data()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "secret");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_path_of() {
+        let program_files = [named(5, 1, "Program Files"), named(5, 2, "PROGRA~1")];
+        // The parent of entry 4 has since been reused, its sequence number is 2 and not 1
+        let mut reused = record(3, &[file_name(5, "Reused"), index_root(&[])]);
+        reused[16..18].copy_from_slice(&2u16.to_le_bytes());
+        let records = vec![
+            (3, record(3, &program_files)),
+            (4, record(1, &[file_name(9, "orphan.txt")])),
+            (9, reused),
+        ];
+        let root = [
+            (3, file_name_data(5, 1, "Program Files")),
+            (3, file_name_data(5, 2, "PROGRA~1")),
+            (9, file_name_data(5, 1, "Reused")),
+        ];
+        let path = volume("squirrel-test-path-of", &root, records);
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(
+            mft.path_of(7).unwrap(),
+            Some(String::from("Windows\\SYSTEM"))
+        );
+        assert_eq!(mft.path_of(3).unwrap(), Some(String::from("Program Files")));
+        assert_eq!(mft.entry_by_path("progra~1").unwrap(), Some(3));
+        assert_eq!(mft.path_of(4).unwrap(), None);
+        assert_eq!(mft.entry_by_path("Reused\\orphan.txt").unwrap(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut records = vec![
+            (8, record(1, &[file_name(6, "a.txt")])),
+            (9, record(0, &[file_name(6, "b.txt")])),
+            (10, record(1, &[])),
+            (
+                11,
+                record(3, &[named(5, 2, "DIR~1"), named(5, 1, "Directory")]),
+            ),
+        ];
+        // Records with garbage run lists, which are skipped
+        for (idx, flags, runs) in [(12, 0, [0x19, 1, 0]), (13, 1, [0x11, 1, 0xF0])].iter() {
+            let data = non_resident(128, runs, 4096);
+            records.push((*idx, record(*flags, &[file_name(6, "bad.txt"), data])));
+        }
+        let path = volume("squirrel-test-walk", &[], records);
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        let mut found = Vec::new();
+        mft.walk(|idx, entry, name, dir| {
+            if idx >= 6 {
+                found.push((idx, entry.in_use(), name.name.clone(), dir));
+            }
+            Ok(())
+        })
+        .unwrap();
+        let windows = Some(String::from("Windows"));
+        assert_eq!(
+            found,
+            vec![
+                (6, true, String::from("Windows"), Some(String::new())),
+                (7, true, String::from("SYSTEM"), windows.clone()),
+                (8, true, String::from("a.txt"), windows.clone()),
+                (9, false, String::from("b.txt"), windows),
+                (11, true, String::from("Directory"), Some(String::new())),
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
 
     fn hex_str<'a, T>(bs: T) -> String
     where
@@ -85,6 +922,47 @@ mod tests {
         .collect::<String>()
     }
 
+    fn from_hex(lines: &[&str]) -> Vec<u8> {
+        lines
+            .iter()
+            .flat_map(|x| x.split_whitespace())
+            .map(|x| u8::from_str_radix(x, 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_boot_4kn() {
+        let dump = from_hex(&[
+            "EB 52 90 4E 54 46 53 20 20 20 20 00 10 01 00 00",
+            "00 00 00 00 00 F8 00 00 3F 00 FF 00 00 08 00 00",
+            "00 00 00 00 80 00 80 00 FF 5F 38 3A 00 00 00 00",
+            "00 00 0C 00 00 00 00 00 02 00 00 00 00 00 00 00",
+            "F4 00 00 00 01 00 00 00 2F 9A 88 3C D2 88 3C 6E",
+        ]);
+        let boot = parse_boot(&mut Cursor::new(dump)).unwrap();
+        assert_eq!(boot.sector_size, 4096);
+        assert_eq!(boot.cluster_size, 4096);
+        assert_eq!(boot.mft_start, 0xC0000 * 4096);
+        assert_eq!(boot.record_size, 4096);
+    }
+
+    #[test]
+    fn test_parse_boot_128k_clusters() {
+        // 0xF8 sectors per cluster is 2^8 of them
+        let dump = from_hex(&[
+            "EB 52 90 4E 54 46 53 20 20 20 20 00 02 F8 00 00",
+            "00 00 00 00 00 F8 00 00 3F 00 FF 00 00 08 00 00",
+            "00 00 00 00 80 00 80 00 FF 87 E0 E8 00 00 00 00",
+            "00 03 00 00 00 00 00 00 01 00 00 00 00 00 00 00",
+            "F6 00 00 00 F4 00 00 00 5B 4D 4F 1E 86 4F 1E 2A",
+        ]);
+        let boot = parse_boot(&mut Cursor::new(dump)).unwrap();
+        assert_eq!(boot.sector_size, 512);
+        assert_eq!(boot.cluster_size, 128 * 1024);
+        assert_eq!(boot.mft_start, 0x300 * 128 * 1024);
+        assert_eq!(boot.record_size, 1024);
+    }
+
     #[test]
     fn test_parse_boot() {
         let mut buf: [u8; 1024] = [0; 1024];
@@ -98,9 +976,9 @@ mod tests {
 
     #[test]
     fn test_read_mft() {
-        let mut mft = MFT::open(r#"\\.\C:"#).unwrap();
-        let mut buf = [0u8; 1024];
-        for i in 0..mft.data.size() / 1024 {
+        let mut mft = MFT::open(r#"\\.\C:"#, false).unwrap();
+        let mut buf = vec![0u8; usize::try_from(mft.boot.record_size).unwrap()];
+        for i in 0..mft.data.size() / mft.boot.record_size {
             mft.data.read_exact(&mut buf).unwrap();
             assert!(
                 buf[0..4] == [0u8; 4][..] || buf[0..4] == b"FILE"[..],
@@ -113,7 +991,7 @@ mod tests {
     #[test]
     fn test_write_logfile() {
         let mut dest = File::create("LogFile").unwrap();
-        let mut mft = MFT::open(r#"\\.\C:"#).unwrap();
+        let mut mft = MFT::open(r#"\\.\C:"#, false).unwrap();
         let mut vol = open_volume(r#"\\.\C:"#).unwrap();
         let mut entry = mft.open_entry(&mut vol, 2).unwrap();
         let mut data = entry.data().unwrap();
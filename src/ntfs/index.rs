@@ -0,0 +1,142 @@
+use byteorder::{ReadBytesExt, LE};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use super::metadata::{fixup, parse_file_name, FileName, MFTEntry};
+
+const INDEX_ROOT: u32 = 0x90;
+const INDEX_ALLOCATION: u32 = 0xA0;
+const BITMAP: u32 = 0xB0;
+
+// The filename index of a directory.
+const I30: &str = "$I30";
+
+/// A file in a directory, the key of the index is a copy of its $FILE_NAME.
+#[derive(Debug, PartialEq)]
+pub struct IndexEntry {
+    pub entry: u64,
+    pub sequence: u16,
+    pub file_name: FileName,
+}
+
+/// Lists the entries of a directory, from the index root in the MFT entry and the index
+/// records in use. The entries aren't sorted by name across records. Torn index records
+/// are an error unless `ignore_torn` is set.
+pub fn read_index<T: Read + Seek>(
+    dir: &mut MFTEntry<T>,
+    ignore_torn: bool,
+) -> io::Result<Vec<IndexEntry>> {
+    let mut root = Vec::new();
+    dir.attr_reader(INDEX_ROOT, I30)
+        .ok_or_else(|| invalid("Not a directory"))?
+        .read_to_end(&mut root)?;
+    if root.len() < 16 {
+        return Err(invalid("Index root too short"));
+    }
+    let record_size = u64::from(Cursor::new(&root[8..12]).read_u32::<LE>()?);
+    let mut entries = parse_node(&root[16..])?;
+    let mut bitmap = Vec::new();
+    if let Some(mut reader) = dir.attr_reader(BITMAP, I30) {
+        reader.read_to_end(&mut bitmap)?;
+    }
+    if let Some(mut alloc) = dir.attr_reader(INDEX_ALLOCATION, I30) {
+        if record_size == 0 {
+            return Err(invalid("Invalid index record size"));
+        }
+        let mut buf = vec![0u8; usize::try_from(record_size).unwrap()];
+        for idx in 0..alloc.size() / record_size {
+            // Records that aren't in use only have stale entries
+            let byte = bitmap.get(usize::try_from(idx / 8).unwrap());
+            if byte.is_some_and(|x| x >> (idx % 8) & 1 == 0) {
+                continue;
+            }
+            alloc.seek(SeekFrom::Start(idx * record_size))?;
+            alloc.read_exact(&mut buf)?;
+            entries.extend(parse_index_record(&mut buf, ignore_torn)?);
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_index_record(buf: &mut [u8], ignore_torn: bool) -> io::Result<Vec<IndexEntry>> {
+    if &buf[0..4] != b"INDX" {
+        return Err(invalid("Invalid index record signature"));
+    }
+    let mut cur = Cursor::new(&buf[4..8]);
+    let fixup_offset = cur.read_u16::<LE>()?;
+    let fixup_entries = cur.read_u16::<LE>()?;
+    if !fixup(fixup_offset, fixup_entries, buf)? && !ignore_torn {
+        return Err(invalid("Torn index record, the fixups don't match"));
+    }
+    // The node header follows the record header
+    parse_node(&buf[24..])
+}
+
+// A node is a header with the offsets relative to it, followed by the entries. The last
+// entry only marks the end.
+fn parse_node(node: &[u8]) -> io::Result<Vec<IndexEntry>> {
+    let mut cur = Cursor::new(node);
+    let start = u64::from(cur.read_u32::<LE>()?);
+    let end = u64::from(cur.read_u32::<LE>()?).min(u64::try_from(node.len()).unwrap());
+    let mut entries = Vec::new();
+    let mut pos = start;
+    while pos + 16 <= end {
+        cur.seek(SeekFrom::Start(pos))?;
+        let reference = cur.read_u64::<LE>()?;
+        let length = cur.read_u16::<LE>()?;
+        let key_length = cur.read_u16::<LE>()?;
+        let flags = cur.read_u32::<LE>()?;
+        if flags & 2 == 2 {
+            break;
+        }
+        if length < 16 {
+            return Err(invalid("Invalid index entry length"));
+        }
+        let key_start = usize::try_from(pos + 16).unwrap();
+        let key_end = key_start + usize::from(key_length);
+        if key_end > node.len() {
+            return Err(invalid("Index entry beyond the node"));
+        }
+        entries.push(IndexEntry {
+            entry: reference & 0xFFFF_FFFF_FFFF,
+            sequence: u16::try_from(reference >> 48).unwrap(),
+            file_name: parse_file_name(&node[key_start..key_end])?,
+        });
+        pos = pos + u64::from(length);
+    }
+    Ok(entries)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::file_system::tests::{file_name_data, index_node};
+    use super::*;
+
+    #[test]
+    fn test_parse_index_record() {
+        // The name crosses the end of the first sector, which is fixed up
+        let node = index_node(400, &[(7, file_name_data(6, 1, "SYSTEM"))]);
+        let mut record = vec![0u8; 1024];
+        record[0..4].copy_from_slice(b"INDX");
+        record[4..6].copy_from_slice(&40u16.to_le_bytes());
+        record[6..8].copy_from_slice(&3u16.to_le_bytes());
+        record[24..24 + node.len()].copy_from_slice(&node);
+        for (sector, end) in [512, 1024].iter().enumerate() {
+            let orig = 42 + sector * 2;
+            let (head, tail) = record.split_at_mut(end - 2);
+            head[orig..orig + 2].copy_from_slice(&tail[0..2]);
+            tail[0..2].copy_from_slice(&[9, 0]);
+        }
+        record[40..42].copy_from_slice(&[9, 0]);
+        let entries = parse_index_record(&mut record, false).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entry, 7);
+        assert_eq!(entries[0].sequence, 1);
+        assert_eq!(entries[0].file_name.name, "SYSTEM");
+        assert!(parse_index_record(&mut [0u8; 1024], false).is_err());
+    }
+}
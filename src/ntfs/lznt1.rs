@@ -0,0 +1,105 @@
+use std::io;
+
+// Every chunk holds this much data once decompressed, except the last.
+const CHUNK_SIZE: usize = 4096;
+
+// Decompresses one compression unit of an NTFS compressed file. The output is zero
+// filled up to `size`, the length of the unit.
+pub fn decompress(input: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+    while pos + 2 <= input.len() && output.len() < size {
+        let header = u16::from_le_bytes([input[pos], input[pos + 1]]);
+        if header == 0 {
+            break;
+        }
+        let len = usize::from(header & 0x0FFF) + 1;
+        let start = pos + 2;
+        let end = start + len;
+        if end > input.len() {
+            return Err(invalid("Chunk beyond the end of the compressed data"));
+        }
+        // Chunks are aligned to CHUNK_SIZE in the output
+        let chunk_start = output.len().next_multiple_of(CHUNK_SIZE);
+        output.resize(chunk_start, 0);
+        if header & 0x8000 == 0 {
+            output.extend_from_slice(&input[start..end]);
+        } else {
+            decompress_chunk(&input[start..end], &mut output)?;
+        }
+        pos = end;
+    }
+    output.resize(size, 0);
+    Ok(output)
+}
+
+// Each flag byte says which of the next eight tokens are literal bytes (0) and which
+// are two byte back references (1).
+fn decompress_chunk(chunk: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+    let chunk_start = output.len();
+    let mut pos = 0;
+    while pos < chunk.len() {
+        let flags = chunk[pos];
+        pos = pos + 1;
+        for bit in 0..8 {
+            if pos >= chunk.len() {
+                break;
+            }
+            if flags >> bit & 1 == 0 {
+                output.push(chunk[pos]);
+                pos = pos + 1;
+                continue;
+            }
+            if pos + 2 > chunk.len() {
+                return Err(invalid("Truncated back reference"));
+            }
+            let token = usize::from(u16::from_le_bytes([chunk[pos], chunk[pos + 1]]));
+            pos = pos + 2;
+            // The further into the chunk, the more bits go to the offset
+            let written = output.len() - chunk_start;
+            if written == 0 {
+                return Err(invalid("Back reference at the start of a chunk"));
+            }
+            let mut shift = 12;
+            let mut x = written - 1;
+            while x >= 0x10 {
+                shift = shift - 1;
+                x = x >> 1;
+            }
+            let offset = (token >> shift) + 1;
+            let len = (token & ((1 << shift) - 1)) + 3;
+            if offset > written {
+                return Err(invalid("Back reference before the start of the chunk"));
+            }
+            // The copy can overlap what it writes
+            for _ in 0..len {
+                let byte = output[output.len() - offset];
+                output.push(byte);
+            }
+        }
+    }
+    if output.len() - chunk_start > CHUNK_SIZE {
+        return Err(invalid("Chunk decompressed to more than 4096 bytes"));
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        // Literals abc and then a back reference of 3 bytes back and 9 long
+        let compressed = [0x05, 0xB0, 0x08, b'a', b'b', b'c', 0x06, 0x20, 0x00, 0x00];
+        assert_eq!(decompress(&compressed, 14).unwrap(), b"abcabcabcabc\0\0");
+        // An uncompressed chunk is copied as is
+        let stored = [0x02, 0x30, b'x', b'y', b'z'];
+        assert_eq!(decompress(&stored, 3).unwrap(), b"xyz");
+        assert!(decompress(&[0x02, 0xB0, 0x01, 0x06, 0x20], 16).is_err());
+    }
+}
@@ -4,28 +4,250 @@ use std::io::{self, Cursor, Read, Seek, SeekFrom};
 
 use super::content::{load_runs, Content, ContentReader, DataRun};
 
+const ATTRIBUTE_LIST: u32 = 0x20;
+
+/// A record of the MFT with its attributes. The data of non-resident attributes is
+/// read from `volume`.
 #[derive(Debug)]
 pub struct MFTEntry<T> {
     volume: T,
     header: MFTHeader,
     attrs: Vec<MFTAttr>,
+    torn: bool,
 }
 
 impl<T: Read + Seek> MFTEntry<T> {
+    /// The content of the file, its unnamed $DATA attribute.
     pub fn data(&mut self) -> Option<ContentReader<&mut T>> {
-        entry_data(&self.attrs, &mut self.volume)
+        entry_data(&self.attrs, "", &mut self.volume)
     }
+    /// Like data, keeping the volume.
     pub fn into_data(self) -> Option<ContentReader<T>> {
-        entry_data(&self.attrs, self.volume)
+        entry_data(&self.attrs, "", self.volume)
+    }
+    /// An alternate data stream, like Zone.Identifier.
+    pub fn stream(&mut self, name: &str) -> Option<ContentReader<&mut T>> {
+        entry_data(&self.attrs, name, &mut self.volume)
+    }
+    /// Like stream, keeping the volume.
+    pub fn into_stream(self, name: &str) -> Option<ContentReader<T>> {
+        entry_data(&self.attrs, name, self.volume)
+    }
+    /// The content of the attribute with this type and name, like $I30 for the index of
+    /// a directory.
+    pub fn attr_reader(&mut self, attr_type: u32, name: &str) -> Option<ContentReader<&mut T>> {
+        let attr = self
+            .attrs
+            .iter()
+            .find(|x| x.attr_type == attr_type && x.name == name)?;
+        Some(attr.content.reader(&mut self.volume))
     }
 }
 
+impl<T> MFTEntry<T> {
+    /// Some sectors of the record weren't written with the rest of it, the fixups of
+    /// those were put back anyway.
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+    /// False for deleted files, whose records can be reused any time.
+    pub fn in_use(&self) -> bool {
+        self.header.flags & 1 == 1
+    }
+    /// Directories have an $I30 index instead of data, see MFT::read_dir.
+    pub fn is_dir(&self) -> bool {
+        self.header.flags & 2 == 2
+    }
+    /// Goes up every time the record is reused, references to the entry include it.
+    pub fn sequence(&self) -> u16 {
+        self.header.sequence
+    }
+    /// The alternate data streams, the named $DATA attributes, with a reader for each on a
+    /// volume of its own.
+    pub fn streams<V, F>(&self, mut open: F) -> io::Result<Vec<(String, ContentReader<V>)>>
+    where
+        V: Read + Seek,
+        F: FnMut() -> io::Result<V>,
+    {
+        let mut streams = Vec::new();
+        for attr in &self.attrs {
+            if attr.attr_type == 128 && !attr.name.is_empty() {
+                streams.push((attr.name.clone(), attr.content.reader(open()?)));
+            }
+        }
+        Ok(streams)
+    }
+    /// The size of the unnamed $DATA attribute and the runs it's stored in, there are none
+    /// when it's resident.
+    pub fn data_runs(&self) -> Option<(u64, &[DataRun])> {
+        self.stream_runs("")
+    }
+    /// Like data_runs, for an alternate data stream.
+    pub fn stream_runs(&self, name: &str) -> Option<(u64, &[DataRun])> {
+        let attr = self
+            .attrs
+            .iter()
+            .find(|x| x.attr_type == 128 && x.name == name)?;
+        match &attr.content {
+            Content::Resident { data } => Some((u64::try_from(data.len()).unwrap(), &[])),
+            Content::NonResident { size, runs, .. } => Some((*size, runs)),
+        }
+    }
+    /// The resident $STANDARD_INFORMATION, which every entry in use has.
+    pub fn standard_information(&self) -> Option<StandardInformation> {
+        self.attrs
+            .iter()
+            .filter(|x| x.attr_type == 16)
+            .find_map(|x| match &x.content {
+                Content::Resident { data } => parse_standard_information(data).ok(),
+                Content::NonResident { .. } => None,
+            })
+    }
+    /// The attributes didn't all fit in this record, the others are in the records
+    /// listed by extension_records.
+    pub fn has_attribute_list(&self) -> bool {
+        self.attrs.iter().any(|x| x.attr_type == ATTRIBUTE_LIST)
+    }
+    /// The records with the attributes that didn't fit in this one, from its
+    /// $ATTRIBUTE_LIST. The list can include this record as well.
+    pub fn extension_records<V: Read + Seek>(&self, volume: V) -> io::Result<Vec<u64>> {
+        let mut data = Vec::new();
+        if let Some(attr) = self.attrs.iter().find(|x| x.attr_type == ATTRIBUTE_LIST) {
+            attr.content.reader(volume).read_to_end(&mut data)?;
+        }
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos + 26 <= data.len() {
+            let length = usize::from(u16::from_le_bytes([data[pos + 4], data[pos + 5]]));
+            if length < 26 {
+                return Err(invalid("Invalid attribute list entry length"));
+            }
+            let reference = u64::from_le_bytes(data[pos + 16..pos + 24].try_into().unwrap());
+            let record = reference & 0xFFFF_FFFF_FFFF;
+            if !records.contains(&record) {
+                records.push(record);
+            }
+            pos = pos + length;
+        }
+        Ok(records)
+    }
+    /// Adds the attributes in the extension records of this entry, the extents of a
+    /// non-resident attribute that's split over records are joined.
+    pub fn add_extensions<U>(&mut self, extensions: Vec<MFTEntry<U>>) {
+        let mut attrs = std::mem::take(&mut self.attrs);
+        for extension in extensions {
+            attrs.extend(extension.attrs);
+        }
+        self.attrs = join_extents(attrs);
+    }
+    /// Every $FILE_NAME, the DOS names included.
+    pub fn file_names(&self) -> Vec<FileName> {
+        self.attrs
+            .iter()
+            .filter(|x| x.attr_type == 48)
+            .filter_map(|x| match &x.content {
+                Content::Resident { data } => parse_file_name(data).ok(),
+                Content::NonResident { .. } => None,
+            })
+            .collect()
+    }
+}
+
+/// FILETIMEs, in 100 nanoseconds since 1601. Changed is when the MFT entry changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timestamps {
+    pub created: u64,
+    pub modified: u64,
+    pub changed: u64,
+    pub accessed: u64,
+}
+
+fn read_timestamps<T: Read>(cur: &mut T) -> io::Result<Timestamps> {
+    Ok(Timestamps {
+        created: cur.read_u64::<LE>()?,
+        modified: cur.read_u64::<LE>()?,
+        changed: cur.read_u64::<LE>()?,
+        accessed: cur.read_u64::<LE>()?,
+    })
+}
+
+/// The times shown by Explorer and set through the API, so the ones timestomping tools
+/// change. Flags are the file attributes, like hidden and system.
+#[derive(Debug, PartialEq)]
+pub struct StandardInformation {
+    pub times: Timestamps,
+    pub flags: u32,
+}
+
+pub fn parse_standard_information(data: &[u8]) -> io::Result<StandardInformation> {
+    let mut cur = Cursor::new(data);
+    let times = read_timestamps(&mut cur)?;
+    let flags = cur.read_u32::<LE>()?;
+    Ok(StandardInformation { times, flags })
+}
+
+/// A name of the entry in its parent directory, there's one per hard link and usually
+/// an extra one for the DOS name. The times, sizes and flags are only updated when the
+/// name changes, the ones in the entry itself are current.
+#[derive(Debug, PartialEq)]
+pub struct FileName {
+    pub parent: u64,
+    pub parent_sequence: u16,
+    pub times: Timestamps,
+    pub alloc_size: u64,
+    pub size: u64,
+    pub flags: u32,
+    pub namespace: u8,
+    pub name: String,
+}
+
+impl FileName {
+    /// The 8.3 name when the long name doesn't fit, which has its own FileName.
+    pub fn is_dos(&self) -> bool {
+        self.namespace == 2
+    }
+}
+
+pub fn parse_file_name(data: &[u8]) -> io::Result<FileName> {
+    let mut cur = Cursor::new(data);
+    // The upper two bytes of the reference are the sequence number
+    let reference = cur.read_u64::<LE>()?;
+    let times = read_timestamps(&mut cur)?;
+    let alloc_size = cur.read_u64::<LE>()?;
+    let size = cur.read_u64::<LE>()?;
+    let flags = cur.read_u32::<LE>()?;
+    cur.seek(SeekFrom::Current(4))?;
+    let name_length = cur.read_u8()?;
+    let namespace = cur.read_u8()?;
+    let name = read_name(&mut cur, name_length)?;
+    Ok(FileName {
+        parent: reference & 0xFFFF_FFFF_FFFF,
+        parent_sequence: u16::try_from(reference >> 48).unwrap(),
+        times,
+        alloc_size,
+        size,
+        flags,
+        namespace,
+        name,
+    })
+}
+
+// Names are UTF-16 and their length is in characters.
+fn read_name<T: Read>(cur: &mut T, length: u8) -> io::Result<String> {
+    let mut name = Vec::with_capacity(length.into());
+    for _ in 0..length {
+        name.push(cur.read_u16::<LE>()?);
+    }
+    Ok(String::from_utf16_lossy(&name))
+}
+
 fn entry_data<T: Read + Seek>(
     attrs: &Vec<MFTAttr>,
+    name: &str,
     volume: T,
 ) -> Option<ContentReader<T>> {
     for attr in attrs {
-        if attr.attr_type == 128 {
+        if attr.attr_type == 128 && attr.name == name {
             return Some(attr.content.reader(volume));
         }
     }
@@ -36,78 +258,125 @@ fn entry_data<T: Read + Seek>(
 pub struct MFTHeader {
     fixup_offset: u16,
     fixup_entries: u16,
+    sequence: u16,
     attr_offset: u16,
     flags: u16,
-    used_size: u32,
-    alloc_size: u32,
 }
 
 #[derive(Debug)]
 pub struct MFTAttr {
     attr_type: u32,
     length: u32,
-    name_length: u8,
-    name_offset: u16,
-    flags: u16,
-    attr_id: u16,
+    name: String,
     content: Content,
 }
 
 pub fn parse_mft_entry<T, U: Read>(
-    sector_size: u16,
-    cluster_size: u16,
+    cluster_size: u32,
+    record_size: u64,
     volume: T,
     mut mft_reader: U,
 ) -> io::Result<MFTEntry<T>> {
-    let mut buf = [0u8; 1024];
+    let mut buf = vec![0u8; usize::try_from(record_size).unwrap()];
     mft_reader.read_exact(&mut buf)?;
-    let mut cur = Cursor::new(buf);
-    let header = parse_mft_header(&mut cur)?;
-    fixup_buf(sector_size, &header, &mut buf);
+    let header = parse_mft_header(&mut Cursor::new(&buf))?;
+    let torn = !fixup_buf(&header, &mut buf)?;
+    let mut cur = Cursor::new(&buf);
     cur.seek(SeekFrom::Start(header.attr_offset.into()))?;
     let attrs = parse_mft_attrs(&mut cur, cluster_size)?;
     Ok(MFTEntry {
         header,
         attrs,
         volume,
+        torn,
     })
 }
 
 fn parse_mft_header<T: Read + Seek>(vol: &mut T) -> io::Result<MFTHeader> {
     let mut sig_buf = [0u8; 4];
     vol.read_exact(&mut sig_buf)?;
-    assert_eq!(&sig_buf, b"FILE");
+    if &sig_buf != b"FILE" {
+        return Err(invalid("Invalid MFT entry signature"));
+    }
     let fixup_offset = vol.read_u16::<LE>()?;
     let fixup_entries = vol.read_u16::<LE>()?;
-    vol.seek(SeekFrom::Current(12))?;
+    vol.seek(SeekFrom::Current(8))?;
+    let sequence = vol.read_u16::<LE>()?;
+    vol.seek(SeekFrom::Current(2))?;
     let attr_offset = vol.read_u16::<LE>()?;
     let flags = vol.read_u16::<LE>()?;
-    let used_size = vol.read_u32::<LE>()?;
-    let alloc_size = vol.read_u32::<LE>()?;
     Ok(MFTHeader {
         fixup_offset,
         fixup_entries,
+        sequence,
         attr_offset,
         flags,
-        used_size,
-        alloc_size,
     })
 }
 
-fn fixup_buf(sector_size: u16, mft_header: &MFTHeader, buf: &mut [u8]) {
-    let offset: usize = mft_header.fixup_offset.into();
+fn fixup_buf(mft_header: &MFTHeader, buf: &mut [u8]) -> io::Result<bool> {
+    fixup(mft_header.fixup_offset, mft_header.fixup_entries, buf)
+}
+
+// Update sequence arrays cover 512 bytes each, whatever the sector size of the volume.
+const FIXUP_STRIDE: usize = 512;
+
+/// Puts back the last two bytes of every 512 byte block, which were replaced with the
+/// update sequence number when the record was written. Index records have them too.
+/// Returns false when a block doesn't end with the update sequence number, after a torn
+/// write.
+pub fn fixup(fixup_offset: u16, fixup_entries: u16, buf: &mut [u8]) -> io::Result<bool> {
+    let offset: usize = fixup_offset.into();
+    let entries = usize::from(fixup_entries);
+    if entries == 0 || offset + entries * 2 > buf.len() || (entries - 1) * FIXUP_STRIDE > buf.len()
+    {
+        return Err(invalid("Invalid fixup array"));
+    }
     let sig: [u8; 2] = buf[offset..offset + 2].try_into().unwrap();
-    for entry in 1..usize::from(mft_header.fixup_entries) {
-        let orig_offset = offset + (entry - 1) * 2;
+    let mut intact = true;
+    for entry in 1..entries {
+        let orig_offset = offset + entry * 2;
         let orig: [u8; 2] = buf[orig_offset..orig_offset + 2].try_into().unwrap();
-        let sector_end = entry * usize::from(sector_size);
+        let sector_end = entry * FIXUP_STRIDE;
         let check: &mut [u8] = &mut buf[sector_end - 2..sector_end];
-        assert_eq!(&sig, check);
+        intact = intact && check == sig;
         check.copy_from_slice(&orig);
     }
+    Ok(intact)
+}
+
+// The first extent of a non-resident attribute has its size and the runs up to its last
+// VCN, the others have the runs from their first VCN on.
+fn join_extents(mut attrs: Vec<MFTAttr>) -> Vec<MFTAttr> {
+    attrs.sort_by_key(|x| match x.content {
+        Content::NonResident { run_start_vcn, .. } => run_start_vcn,
+        Content::Resident { .. } => 0,
+    });
+    let mut joined: Vec<MFTAttr> = Vec::new();
+    for attr in attrs {
+        let first = joined
+            .iter_mut()
+            .find(|x| x.attr_type == attr.attr_type && x.name == attr.name);
+        match (first, &attr.content) {
+            (
+                Some(first),
+                Content::NonResident {
+                    run_start_vcn,
+                    runs,
+                    ..
+                },
+            ) if *run_start_vcn > 0 => first.content.extend(runs),
+            _ => joined.push(attr),
+        }
+    }
+    joined
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
 }
 
-fn parse_mft_attr<T: Read + Seek>(cur: &mut T, cluster_size: u16) -> io::Result<MFTAttr> {
+fn parse_mft_attr<T: Read + Seek>(cur: &mut T, cluster_size: u32) -> io::Result<MFTAttr> {
     let start_pos = cur.stream_position()?;
     let attr_type = cur.read_u32::<LE>()?;
     let length = cur.read_u32::<LE>()?;
@@ -115,7 +384,8 @@ fn parse_mft_attr<T: Read + Seek>(cur: &mut T, cluster_size: u16) -> io::Result<
     let name_length = cur.read_u8()?;
     let name_offset = cur.read_u16::<LE>()?;
     let flags = cur.read_u16::<LE>()?;
-    let attr_id = cur.read_u16::<LE>()?;
+    // Skip the attribute ID
+    cur.seek(SeekFrom::Current(2))?;
     let content = if non_resident == 0 {
         let size = cur.read_u32::<LE>()?;
         let offset = cur.read_u16::<LE>()?;
@@ -126,37 +396,48 @@ fn parse_mft_attr<T: Read + Seek>(cur: &mut T, cluster_size: u16) -> io::Result<
         Content::Resident { data: data.into() }
     } else {
         let run_start_vcn = cur.read_u64::<LE>()?;
-        let run_end_vcn = cur.read_u64::<LE>()?;
+        // Skip the last VCN, the extent ends with its runs
+        cur.seek(SeekFrom::Current(8))?;
         let run_offset = cur.read_u16::<LE>()?;
-        cur.seek(SeekFrom::Current(6))?;
-        let alloc_size = cur.read_u64::<LE>()?;
+        // As a power of two of the cluster size, usually 16 clusters
+        let unit_shift = cur.read_u16::<LE>()?;
+        // Skip the padding and the allocated size
+        cur.seek(SeekFrom::Current(12))?;
         let size = cur.read_u64::<LE>()?;
         cur.seek(SeekFrom::Start(start_pos + u64::from(run_offset)))?;
-        let runs = parse_run_list(cur, cluster_size, size)?.into();
+        // Only the first extent has the size, the slack is cut off once they're joined
+        let limit = if run_start_vcn > 0 { u64::MAX } else { size };
+        let runs = parse_run_list(cur, cluster_size, limit)?.into();
+        let compression_unit = if flags & 0xFF != 0 && unit_shift > 0 {
+            u64::from(cluster_size) << unit_shift
+        } else {
+            0
+        };
         Content::NonResident {
             run_start_vcn,
-            run_end_vcn,
-            alloc_size,
             size,
             runs,
+            compression_unit,
         }
     };
+    cur.seek(SeekFrom::Start(start_pos + u64::from(name_offset)))?;
+    let name = read_name(cur, name_length)?;
     Ok(MFTAttr {
         attr_type,
         length,
-        name_length,
-        name_offset,
-        flags,
-        attr_id,
+        name,
         content,
     })
 }
 
-fn parse_mft_attrs<T: Read + Seek>(cur: &mut T, cluster_size: u16) -> io::Result<Vec<MFTAttr>> {
+fn parse_mft_attrs<T: Read + Seek>(cur: &mut T, cluster_size: u32) -> io::Result<Vec<MFTAttr>> {
     let mut attrs: Vec<MFTAttr> = Vec::new();
     while cur.read_u16::<LE>()? != u16::MAX {
         let pos = cur.seek(SeekFrom::Current(-2))?;
         let attr = parse_mft_attr(cur, cluster_size)?;
+        if attr.length == 0 {
+            return Err(invalid("Invalid attribute length"));
+        }
         cur.seek(SeekFrom::Start(pos + u64::from(attr.length)))?;
         attrs.push(attr);
     }
@@ -164,7 +445,9 @@ fn parse_mft_attrs<T: Read + Seek>(cur: &mut T, cluster_size: u16) -> io::Result
 }
 
 fn read_int_bytes<T: Read>(num_bytes: u8, cur: &mut T, signed: bool) -> io::Result<[u8; 8]> {
-    assert!(num_bytes <= 8);
+    if num_bytes > 8 {
+        return Err(invalid("Invalid run header"));
+    }
     let mut bytes = Vec::with_capacity(8);
     for _ in 0..num_bytes {
         bytes.push(cur.read_u8()?);
@@ -188,22 +471,35 @@ fn read_int_bytes<T: Read>(num_bytes: u8, cur: &mut T, signed: bool) -> io::Resu
 
 fn parse_run_list<T: Read + Seek>(
     cur: &mut T,
-    cluster_size: u16,
+    cluster_size: u32,
     size: u64,
 ) -> io::Result<Vec<DataRun>> {
     let mut runs = Vec::new();
-    let mut offset = 0;
+    let mut offset = 0i64;
+    let mut total = 0u64;
+    // Deleted records can be partly overwritten, so anything out of range is an error
+    let bad_run = || invalid("Invalid data run");
     let mut first_byte = cur.read_u8()?;
     while first_byte > 0 {
         let length_length = u8::MAX.wrapping_shr(4) & first_byte;
         let offset_length = first_byte.wrapping_shr(4);
         let length = u64::from_le_bytes(read_int_bytes(length_length, cur, false)?);
-        let rel_offset = i64::from_le_bytes(read_int_bytes(offset_length, cur, true)?);
-        offset = offset + rel_offset;
-        runs.push((
-            u64::try_from(offset).unwrap() * u64::from(cluster_size),
-            length * u64::from(cluster_size),
-        ));
+        let length = length
+            .checked_mul(u64::from(cluster_size))
+            .ok_or_else(bad_run)?;
+        total = total.checked_add(length).ok_or_else(bad_run)?;
+        if offset_length == 0 {
+            // Sparse runs have no offset, they're holes that read as zeros
+            runs.push((None, length));
+        } else {
+            let rel_offset = i64::from_le_bytes(read_int_bytes(offset_length, cur, true)?);
+            offset = offset.checked_add(rel_offset).ok_or_else(bad_run)?;
+            let start = u64::try_from(offset)
+                .ok()
+                .and_then(|x| x.checked_mul(u64::from(cluster_size)))
+                .ok_or_else(bad_run)?;
+            runs.push((Some(start), length));
+        }
         first_byte = cur.read_u8()?;
     }
     Ok(load_runs(runs, size))
@@ -211,28 +507,43 @@ fn parse_run_list<T: Read + Seek>(
 
 #[cfg(test)]
 mod tests {
-    use std::panic::catch_unwind;
-
+    use super::super::file_system::tests::{
+        file_name, non_resident, record, resident, resident_named,
+    };
     use super::super::file_system::MFT;
     use super::*;
 
     #[test]
     fn test_fixup() {
         let mut buf: [u8; 1024] = [0; 1024];
-        let mut mft = MFT::open(r#"\\.\C:"#).unwrap();
+        let mut mft = MFT::open(r#"\\.\C:"#, false).unwrap();
         mft.data.read_exact(&mut buf).unwrap();
         let header = parse_mft_header(&mut mft.data).unwrap();
-        fixup_buf(mft.boot.sector_size, &header, &mut buf);
-        let fail = catch_unwind(move || fixup_buf(mft.boot.sector_size, &header, &mut buf));
-        assert!(fail.is_err())
+        assert!(fixup_buf(&header, &mut buf).unwrap());
+        assert!(!fixup_buf(&header, &mut buf).unwrap());
+    }
+
+    #[test]
+    fn test_torn_record() {
+        let mut rec = record(1, &[file_name(5, "torn.txt"), resident(128, b"data")]);
+        // The second sector is from an older write
+        rec[1022..1024].copy_from_slice(&[0, 0]);
+        let entry = parse_mft_entry(512, 1024, (), Cursor::new(&rec)).unwrap();
+        assert!(entry.is_torn());
+        assert_eq!(entry.file_names()[0].name, "torn.txt");
+        // More fixups than sectors
+        let mut header = rec.clone();
+        header[6..8].copy_from_slice(&9u16.to_le_bytes());
+        assert!(parse_mft_entry(512, 1024, (), Cursor::new(header)).is_err());
+        assert!(parse_mft_entry(512, 1024, (), Cursor::new([0u8; 1024])).is_err());
     }
 
     #[test]
     fn test_parse_attrs() {
-        let mut mft = MFT::open(r#"\\.\C:"#).unwrap();
+        let mut mft = MFT::open(r#"\\.\C:"#, false).unwrap();
         let entry = parse_mft_entry(
-            mft.boot.sector_size,
             mft.boot.cluster_size,
+            mft.boot.record_size,
             r#"\\.\C:"#,
             &mut mft.data,
         )
@@ -248,6 +559,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_file_name() {
+        let mut data = file_name(5, "Windows");
+        data[24 + 16..24 + 24].copy_from_slice(&132_000_000_000_000_000u64.to_le_bytes());
+        let name = parse_file_name(&data[24..]).unwrap();
+        assert_eq!(
+            name,
+            FileName {
+                parent: 5,
+                parent_sequence: 1,
+                times: Timestamps {
+                    modified: 132_000_000_000_000_000,
+                    ..Timestamps::default()
+                },
+                alloc_size: 0,
+                size: 0,
+                flags: 0,
+                namespace: 1,
+                name: String::from("Windows")
+            }
+        );
+        assert!(!name.is_dos());
+    }
+
+    #[test]
+    fn test_streams() {
+        let zone = b"[ZoneTransfer]\r\nZoneId=3\r\n";
+        let attrs = [
+            file_name(5, "setup.exe"),
+            resident(128, b"MZ"),
+            resident_named(128, "Zone.Identifier", zone),
+        ];
+        let rec = record(1, &attrs);
+        let volume = Cursor::new(Vec::new());
+        let mut entry = parse_mft_entry(512, 1024, volume, Cursor::new(rec)).unwrap();
+        let streams = entry.streams(|| Ok(Cursor::new(Vec::new()))).unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].0, "Zone.Identifier");
+        assert_eq!(streams[0].1.size(), 26);
+        let mut data = Vec::new();
+        entry.data().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"MZ");
+        assert!(entry.stream("other").is_none());
+        let mut data = Vec::new();
+        let mut stream = entry.into_stream("Zone.Identifier").unwrap();
+        stream.read_to_end(&mut data).unwrap();
+        assert_eq!(data, zone);
+    }
+
+    #[test]
+    fn test_standard_information() {
+        let mut si = vec![0u8; 72];
+        for (i, time) in [4u64, 3, 2, 1].iter().enumerate() {
+            si[i * 8..i * 8 + 8].copy_from_slice(&time.to_le_bytes());
+        }
+        si[32..36].copy_from_slice(&0x26u32.to_le_bytes());
+        let rec = record(1, &[resident(16, &si), file_name(5, "pagefile.sys")]);
+        let entry = parse_mft_entry(512, 1024, (), Cursor::new(rec)).unwrap();
+        let times = Timestamps {
+            created: 4,
+            modified: 3,
+            changed: 2,
+            accessed: 1,
+        };
+        let expected = StandardInformation { times, flags: 0x26 };
+        assert_eq!(entry.standard_information(), Some(expected));
+        assert_eq!(entry.file_names()[0].times, Timestamps::default());
+    }
+
     #[test]
     fn test_parse_run_list() {
         let data: [u8; 8] = [0x21, 0x10, 0x00, 0x01, 0x11, 0x20, 0xE0, 0x00]; // 16/256 32/-32
@@ -256,15 +636,39 @@ mod tests {
         let valid = vec![
             DataRun {
                 len: 16,
-                offset: 256,
+                offset: Some(256),
                 virt_offset: 0,
             },
             DataRun {
                 len: 32,
-                offset: 256 - 32,
+                offset: Some(256 - 32),
                 virt_offset: 16,
             },
         ];
         assert_eq!(run_lists, valid);
     }
+
+    #[test]
+    fn test_parse_sparse_run_list() {
+        // 16/256, a hole of 32 and then 8/-32, relative to the run before the hole
+        let data = [0x21, 0x10, 0x00, 0x01, 0x01, 0x20, 0x11, 0x08, 0xE0, 0x00];
+        let runs = parse_run_list(&mut Cursor::new(data), 1, 56).unwrap();
+        let offsets: Vec<Option<u64>> = runs.iter().map(|x| x.offset).collect();
+        assert_eq!(offsets, vec![Some(256), None, Some(224)]);
+        assert_eq!(runs[2].virt_offset, 48);
+    }
+
+    #[test]
+    fn test_parse_bad_run_list() {
+        // Deleted records with garbage in them: a length of 9 bytes and a run that
+        // starts before the volume
+        let bad: [&[u8]; 2] = [&[0x19, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0], &[0x11, 1, 0xF0, 0]];
+        for runs in bad.iter() {
+            let err = parse_run_list(&mut Cursor::new(runs), 512, 512).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let attrs = [file_name(5, "gone.txt"), non_resident(128, runs, 4096)];
+            let rec = record(0, &attrs);
+            assert!(parse_mft_entry(512, 512, (), Cursor::new(&rec)).is_err());
+        }
+    }
 }
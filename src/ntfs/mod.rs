@@ -1,6 +1,18 @@
-pub use self::file_system::MFT;
-pub use self::content::open_volume;
+//! The raw NTFS reader, for files that are locked even in a snapshot ($MFT, $LogFile)
+//! and for reading from a volume without going through the file system.
+
+pub use self::file_system::{Boot, Deleted, MFT};
+pub use self::index::IndexEntry;
+pub use self::metadata::{fixup, FileName, MFTEntry, StandardInformation, Timestamps};
+pub use self::content::{
+    open_volume, set_buffer_size, ContentReader, DataRun, RunReader, Volume,
+    DEFAULT_BUFFER_SIZE,
+};
+#[cfg(test)]
+pub use self::file_system::tests::{file_name_data, record, resident, usnjrnl_volume, volume};
 
 mod file_system;
 mod content;
+mod index;
+mod lznt1;
 mod metadata;
@@ -0,0 +1,116 @@
+use byteorder::{ByteOrder, LE};
+use std::convert::TryFrom;
+use std::io;
+
+use super::invalid;
+
+const SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+const STREAM: u8 = 2;
+const ROOT: u8 = 5;
+
+// Minimal OLE compound file reader, returns the streams by name.
+pub fn streams(data: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    if !data.starts_with(&SIGNATURE) || data.len() < 512 {
+        return Err(invalid("No compound file signature"));
+    }
+    // Version 3 files have 512 byte sectors and version 4 files 4096 byte ones
+    let sector_size = match LE::read_u16(&data[0x1E..0x20]) {
+        shift @ (9 | 12) => 1usize << shift,
+        _ => return Err(invalid("Invalid sector shift")),
+    };
+    let mini_sector_size = match LE::read_u16(&data[0x20..0x22]) {
+        6 => 64,
+        _ => return Err(invalid("Invalid mini sector shift")),
+    };
+    let cutoff = u64::from(LE::read_u32(&data[0x38..0x3C]));
+    let sector = |n: u32| {
+        let start = (usize::try_from(n).unwrap() + 1) * sector_size;
+        data.get(start..start + sector_size)
+            .ok_or_else(|| invalid("Sector out of range"))
+    };
+
+    let mut fat_sectors: Vec<u32> = data[0x4C..0x200]
+        .chunks_exact(4)
+        .map(LE::read_u32)
+        .collect();
+    let mut difat = LE::read_u32(&data[0x44..0x48]);
+    let mut difat_count = LE::read_u32(&data[0x48..0x4C]);
+    while difat != END_OF_CHAIN && difat_count > 0 {
+        let entries: Vec<u32> = sector(difat)?.chunks_exact(4).map(LE::read_u32).collect();
+        let (next, entries) = entries.split_last().unwrap();
+        fat_sectors.extend_from_slice(entries);
+        difat = *next;
+        difat_count = difat_count - 1;
+    }
+    let fat_count = usize::try_from(LE::read_u32(&data[0x2C..0x30])).unwrap();
+    let mut fat = Vec::new();
+    for n in fat_sectors.into_iter().take(fat_count) {
+        fat.extend(sector(n)?.chunks_exact(4).map(LE::read_u32));
+    }
+
+    let chain = |start: u32, table: &[u32]| {
+        let mut chain = Vec::new();
+        let mut next = start;
+        while next != END_OF_CHAIN {
+            if chain.len() > table.len() {
+                return Err(invalid("Sector chain loop"));
+            }
+            chain.push(next);
+            next = *table
+                .get(usize::try_from(next).unwrap())
+                .ok_or_else(|| invalid("Sector chain out of range"))?;
+        }
+        Ok(chain)
+    };
+    let read = |start: u32| -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for n in chain(start, &fat)? {
+            buf.extend_from_slice(sector(n)?);
+        }
+        Ok(buf)
+    };
+
+    let directory = read(LE::read_u32(&data[0x30..0x34]))?;
+    let mini_fat: Vec<u32> = match LE::read_u32(&data[0x3C..0x40]) {
+        END_OF_CHAIN => Vec::new(),
+        start => read(start)?.chunks_exact(4).map(LE::read_u32).collect(),
+    };
+    let mut mini_stream = Vec::new();
+    let mut streams = Vec::new();
+    for entry in directory.chunks_exact(128) {
+        let name_len = usize::from(LE::read_u16(&entry[0x40..0x42])).min(64);
+        let chars: Vec<u16> = entry[..name_len]
+            .chunks_exact(2)
+            .map(LE::read_u16)
+            .collect();
+        let name = String::from_utf16_lossy(&chars)
+            .trim_end_matches('\0')
+            .to_string();
+        let start = LE::read_u32(&entry[0x74..0x78]);
+        // Only the low 32 bits of the size are valid in version 3 files
+        let size = u64::from(LE::read_u32(&entry[0x78..0x7C]));
+        match entry[0x42] {
+            ROOT => mini_stream = read(start)?,
+            STREAM if size < cutoff => {
+                let mut buf = Vec::new();
+                for n in chain(start, &mini_fat)? {
+                    let start = usize::try_from(n).unwrap() * mini_sector_size;
+                    let mini_sector = mini_stream
+                        .get(start..start + mini_sector_size)
+                        .ok_or_else(|| invalid("Mini sector out of range"))?;
+                    buf.extend_from_slice(mini_sector);
+                }
+                buf.truncate(usize::try_from(size).unwrap());
+                streams.push((name, buf));
+            }
+            STREAM => {
+                let mut buf = read(start)?;
+                buf.truncate(usize::try_from(size).unwrap());
+                streams.push((name, buf));
+            }
+            _ => (),
+        }
+    }
+    Ok(streams)
+}
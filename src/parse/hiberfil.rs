@@ -0,0 +1,138 @@
+use byteorder::{ByteOrder, LE};
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use super::invalid;
+use super::xpress::decompress_plain;
+
+const BLOCK_SIGNATURE: &[u8] = b"\x81\x81xpress";
+const HEADER_SIZE: usize = 0x20;
+const PAGE_SIZE: usize = 4096;
+const READ_SIZE: usize = 1024 * 1024;
+
+// Extracts the memory pages stored in the Xpress blocks of a Windows XP to 7
+// hibernation file, in the order they're stored. That's not a memory image: the page
+// tables that give the physical address of each page aren't read, so strings and
+// carving work on the output but tools that walk kernel structures don't. Windows 8
+// and later use a different layout.
+pub fn convert(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<JsonValue> {
+    let mut buf = Vec::new();
+    let mut eof = false;
+    let mut written = 0;
+    let mut start = 0;
+    let mut more = true;
+    loop {
+        if more && !eof {
+            buf.drain(..start);
+            start = 0;
+            let len = buf.len();
+            buf.resize(len + READ_SIZE, 0);
+            let read = read_full(input, &mut buf[len..])?;
+            buf.truncate(len + read);
+            eof = read == 0;
+            more = false;
+        }
+        let block = match find(&buf, start) {
+            Some(block) => block,
+            None if eof => break,
+            None => {
+                start = buf.len().saturating_sub(BLOCK_SIGNATURE.len());
+                more = true;
+                continue;
+            }
+        };
+        if block + HEADER_SIZE > buf.len() && !eof {
+            start = block;
+            more = true;
+            continue;
+        }
+        let info = buf
+            .get(block + 8..block + 12)
+            .map(LE::read_u32)
+            .ok_or_else(|| invalid("Truncated Xpress block"))?;
+        let pages = usize::try_from(info & 0xFF).unwrap() + 1;
+        let compressed = (usize::try_from(info >> 10).unwrap() + 1 + 7) & !7;
+        let data_start = block + HEADER_SIZE;
+        if data_start + compressed > buf.len() {
+            if eof {
+                return Err(invalid("Truncated Xpress block"));
+            }
+            start = block;
+            more = true;
+            continue;
+        }
+        let data = &buf[data_start..data_start + compressed];
+        let size = pages * PAGE_SIZE;
+        if compressed == size {
+            output.write_all(data)?;
+        } else {
+            output.write_all(&decompress_plain(data, size)?)?;
+        }
+        written = written + u64::try_from(size).unwrap();
+        start = data_start + compressed;
+    }
+    if written == 0 {
+        return Err(invalid(
+            "No Xpress blocks found, Windows 8+ hibernation files are not supported",
+        ));
+    }
+    let mut result = JsonValue::new_object();
+    result["Size"] = written.into();
+    result["Pages"] = (written / u64::try_from(PAGE_SIZE).unwrap()).into();
+    Ok(result)
+}
+
+fn find(buf: &[u8], start: usize) -> Option<usize> {
+    buf.get(start..)?
+        .windows(BLOCK_SIGNATURE.len())
+        .position(|x| x == BLOCK_SIGNATURE)
+        .map(|x| x + start)
+}
+
+fn read_full(input: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match input.read(&mut buf[read..])? {
+            0 => break,
+            n => read = read + n,
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let mut data = vec![0u8; PAGE_SIZE];
+        // One uncompressed page
+        let mut block = BLOCK_SIGNATURE.to_vec();
+        block.extend_from_slice(&(u32::try_from(PAGE_SIZE - 1).unwrap() << 10).to_le_bytes());
+        block.resize(HEADER_SIZE, 0);
+        block.extend(vec![0xAB; PAGE_SIZE]);
+        data.extend_from_slice(&block);
+        // One compressed page of zeroes: a literal and a long match
+        let mut block = BLOCK_SIGNATURE.to_vec();
+        let mut payload = (1u32 << 30).to_le_bytes().to_vec();
+        payload.push(0);
+        payload.extend_from_slice(&7u16.to_le_bytes());
+        payload.extend_from_slice(&[0x0F, 0xFF]);
+        payload.extend_from_slice(&u16::try_from(PAGE_SIZE - 1 - 3).unwrap().to_le_bytes());
+        let size = u32::try_from(payload.len() - 1).unwrap() << 10;
+        block.extend_from_slice(&size.to_le_bytes());
+        block.resize(HEADER_SIZE, 0);
+        block.extend_from_slice(&payload);
+        block.resize(HEADER_SIZE + payload.len().next_multiple_of(8), 0);
+        data.extend_from_slice(&block);
+
+        let mut output = Vec::new();
+        let result = convert(&mut data.as_slice(), &mut output).unwrap();
+        assert_eq!(result["Size"], 2 * 4096);
+        assert_eq!(result["Pages"], 2);
+        assert!(output[..PAGE_SIZE].iter().all(|x| *x == 0xAB));
+        assert!(output[PAGE_SIZE..].iter().all(|x| *x == 0));
+    }
+}
@@ -0,0 +1,135 @@
+use byteorder::{ByteOrder, LE};
+use json::JsonValue;
+use std::io;
+
+use super::{cfb, filetime, invalid, lnk};
+
+pub fn parse(data: &[u8]) -> io::Result<JsonValue> {
+    let streams = cfb::streams(data)?;
+    let dest_list = streams
+        .iter()
+        .find(|(name, _)| name == "DestList")
+        .map(|(_, data)| data.as_slice())
+        .ok_or_else(|| invalid("No DestList stream"))?;
+    if dest_list.len() < 32 {
+        return Err(invalid("DestList too short"));
+    }
+    let version = LE::read_u32(&dest_list[0..4]);
+    // Windows 10 entries carry an access count and a trailing 4 bytes
+    let (path_offset, trailer) = if version >= 3 { (128, 4) } else { (112, 0) };
+    let mut entries = JsonValue::new_array();
+    let mut pos = 32;
+    while pos + path_offset + 2 <= dest_list.len() {
+        let entry = &dest_list[pos..];
+        let path_len = usize::from(LE::read_u16(&entry[path_offset..path_offset + 2])) * 2;
+        let path = entry
+            .get(path_offset + 2..path_offset + 2 + path_len)
+            .ok_or_else(|| invalid("DestList entry out of range"))?;
+        let chars: Vec<u16> = path.chunks_exact(2).map(LE::read_u16).collect();
+        let number = LE::read_u32(&entry[88..92]);
+        let mut result = JsonValue::new_object();
+        result["EntryNumber"] = number.into();
+        result["Hostname"] = entry[72..88]
+            .iter()
+            .take_while(|x| **x != 0)
+            .map(|b| char::from(*b))
+            .collect::<String>()
+            .into();
+        result["LastModified"] = filetime(LE::read_u64(&entry[100..108])).into();
+        result["Pinned"] = (LE::read_i32(&entry[108..112]) >= 0).into();
+        if version >= 3 {
+            result["AccessCount"] = LE::read_u32(&entry[116..120]).into();
+        }
+        result["Path"] = String::from_utf16_lossy(&chars).into();
+        let stream = format!("{:x}", number);
+        if let Some((_, link)) = streams.iter().find(|(name, _)| *name == stream) {
+            result["Link"] = match lnk::parse(link) {
+                Ok(link) => link,
+                Err(e) => e.to_string().into(),
+            };
+        }
+        entries.push(result).unwrap();
+        pos = pos + path_offset + 2 + path_len + trailer;
+    }
+    let mut result = JsonValue::new_object();
+    result["Version"] = version.into();
+    result["Entries"] = entries;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn dir_entry(name: &str, kind: u8, start: u32, size: usize) -> Vec<u8> {
+        let mut entry = vec![0u8; 128];
+        let chars: Vec<u8> = name.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        entry[..chars.len()].copy_from_slice(&chars);
+        let name_len = u16::try_from(chars.len() + 2).unwrap();
+        entry[0x40..0x42].copy_from_slice(&name_len.to_le_bytes());
+        entry[0x42] = kind;
+        entry[0x74..0x78].copy_from_slice(&start.to_le_bytes());
+        entry[0x78..0x7C].copy_from_slice(&u32::try_from(size).unwrap().to_le_bytes());
+        entry
+    }
+
+    #[test]
+    fn test_parse_jumplist() {
+        let mut dest_list = vec![0u8; 32];
+        dest_list[0..4].copy_from_slice(&4u32.to_le_bytes());
+        let mut entry = vec![0u8; 130];
+        entry[72..77].copy_from_slice(b"host1");
+        entry[88..92].copy_from_slice(&1u32.to_le_bytes());
+        entry[100..108].copy_from_slice(&132539328000000000u64.to_le_bytes());
+        entry[108..112].copy_from_slice(&(-1i32).to_le_bytes());
+        entry[116..120].copy_from_slice(&3u32.to_le_bytes());
+        let path: Vec<u16> = r#"C:\doc.txt"#.encode_utf16().collect();
+        entry[128..130].copy_from_slice(&u16::try_from(path.len()).unwrap().to_le_bytes());
+        path.iter()
+            .for_each(|c| entry.extend_from_slice(&c.to_le_bytes()));
+        entry.extend_from_slice(&[0u8; 4]);
+        dest_list.extend_from_slice(&entry);
+        let mut link = vec![0u8; 0x4C];
+        link[0] = 0x4C;
+        link.extend_from_slice(&0u32.to_le_bytes());
+
+        // Header, FAT in sector 0, directory in 1, DestList in 2 and the link in 3
+        let mut data = vec![0u8; 512 * 5];
+        data[0..8].copy_from_slice(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+        data[0x1E..0x20].copy_from_slice(&9u16.to_le_bytes());
+        data[0x20..0x22].copy_from_slice(&6u16.to_le_bytes());
+        data[0x2C..0x30].copy_from_slice(&1u32.to_le_bytes());
+        data[0x30..0x34].copy_from_slice(&1u32.to_le_bytes());
+        data[0x3C..0x40].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+        data[0x44..0x48].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+        data[0x4C..0x200].iter_mut().for_each(|x| *x = 0xFF);
+        data[0x4C..0x50].copy_from_slice(&0u32.to_le_bytes());
+        let fat = [0xFFFF_FFFDu32, 0xFFFF_FFFE, 0xFFFF_FFFE, 0xFFFF_FFFE];
+        for (i, x) in fat.iter().enumerate() {
+            data[512 + i * 4..512 + i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+        }
+        let mut directory = dir_entry("Root Entry", 5, 0xFFFF_FFFE, 0);
+        directory.extend(dir_entry("DestList", 2, 2, dest_list.len()));
+        directory.extend(dir_entry("1", 2, 3, link.len()));
+        data[1024..1024 + directory.len()].copy_from_slice(&directory);
+        data[1536..1536 + dest_list.len()].copy_from_slice(&dest_list);
+        data[2048..2048 + link.len()].copy_from_slice(&link);
+
+        let result = parse(&data).unwrap();
+        assert_eq!(result["Version"], 4);
+        let entry = &result["Entries"][0];
+        assert_eq!(entry["Hostname"], "host1");
+        assert_eq!(entry["Path"], r#"C:\doc.txt"#);
+        assert_eq!(entry["LastModified"], "2021-01-01T00:00:00Z");
+        assert_eq!(entry["Pinned"], false);
+        assert_eq!(entry["AccessCount"], 3);
+        assert!(entry["Link"].is_object());
+        // Only 512 and 4096 byte sectors and 64 byte mini sectors exist
+        data[0x1E..0x20].copy_from_slice(&16u16.to_le_bytes());
+        assert!(parse(&data).is_err());
+        data[0x1E..0x20].copy_from_slice(&9u16.to_le_bytes());
+        data[0x20..0x22].copy_from_slice(&7u16.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use byteorder::{ByteOrder, ReadBytesExt, LE};
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use super::{filetime, invalid};
+
+const HAS_ID_LIST: u32 = 0x01;
+const HAS_LINK_INFO: u32 = 0x02;
+const IS_UNICODE: u32 = 0x80;
+const STRINGS: [(u32, &str); 5] = [
+    (0x04, "Name"),
+    (0x08, "RelativePath"),
+    (0x10, "WorkingDirectory"),
+    (0x20, "Arguments"),
+    (0x40, "IconLocation"),
+];
+const TRACKER_SIGNATURE: u32 = 0xA000_0003;
+
+pub fn parse(data: &[u8]) -> io::Result<JsonValue> {
+    let mut cur = Cursor::new(data);
+    if cur.read_u32::<LE>()? != 0x4C {
+        return Err(invalid("Invalid shell link header size"));
+    }
+    cur.seek(SeekFrom::Start(0x14))?;
+    let flags = cur.read_u32::<LE>()?;
+    let attributes = cur.read_u32::<LE>()?;
+    let created = cur.read_u64::<LE>()?;
+    let accessed = cur.read_u64::<LE>()?;
+    let modified = cur.read_u64::<LE>()?;
+    let size = cur.read_u32::<LE>()?;
+    let mut result = JsonValue::new_object();
+    result["TargetCreated"] = filetime(created).into();
+    result["TargetAccessed"] = filetime(accessed).into();
+    result["TargetModified"] = filetime(modified).into();
+    result["TargetSize"] = size.into();
+    result["TargetAttributes"] = format!("{:08X}", attributes).into();
+    cur.seek(SeekFrom::Start(0x4C))?;
+    if flags & HAS_ID_LIST != 0 {
+        let len = cur.read_u16::<LE>()?;
+        cur.seek(SeekFrom::Current(i64::from(len)))?;
+    }
+    if flags & HAS_LINK_INFO != 0 {
+        let start = usize::try_from(cur.position()).unwrap();
+        let len = usize::try_from(cur.read_u32::<LE>()?).unwrap();
+        let info = data
+            .get(start..start + len)
+            .ok_or_else(|| invalid("Link info out of range"))?;
+        link_info(info, &mut result)?;
+        cur.seek(SeekFrom::Start(u64::try_from(start + len).unwrap()))?;
+    }
+    for (flag, name) in STRINGS.iter() {
+        if flags & flag != 0 {
+            result[*name] = string_data(&mut cur, flags & IS_UNICODE != 0)?.into();
+        }
+    }
+    // Extra data blocks run until a terminal block smaller than 4 bytes.
+    loop {
+        let start = usize::try_from(cur.position()).unwrap();
+        let len = match cur.read_u32::<LE>() {
+            Ok(len) if len >= 8 => usize::try_from(len).unwrap(),
+            _ => break,
+        };
+        let block = match data.get(start..start + len) {
+            Some(block) => block,
+            None => break,
+        };
+        if len >= 0x60 && LE::read_u32(&block[4..8]) == TRACKER_SIGNATURE {
+            result["MachineID"] = ansi(&block[16..32]).into();
+        }
+        cur.seek(SeekFrom::Start(u64::try_from(start + len).unwrap()))?;
+    }
+    Ok(result)
+}
+
+fn link_info(info: &[u8], result: &mut JsonValue) -> io::Result<()> {
+    let mut cur = Cursor::new(info);
+    cur.seek(SeekFrom::Start(8))?;
+    let flags = cur.read_u32::<LE>()?;
+    let volume_offset = offset(cur.read_u32::<LE>()?);
+    let base_path_offset = offset(cur.read_u32::<LE>()?);
+    let network_offset = offset(cur.read_u32::<LE>()?);
+    let suffix_offset = offset(cur.read_u32::<LE>()?);
+    let suffix = ansi(info.get(suffix_offset..).unwrap_or(&[]));
+    if flags & 1 != 0 {
+        let volume = info
+            .get(volume_offset..)
+            .filter(|x| x.len() >= 16)
+            .ok_or_else(|| invalid("Volume ID out of range"))?;
+        result["DriveType"] = LE::read_u32(&volume[4..8]).into();
+        result["VolumeSerial"] = format!("{:08X}", LE::read_u32(&volume[8..12])).into();
+        let label = offset(LE::read_u32(&volume[12..16]));
+        result["VolumeLabel"] = ansi(volume.get(label..).unwrap_or(&[])).into();
+        let base = ansi(info.get(base_path_offset..).unwrap_or(&[]));
+        result["TargetPath"] = (base + &suffix).into();
+    } else if flags & 2 != 0 {
+        let network = info
+            .get(network_offset..)
+            .filter(|x| x.len() >= 12)
+            .ok_or_else(|| invalid("Network link out of range"))?;
+        let name = offset(LE::read_u32(&network[8..12]));
+        let share = ansi(network.get(name..).unwrap_or(&[]));
+        result["TargetPath"] = if suffix.is_empty() {
+            share
+        } else {
+            format!("{}\\{}", share, suffix)
+        }
+        .into();
+    }
+    Ok(())
+}
+
+fn string_data(cur: &mut Cursor<&[u8]>, unicode: bool) -> io::Result<String> {
+    let len = usize::from(cur.read_u16::<LE>()?);
+    if unicode {
+        let mut chars = vec![0u16; len];
+        cur.read_u16_into::<LE>(&mut chars)?;
+        Ok(String::from_utf16_lossy(&chars))
+    } else {
+        let mut bytes = vec![0u8; len];
+        cur.read_exact(&mut bytes)?;
+        Ok(ansi(&bytes))
+    }
+}
+
+fn offset(x: u32) -> usize {
+    usize::try_from(x).unwrap()
+}
+
+fn ansi(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|x| *x == 0).unwrap_or(bytes.len());
+    bytes[..len].iter().map(|b| char::from(*b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lnk() {
+        let mut data = vec![0u8; 0x4C];
+        data[0] = 0x4C;
+        data[0x14..0x18].copy_from_slice(&(HAS_LINK_INFO | 0x20 | IS_UNICODE).to_le_bytes());
+        data[0x2C..0x34].copy_from_slice(&132539328000000000u64.to_le_bytes());
+        data[0x34..0x38].copy_from_slice(&1234u32.to_le_bytes());
+        // Link info with a volume ID and a local base path
+        let mut info = vec![0u8; 0x1C];
+        let mut volume = vec![0u8; 0x10];
+        volume[0..4].copy_from_slice(&0x11u32.to_le_bytes());
+        volume[4..8].copy_from_slice(&3u32.to_le_bytes());
+        volume[8..12].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        volume[12..16].copy_from_slice(&0x10u32.to_le_bytes());
+        volume.push(0);
+        let base = b"C:\\Tools\\tool.exe\0";
+        let len = 0x1C + volume.len() + base.len() + 1;
+        info[0..4].copy_from_slice(&u32::try_from(len).unwrap().to_le_bytes());
+        info[4..8].copy_from_slice(&0x1Cu32.to_le_bytes());
+        info[8..12].copy_from_slice(&1u32.to_le_bytes());
+        info[12..16].copy_from_slice(&0x1Cu32.to_le_bytes());
+        let base_offset = u32::try_from(0x1C + volume.len()).unwrap();
+        info[16..20].copy_from_slice(&base_offset.to_le_bytes());
+        let suffix_offset = base_offset + u32::try_from(base.len()).unwrap();
+        info[24..28].copy_from_slice(&suffix_offset.to_le_bytes());
+        info.extend_from_slice(&volume);
+        info.extend_from_slice(base);
+        info.push(0);
+        data.extend_from_slice(&info);
+        let args: Vec<u16> = "-x".encode_utf16().collect();
+        data.extend_from_slice(&2u16.to_le_bytes());
+        args.iter()
+            .for_each(|c| data.extend_from_slice(&c.to_le_bytes()));
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let result = parse(&data).unwrap();
+        assert_eq!(result["TargetPath"], r#"C:\Tools\tool.exe"#);
+        assert_eq!(result["VolumeSerial"], "DEADBEEF");
+        assert_eq!(result["DriveType"], 3);
+        assert_eq!(result["Arguments"], "-x");
+        assert_eq!(result["TargetModified"], "2021-01-01T00:00:00Z");
+        assert_eq!(result["TargetSize"], 1234);
+    }
+}
@@ -0,0 +1,431 @@
+use byteorder::{ByteOrder, LE};
+use json::JsonValue;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use super::invalid;
+use crate::ntfs::fixup;
+
+// Restart pages are at least this large, the real size is in the first one
+const MIN_PAGE_SIZE: usize = 4096;
+const RECORD_HEADER_SIZE: usize = 0x30;
+// The fixed part of the NTFS client data, the LCNs follow
+const CLIENT_HEADER_SIZE: usize = 0x20;
+const MAX_RECORD: usize = 0x10000;
+
+const OPERATIONS: [&str; 35] = [
+    "Noop",
+    "CompensationLogRecord",
+    "InitializeFileRecordSegment",
+    "DeallocateFileRecordSegment",
+    "WriteEndOfFileRecordSegment",
+    "CreateAttribute",
+    "DeleteAttribute",
+    "UpdateResidentValue",
+    "UpdateNonresidentValue",
+    "UpdateMappingPairs",
+    "DeleteDirtyClusters",
+    "SetNewAttributeSizes",
+    "AddIndexEntryRoot",
+    "DeleteIndexEntryRoot",
+    "AddIndexEntryAllocation",
+    "DeleteIndexEntryAllocation",
+    "WriteEndOfIndexBuffer",
+    "SetIndexEntryVcnRoot",
+    "SetIndexEntryVcnAllocation",
+    "UpdateFileNameRoot",
+    "UpdateFileNameAllocation",
+    "SetBitsInNonresidentBitMap",
+    "ClearBitsInNonresidentBitMap",
+    "HotFix",
+    "EndTopLevelAction",
+    "PrepareTransaction",
+    "CommitTransaction",
+    "ForgetTransaction",
+    "OpenNonresidentAttribute",
+    "OpenAttributeTableDump",
+    "AttributeNamesDump",
+    "DirtyPageTableDump",
+    "TransactionTableDump",
+    "UpdateRecordDataRoot",
+    "UpdateRecordDataAllocation",
+];
+// The operations with an index entry as their data, which has a copy of the $FILE_NAME
+const ADD_INDEX_ENTRY: [u16; 2] = [0x0C, 0x0E];
+
+// Writes a JSONL line for every log record in the RCRD pages of $LogFile, the newest
+// restart area (RSTR) goes in the summary. Torn pages are counted and skipped, records
+// that are in more than one page (the buffer pages copy the tail) are written once.
+pub fn convert(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<JsonValue> {
+    let mut page = vec![0u8; MIN_PAGE_SIZE];
+    input.read_exact(&mut page)?;
+    if &page[0..4] != b"RSTR" && &page[0..4] != b"CHKD" {
+        return Err(invalid("Invalid restart page signature"));
+    }
+    let system_page_size = usize::try_from(LE::read_u32(&page[0x10..0x14])).unwrap();
+    let log_page_size = usize::try_from(LE::read_u32(&page[0x14..0x18])).unwrap();
+    for size in [system_page_size, log_page_size].iter() {
+        if !size.is_power_of_two() || *size < 512 || *size > 0x10000 {
+            return Err(invalid("Invalid $LogFile page size"));
+        }
+    }
+    page.resize(system_page_size.max(MIN_PAGE_SIZE), 0);
+    input.read_exact(&mut page[MIN_PAGE_SIZE..])?;
+    page.truncate(system_page_size);
+    let first = parse_restart(&mut page);
+    // The second restart page is a copy that's written to alternately
+    input.read_exact(&mut page)?;
+    let restart = match (first, parse_restart(&mut page)) {
+        (Ok(x), Ok(y)) => {
+            if x["CurrentLsn"].as_u64() >= y["CurrentLsn"].as_u64() {
+                x
+            } else {
+                y
+            }
+        }
+        (Ok(x), Err(_)) | (Err(_), Ok(x)) => x,
+        (Err(e), Err(_)) => return Err(e),
+    };
+    let mut page = vec![0u8; log_page_size];
+    let mut pages = 0u64;
+    let mut torn = 0u64;
+    let mut seen = HashSet::new();
+    let mut pending = None;
+    loop {
+        match input.read_exact(&mut page) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if &page[0..4] != b"RCRD" {
+            pending = None;
+            continue;
+        }
+        pages = pages + 1;
+        if !fixup_page(&mut page).unwrap_or(false) {
+            torn = torn + 1;
+            pending = None;
+            continue;
+        }
+        for record in parse_page(&page, &mut pending) {
+            if seen.insert(LE::read_u64(&record[0..8])) {
+                let line = parse_record(&record);
+                output.write_all(line.dump().as_bytes())?;
+                output.write_all(b"\n")?;
+            }
+        }
+    }
+    let mut result = restart;
+    result["Pages"] = pages.into();
+    result["TornPages"] = torn.into();
+    result["Records"] = seen.len().into();
+    Ok(result)
+}
+
+// RSTR and RCRD pages have an update sequence array like MFT records, false when the
+// page is torn.
+fn fixup_page(page: &mut [u8]) -> io::Result<bool> {
+    let offset = LE::read_u16(&page[4..6]);
+    let entries = LE::read_u16(&page[6..8]);
+    fixup(offset, entries, page)
+}
+
+fn parse_restart(page: &mut [u8]) -> io::Result<JsonValue> {
+    if &page[0..4] != b"RSTR" && &page[0..4] != b"CHKD" {
+        return Err(invalid("Invalid restart page signature"));
+    }
+    if !fixup_page(page)? {
+        return Err(invalid("Torn restart page"));
+    }
+    let area = page
+        .get(usize::from(LE::read_u16(&page[0x18..0x1A]))..)
+        .filter(|x| x.len() >= 0x30)
+        .ok_or_else(|| invalid("Restart area beyond the page"))?;
+    let mut result = JsonValue::new_object();
+    result["CurrentLsn"] = LE::read_u64(&area[0..8]).into();
+    result["Version"] = format!(
+        "{}.{}",
+        LE::read_i16(&page[0x1C..0x1E]),
+        LE::read_i16(&page[0x1A..0x1C])
+    )
+    .into();
+    // The client records, NTFS is the only client
+    let count = usize::from(LE::read_u16(&area[0x08..0x0A]));
+    let mut pos = usize::from(LE::read_u16(&area[0x16..0x18]));
+    let mut clients = JsonValue::new_array();
+    for _ in 0..count {
+        let client = match area.get(pos..pos + 0xA0) {
+            Some(client) => client,
+            None => break,
+        };
+        let len = usize::try_from(LE::read_u32(&client[0x1C..0x20]))
+            .unwrap()
+            .min(0x80);
+        let name: Vec<u16> = client[0x20..0x20 + len]
+            .chunks_exact(2)
+            .map(LE::read_u16)
+            .collect();
+        let mut entry = JsonValue::new_object();
+        entry["Name"] = String::from_utf16_lossy(&name).into();
+        entry["OldestLsn"] = LE::read_u64(&client[0..8]).into();
+        entry["RestartLsn"] = LE::read_u64(&client[8..16]).into();
+        clients.push(entry).unwrap();
+        pos = pos + 0xA0;
+    }
+    result["Clients"] = clients;
+    Ok(result)
+}
+
+// The complete records that are in a page. A record that doesn't fit continues after
+// the header of the next page, `pending` holds what's been read of it so far.
+fn parse_page(page: &[u8], pending: &mut Option<Vec<u8>>) -> Vec<Vec<u8>> {
+    // The data starts after the update sequence array
+    let usa_end =
+        usize::from(LE::read_u16(&page[4..6])) + 2 * usize::from(LE::read_u16(&page[6..8]));
+    let data_offset = align(usa_end).min(page.len());
+    let mut records = Vec::new();
+    let mut pos = data_offset;
+    if let Some(mut record) = pending.take() {
+        let needed = record_len(&record) - record.len();
+        let available = page.len().saturating_sub(data_offset);
+        record.extend_from_slice(&page[pos..pos + needed.min(available)]);
+        if needed > available {
+            *pending = Some(record);
+            return records;
+        }
+        records.push(record);
+        pos = align(pos + needed);
+    }
+    let mut last_lsn = 0;
+    while pos + RECORD_HEADER_SIZE <= page.len() {
+        let header = &page[pos..pos + RECORD_HEADER_SIZE];
+        let lsn = LE::read_u64(&header[0..8]);
+        let record_type = LE::read_u32(&header[0x20..0x24]);
+        let len = record_len(header);
+        // Whatever follows the last record is stale, from before the log wrapped
+        if lsn <= last_lsn || !(record_type == 1 || record_type == 2) || len > MAX_RECORD {
+            break;
+        }
+        last_lsn = lsn;
+        if pos + len > page.len() {
+            *pending = Some(page[pos..].to_vec());
+            break;
+        }
+        records.push(page[pos..pos + len].to_vec());
+        pos = align(pos + len);
+    }
+    records
+}
+
+fn parse_record(record: &[u8]) -> JsonValue {
+    let mut line = JsonValue::new_object();
+    line["Lsn"] = LE::read_u64(&record[0..8]).into();
+    line["PreviousLsn"] = LE::read_u64(&record[0x08..0x10]).into();
+    line["UndoNextLsn"] = LE::read_u64(&record[0x10..0x18]).into();
+    line["TransactionId"] = LE::read_u32(&record[0x24..0x28]).into();
+    if LE::read_u32(&record[0x20..0x24]) == 2 {
+        line["Type"] = "ClientRestart".into();
+        return line;
+    }
+    line["Type"] = "Client".into();
+    let data = &record[RECORD_HEADER_SIZE..];
+    if data.len() < CLIENT_HEADER_SIZE {
+        return line;
+    }
+    let redo = LE::read_u16(&data[0..2]);
+    let undo = LE::read_u16(&data[2..4]);
+    line["Redo"] = operation(redo).into();
+    line["Undo"] = operation(undo).into();
+    line["TargetAttribute"] = LE::read_u16(&data[0x0C..0x0E]).into();
+    line["TargetVcn"] = LE::read_u64(&data[0x18..0x20]).into();
+    line["RecordOffset"] = LE::read_u16(&data[0x10..0x12]).into();
+    line["AttributeOffset"] = LE::read_u16(&data[0x12..0x14]).into();
+    line["ClusterBlockOffset"] = LE::read_u16(&data[0x14..0x16]).into();
+    let lcns: Vec<u64> = data[CLIENT_HEADER_SIZE..]
+        .chunks_exact(8)
+        .take(usize::from(LE::read_u16(&data[0x0E..0x10])))
+        .map(LE::read_u64)
+        .collect();
+    line["Lcns"] = lcns.into();
+    // An added index entry is in the redo data, a deleted one in the undo data
+    let entry = if ADD_INDEX_ENTRY.contains(&redo) {
+        Some((4, 6))
+    } else if ADD_INDEX_ENTRY.contains(&undo) {
+        Some((8, 10))
+    } else {
+        None
+    };
+    let file_name = entry.and_then(|(offset, length)| {
+        let start = usize::from(LE::read_u16(&data[offset..offset + 2]));
+        let len = usize::from(LE::read_u16(&data[length..length + 2]));
+        index_entry_name(data.get(start..start + len)?)
+    });
+    if let Some((parent, name)) = file_name {
+        line["ParentEntry"] = parent.into();
+        line["FileName"] = name.into();
+    }
+    line
+}
+
+// The key of a directory index entry is the $FILE_NAME of the file.
+fn index_entry_name(entry: &[u8]) -> Option<(u64, String)> {
+    let key = entry.get(0x10..)?;
+    let len = usize::from(*key.get(0x40)?) * 2;
+    let name: Vec<u16> = key
+        .get(0x42..0x42 + len)?
+        .chunks_exact(2)
+        .map(LE::read_u16)
+        .collect();
+    let parent = LE::read_u64(&key[0..8]) & 0xFFFF_FFFF_FFFF;
+    Some((parent, String::from_utf16_lossy(&name)))
+}
+
+fn operation(code: u16) -> String {
+    match OPERATIONS.get(usize::from(code)) {
+        Some(name) => String::from(*name),
+        None => format!("Unknown{:#x}", code),
+    }
+}
+
+fn record_len(header: &[u8]) -> usize {
+    RECORD_HEADER_SIZE + usize::try_from(LE::read_u32(&header[0x18..0x1C])).unwrap()
+}
+
+fn align(pos: usize) -> usize {
+    pos.next_multiple_of(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str;
+
+    const PAGE_SIZE: usize = 4096;
+
+    // Swaps the end of every sector for the update sequence number, as when written.
+    fn protect(page: &mut [u8], usa_offset: usize) {
+        page[4..6].copy_from_slice(&u16::try_from(usa_offset).unwrap().to_le_bytes());
+        page[6..8].copy_from_slice(&9u16.to_le_bytes());
+        page[usa_offset..usa_offset + 2].copy_from_slice(&[7, 0]);
+        for i in 1..9 {
+            let end = i * 512;
+            page.copy_within(end - 2..end, usa_offset + i * 2);
+            page[end - 2..end].copy_from_slice(&[7, 0]);
+        }
+    }
+
+    fn restart_page(lsn: u64) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[0..4].copy_from_slice(b"RSTR");
+        page[0x10..0x14].copy_from_slice(&4096u32.to_le_bytes());
+        page[0x14..0x18].copy_from_slice(&4096u32.to_le_bytes());
+        page[0x18..0x1A].copy_from_slice(&0x30u16.to_le_bytes());
+        page[0x1A..0x1C].copy_from_slice(&1u16.to_le_bytes());
+        page[0x1C..0x1E].copy_from_slice(&2u16.to_le_bytes());
+        let area = 0x30;
+        page[area..area + 8].copy_from_slice(&lsn.to_le_bytes());
+        page[area + 8..area + 10].copy_from_slice(&1u16.to_le_bytes());
+        page[area + 0x16..area + 0x18].copy_from_slice(&0x30u16.to_le_bytes());
+        let client = area + 0x30;
+        page[client + 0x1C..client + 0x20].copy_from_slice(&8u32.to_le_bytes());
+        for (i, c) in "NTFS".encode_utf16().enumerate() {
+            let pos = client + 0x20 + i * 2;
+            page[pos..pos + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        protect(&mut page, 0x1E);
+        page
+    }
+
+    fn log_record(lsn: u64, record_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_HEADER_SIZE];
+        record[0..8].copy_from_slice(&lsn.to_le_bytes());
+        let len = u32::try_from(data.len()).unwrap();
+        record[0x18..0x1C].copy_from_slice(&len.to_le_bytes());
+        record[0x20..0x24].copy_from_slice(&record_type.to_le_bytes());
+        record[0x24..0x28].copy_from_slice(&24u32.to_le_bytes());
+        record.extend_from_slice(data);
+        record.resize(align(record.len()), 0);
+        record
+    }
+
+    // Client data with the redo data and one LCN.
+    fn client_data(redo: u16, undo: u16, redo_data: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; CLIENT_HEADER_SIZE + 8];
+        data[0..2].copy_from_slice(&redo.to_le_bytes());
+        data[2..4].copy_from_slice(&undo.to_le_bytes());
+        data[4..6].copy_from_slice(&0x28u16.to_le_bytes());
+        let len = u16::try_from(redo_data.len()).unwrap();
+        data[6..8].copy_from_slice(&len.to_le_bytes());
+        data[0x0E..0x10].copy_from_slice(&1u16.to_le_bytes());
+        data[0x20..0x28].copy_from_slice(&1234u64.to_le_bytes());
+        data.extend_from_slice(redo_data);
+        data
+    }
+
+    fn index_entry(parent: u64, name: &str) -> Vec<u8> {
+        let mut entry = vec![0u8; 0x10 + 0x42];
+        entry[0x10..0x18].copy_from_slice(&(parent | 1 << 48).to_le_bytes());
+        entry[0x50] = u8::try_from(name.len()).unwrap();
+        entry[0x51] = 1;
+        name.encode_utf16()
+            .for_each(|c| entry.extend_from_slice(&c.to_le_bytes()));
+        entry
+    }
+
+    fn log_page(data: &[u8]) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[0..4].copy_from_slice(b"RCRD");
+        page[0x40..0x40 + data.len()].copy_from_slice(data);
+        protect(&mut page, 0x28);
+        page
+    }
+
+    #[test]
+    fn test_convert() {
+        let added = client_data(0x0E, 0x0F, &index_entry(5, "new.txt"));
+        let first = log_record(100, 1, &added);
+        // The second record continues in the next page
+        let big = log_record(101, 1, &client_data(8, 8, &vec![0xAA; 4096]));
+        let split = PAGE_SIZE - 0x40 - first.len();
+        let mut data = first.clone();
+        data.extend_from_slice(&big[..split]);
+        let mut next = big[split..].to_vec();
+        next.extend(log_record(102, 2, &[]));
+
+        let mut log = restart_page(90);
+        log.extend(restart_page(110));
+        log.extend(log_page(&data));
+        log.extend(log_page(&next));
+        // A copy of the first page and a torn page
+        log.extend(log_page(&data));
+        let mut torn = log_page(&first);
+        torn[1022] = 0;
+        log.extend(torn);
+
+        let mut output = Vec::new();
+        let result = convert(&mut log.as_slice(), &mut output).unwrap();
+        assert_eq!(result["CurrentLsn"], 110);
+        assert_eq!(result["Version"], "2.1");
+        assert_eq!(result["Clients"][0]["Name"], "NTFS");
+        assert_eq!(result["Pages"], 4);
+        assert_eq!(result["TornPages"], 1);
+        assert_eq!(result["Records"], 3);
+
+        let lines: Vec<JsonValue> = str::from_utf8(&output)
+            .unwrap()
+            .lines()
+            .map(|x| json::parse(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["Lsn"], 100);
+        assert_eq!(lines[0]["Redo"], "AddIndexEntryAllocation");
+        assert_eq!(lines[0]["Undo"], "DeleteIndexEntryAllocation");
+        assert_eq!(lines[0]["FileName"], "new.txt");
+        assert_eq!(lines[0]["ParentEntry"], 5);
+        assert_eq!(lines[0]["Lcns"][0], 1234);
+        assert_eq!(lines[1]["Lsn"], 101);
+        assert_eq!(lines[1]["Redo"], "UpdateNonresidentValue");
+        assert_eq!(lines[2]["Type"], "ClientRestart");
+    }
+}
@@ -0,0 +1,312 @@
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use super::filetime;
+use super::timeline::csv_field;
+use crate::ntfs::{FileName, MFTEntry, Timestamps, MFT};
+
+pub const HEADER: &str = "Entry,Sequence,InUse,Directory,ParentEntry,ParentPath,Name,Size,Flags,\
+SICreated,SIModified,SIChanged,SIAccessed,FNCreated,FNModified,FNChanged,FNAccessed\r\n";
+
+pub const TIMES: [&str; 4] = ["Created", "Modified", "Changed", "Accessed"];
+
+// The file attributes in $STANDARD_INFORMATION, for the Flags column.
+const ATTRIBUTES: [(u32, &str); 11] = [
+    (0x1, "ReadOnly"),
+    (0x2, "Hidden"),
+    (0x4, "System"),
+    (0x20, "Archive"),
+    (0x100, "Temporary"),
+    (0x200, "Sparse"),
+    (0x400, "ReparsePoint"),
+    (0x800, "Compressed"),
+    (0x1000, "Offline"),
+    (0x2000, "NotIndexed"),
+    (0x4000, "Encrypted"),
+];
+
+// Writes a JSONL line with the mft-dump columns for every MFT entry with a name, deleted
+// ones included, and flags entries that look timestomped. Paths start with `prefix`, like
+// C:. Torn records are skipped and counted, unless the MFT was opened to parse them anyway.
+pub fn walk(mft: &mut MFT, prefix: &str, output: &mut dyn Write) -> io::Result<JsonValue> {
+    let mut entries = 0u64;
+    let mut timestomped = JsonValue::new_array();
+    mft.walk(|idx, entry, name, dir| {
+        let line = Row::new(idx, entry, name, dir.map(|x| join(prefix, &x))).json();
+        if line.has_key("Timestomped") {
+            let mut flagged = JsonValue::new_object();
+            for key in ["Entry", "Name", "Timestomped", "SICreated", "FNCreated"].iter() {
+                flagged[*key] = line[*key].clone();
+            }
+            timestomped.push(flagged).unwrap();
+        }
+        output.write_all(line.dump().as_bytes())?;
+        output.write_all(b"\n")?;
+        entries = entries + 1;
+        Ok(())
+    })?;
+    let mut result = JsonValue::new_object();
+    result["Entries"] = entries.into();
+    result["Timestomped"] = timestomped;
+    result["Torn"] = mft.fixup_errors().len().into();
+    Ok(result)
+}
+
+pub fn join(dir: &str, name: &str) -> String {
+    if name.is_empty() {
+        format!("{}\\", dir)
+    } else {
+        format!("{}\\{}", dir.trim_end_matches('\\'), name)
+    }
+}
+
+// An MFT entry under one of its names, as written by mft-dump and the mft parser.
+pub struct Row {
+    pub entry: i64,
+    pub sequence: u16,
+    pub in_use: bool,
+    pub dir: bool,
+    pub parent: u64,
+    // None when the directory is gone, as with some deleted files
+    pub parent_path: Option<String>,
+    pub name: String,
+    pub size: u64,
+    pub flags: u32,
+    pub si: Option<Timestamps>,
+    pub fn_times: Timestamps,
+}
+
+impl Row {
+    pub fn new<T>(
+        idx: i64,
+        entry: &MFTEntry<T>,
+        name: &FileName,
+        parent_path: Option<String>,
+    ) -> Row {
+        let si = entry.standard_information();
+        Row {
+            entry: idx,
+            sequence: entry.sequence(),
+            in_use: entry.in_use(),
+            dir: entry.is_dir(),
+            parent: name.parent,
+            parent_path,
+            name: name.name.clone(),
+            size: entry.data_runs().map_or(0, |(size, _)| size),
+            flags: si.as_ref().map_or(0, |x| x.flags),
+            si: si.map(|x| x.times),
+            fn_times: name.times,
+        }
+    }
+    fn flag_names(&self) -> Vec<&'static str> {
+        ATTRIBUTES
+            .iter()
+            .filter(|(x, _)| self.flags & x != 0)
+            .map(|(_, x)| *x)
+            .collect()
+    }
+    pub fn csv(&self) -> String {
+        let mut fields = vec![
+            self.entry.to_string(),
+            self.sequence.to_string(),
+            self.in_use.to_string(),
+            self.dir.to_string(),
+            self.parent.to_string(),
+            csv_field(self.parent_path.as_deref().unwrap_or("")),
+            csv_field(&self.name),
+            self.size.to_string(),
+            self.flag_names().join("|"),
+        ];
+        for times in [self.si, Some(self.fn_times)].iter() {
+            let times = times.map_or([0; 4], |x| [x.created, x.modified, x.changed, x.accessed]);
+            fields.extend(times.iter().map(|x| filetime(*x).unwrap_or_default()));
+        }
+        fields.join(",") + "\r\n"
+    }
+    // The CSV columns, with the times that are set and the Timestomped reasons if any.
+    pub fn json(&self) -> JsonValue {
+        let mut line = JsonValue::new_object();
+        line["Entry"] = self.entry.into();
+        line["Sequence"] = self.sequence.into();
+        line["InUse"] = self.in_use.into();
+        line["Directory"] = self.dir.into();
+        line["ParentEntry"] = self.parent.into();
+        line["ParentPath"] = self.parent_path.as_deref().into();
+        line["Name"] = self.name.as_str().into();
+        line["Size"] = self.size.into();
+        line["Flags"] = self.flag_names().into();
+        for (kind, times) in [("SI", self.si), ("FN", Some(self.fn_times))].iter() {
+            if let Some(times) = times {
+                let times = [times.created, times.modified, times.changed, times.accessed];
+                for (time_name, time) in TIMES.iter().zip(times.iter()) {
+                    line[format!("{}{}", kind, time_name)] = filetime(*time).into();
+                }
+            }
+        }
+        let reasons = self.timestomped();
+        if !reasons.is_empty() {
+            line["Timestomped"] = reasons.into();
+        }
+        line
+    }
+    // Two lines in the TSK bodyfile format, one with the $STANDARD_INFORMATION times and
+    // one with the $FILE_NAME times.
+    pub fn bodyfile(&self) -> String {
+        let dir = match &self.parent_path {
+            Some(dir) => dir.clone(),
+            None => String::from("$OrphanFiles"),
+        };
+        let mut path = join(&dir, &self.name);
+        if !self.in_use {
+            path = path + " (deleted)";
+        }
+        let mode = if self.dir {
+            "d/drwxrwxrwx"
+        } else {
+            "r/rrwxrwxrwx"
+        };
+        let mut lines = String::new();
+        let si = self.si.unwrap_or_default();
+        for (suffix, times) in [("", si), (" ($FILE_NAME)", self.fn_times)].iter() {
+            lines = lines
+                + &format!(
+                    "0|{}{}|{}|{}|0|0|{}|{}|{}|{}|{}\n",
+                    path,
+                    suffix,
+                    self.entry,
+                    mode,
+                    self.size,
+                    unix_time(times.accessed),
+                    unix_time(times.modified),
+                    unix_time(times.changed),
+                    unix_time(times.created),
+                );
+        }
+        lines
+    }
+    // Why the $STANDARD_INFORMATION times look set by hand, compared to $FILE_NAME.
+    pub fn timestomped(&self) -> Vec<&'static str> {
+        let mut reasons = Vec::new();
+        let si = match self.si {
+            Some(si) => si,
+            None => return reasons,
+        };
+        if si.created < self.fn_times.created {
+            reasons.push("SICreatedBeforeFN");
+        }
+        // Tools that set times through second resolution APIs leave no fractions
+        if si.created != 0 && si.created % 10_000_000 == 0 && si.modified % 10_000_000 == 0 {
+            reasons.push("ZeroedFractions");
+        }
+        reasons
+    }
+}
+
+// Seconds since 1970 of a FILETIME, 0 when it isn't set.
+fn unix_time(time: u64) -> i64 {
+    match time {
+        0 => 0,
+        _ => i64::try_from(time / 10_000_000).unwrap() - 11_644_473_600,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::{file_name_data, record, resident, volume};
+    use std::str;
+
+    fn entry(si: u64, fn_created: u64, name: &str) -> Vec<u8> {
+        let mut si_data = vec![0u8; 48];
+        for x in 0..4 {
+            si_data[x * 8..x * 8 + 8].copy_from_slice(&si.to_le_bytes());
+        }
+        let mut fn_data = file_name_data(5, 1, name);
+        fn_data[8..16].copy_from_slice(&fn_created.to_le_bytes());
+        record(1, &[resident(0x10, &si_data), resident(48, &fn_data)])
+    }
+
+    #[test]
+    fn test_walk() {
+        let normal = 132539328001234567;
+        let stomped = 132000000000000000;
+        let mut torn = entry(normal, normal, "torn.txt");
+        torn[1022..1024].copy_from_slice(&[0, 0]);
+        let records = vec![
+            (8, entry(normal, normal, "normal.txt")),
+            (9, entry(stomped, normal, "evil.exe")),
+            (10, torn),
+        ];
+        let path = volume("squirrel-test-parse-mft", &[], records);
+        let mut mft = MFT::open(path.to_str().unwrap(), false).unwrap();
+        let mut output = Vec::new();
+        let result = walk(&mut mft, "C:", &mut output).unwrap();
+        assert_eq!(result["Torn"], 1);
+        assert_eq!(result["Timestomped"].len(), 1);
+        let flagged = &result["Timestomped"][0];
+        assert_eq!(flagged["Entry"], 9);
+        assert_eq!(flagged["Name"], "evil.exe");
+        assert_eq!(flagged["Timestomped"][0], "SICreatedBeforeFN");
+        assert_eq!(flagged["Timestomped"][1], "ZeroedFractions");
+
+        let lines: Vec<JsonValue> = str::from_utf8(&output)
+            .unwrap()
+            .lines()
+            .map(|x| json::parse(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), result["Entries"].as_usize().unwrap());
+        let line = lines.iter().find(|x| x["Name"] == "normal.txt").unwrap();
+        assert_eq!(line["ParentEntry"], 5);
+        assert_eq!(line["ParentPath"], "C:\\");
+        assert_eq!(line["InUse"], true);
+        assert_eq!(line["SICreated"], "2021-01-01T00:00:00.123456700Z");
+        assert!(!line.has_key("Timestomped"));
+        assert!(!lines.iter().any(|x| x["Name"] == "torn.txt"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_row() {
+        // 2021-01-01T00:00:00Z
+        let time = 132_539_328_000_000_000;
+        let times = Timestamps {
+            created: time,
+            modified: time + 10_000_000,
+            changed: time,
+            accessed: 0,
+        };
+        let mut row = Row {
+            entry: 42,
+            sequence: 3,
+            in_use: false,
+            dir: false,
+            parent: 16,
+            parent_path: Some(String::from("C:\\Windows")),
+            name: String::from("a, b.txt"),
+            size: 10,
+            flags: 0x22,
+            si: Some(times),
+            fn_times: Timestamps::default(),
+        };
+        assert_eq!(
+            row.csv(),
+            "42,3,false,false,16,C:\\Windows,\"a, b.txt\",10,Hidden|Archive,\
+             2021-01-01T00:00:00Z,2021-01-01T00:00:01Z,2021-01-01T00:00:00Z,,,,,\r\n"
+        );
+        assert_eq!(
+            row.bodyfile(),
+            "0|C:\\Windows\\a, b.txt (deleted)|42|r/rrwxrwxrwx|0|0|10|0|1609459201|1609459200|1609459200\n\
+             0|C:\\Windows\\a, b.txt (deleted) ($FILE_NAME)|42|r/rrwxrwxrwx|0|0|10|0|0|0|0\n"
+        );
+        let line = row.json();
+        assert_eq!(line["Flags"][1], "Archive");
+        assert_eq!(line["SIModified"], "2021-01-01T00:00:01Z");
+        assert_eq!(line["Timestomped"][0], "ZeroedFractions");
+        row.parent_path = None;
+        assert!(row
+            .bodyfile()
+            .starts_with("0|$OrphanFiles\\a, b.txt (deleted)|"));
+        assert!(row.json()["ParentPath"].is_null());
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, SecondsFormat};
+use glob::{MatchOptions, Pattern};
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use self::timeline::Timeline;
+use crate::ntfs::MFT;
+
+mod cfb;
+mod hiberfil;
+mod jumplist;
+mod lnk;
+mod logfile;
+pub mod mft;
+mod prefetch;
+mod registry;
+pub mod timeline;
+mod usnjrnl;
+mod xpress;
+
+pub struct Parser {
+    pub name: &'static str,
+    pub flags: &'static [&'static str],
+    pub patterns: &'static [&'static str],
+    pub parse: Parse,
+}
+
+pub enum Parse {
+    // Parses a whole file into a line of the parser's JSONL output
+    Json(fn(&[u8]) -> io::Result<JsonValue>),
+    // Converts a file into a separate output file with the given extension,
+    // returns a summary for the parser's JSONL output
+    Convert(
+        fn(&mut dyn Read, &mut dyn Write) -> io::Result<JsonValue>,
+        &'static str,
+    ),
+    // Like Convert, for the MFT of the volume the file is on. Paths start with the given
+    // prefix, like C:.
+    Walk(
+        fn(&mut MFT, &str, &mut dyn Write) -> io::Result<JsonValue>,
+        &'static str,
+    ),
+}
+
+pub const PARSERS: [Parser; 8] = [
+    Parser {
+        name: "hiberfil",
+        flags: &["hiberfile"],
+        patterns: &["hiberfil.sys"],
+        parse: Parse::Convert(hiberfil::convert, "bin"),
+    },
+    Parser {
+        name: "jump-lists",
+        flags: &["jump-lists"],
+        patterns: &["*.automaticDestinations-ms"],
+        parse: Parse::Json(jumplist::parse),
+    },
+    Parser {
+        name: "lnk",
+        flags: &["jump-lists"],
+        patterns: &["*.lnk"],
+        parse: Parse::Json(lnk::parse),
+    },
+    Parser {
+        name: "logfile",
+        flags: &["logfile"],
+        patterns: &["$LogFile"],
+        parse: Parse::Convert(logfile::convert, "jsonl"),
+    },
+    Parser {
+        name: "mft",
+        flags: &["mft"],
+        patterns: &["$MFT"],
+        parse: Parse::Walk(mft::walk, "jsonl"),
+    },
+    Parser {
+        name: "prefetch",
+        flags: &["prefetch"],
+        patterns: &["*.pf"],
+        parse: Parse::Json(prefetch::parse),
+    },
+    Parser {
+        name: "registry-triage",
+        flags: &["registry", "ntuser"],
+        patterns: &["SYSTEM", "SOFTWARE", "NTUSER.DAT"],
+        parse: Parse::Json(registry::triage),
+    },
+    Parser {
+        name: "usnjrnl",
+        flags: &["usnjrnl"],
+        patterns: &["$Extend\\$UsnJrnl:$J"],
+        parse: Parse::Convert(usnjrnl::convert, "jsonl"),
+    },
+];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+pub struct Parsers {
+    outputs: Vec<(&'static Parser, Vec<Pattern>, Vec<u8>)>,
+    // Converted outputs are added as they're written, the rest when the parsers finish
+    timeline: Option<Timeline>,
+}
+
+impl Parsers {
+    pub fn new(names: &[String], timeline: bool) -> Parsers {
+        let outputs = PARSERS
+            .iter()
+            .filter(|p| names.iter().any(|n| n == p.name))
+            .map(|p| {
+                let patterns = p.patterns.iter().map(|x| Pattern::new(x).unwrap());
+                (p, patterns.collect(), Vec::new())
+            })
+            .collect();
+        let timeline = if timeline {
+            Some(Timeline::new())
+        } else {
+            None
+        };
+        Parsers { outputs, timeline }
+    }
+    pub fn wants(&self, file_name: &str) -> bool {
+        self.outputs
+            .iter()
+            .any(|(x, p, _)| matches!(x.parse, Parse::Json(_)) && matches_any(p, file_name))
+    }
+    pub fn converter(&self, file_name: &str) -> Option<&'static Parser> {
+        self.outputs
+            .iter()
+            .find(|(x, p, _)| !matches!(x.parse, Parse::Json(_)) && matches_any(p, file_name))
+            .map(|(x, _, _)| *x)
+    }
+    pub fn feed(&mut self, path: &str, file_name: &str, data: &[u8]) {
+        for (parser, patterns, output) in self.outputs.iter_mut() {
+            let parse = match parser.parse {
+                Parse::Json(parse) => parse,
+                Parse::Convert(..) | Parse::Walk(..) => continue,
+            };
+            if matches_any(patterns, file_name) {
+                push_line(output, path, parse(data));
+            }
+        }
+    }
+    // Records why a file the parsers wanted wasn't parsed.
+    pub fn skip(&mut self, path: &str, file_name: &str, reason: &str) {
+        for (parser, patterns, output) in self.outputs.iter_mut() {
+            if matches!(parser.parse, Parse::Json(_)) && matches_any(patterns, file_name) {
+                let error = io::Error::other(reason);
+                push_line(output, path, Err(error));
+            }
+        }
+    }
+    pub fn converted(&mut self, name: &str, path: &str, result: io::Result<JsonValue>) {
+        for (parser, _, output) in self.outputs.iter_mut() {
+            if parser.name == name {
+                push_line(output, path, result);
+                return;
+            }
+        }
+    }
+    // Adds what a converter wrote to `output` in the archive, from the temporary file at
+    // `path`, to the timeline if there is one.
+    pub fn add_to_timeline(&mut self, name: &str, output: &str, path: &Path) -> io::Result<()> {
+        match &mut self.timeline {
+            Some(timeline) => {
+                let mut lines = BufReader::new(File::open(path)?);
+                timeline.add(name, Some(output), &mut lines)
+            }
+            None => Ok(()),
+        }
+    }
+    pub fn finish(self) -> (Vec<(&'static str, Vec<u8>)>, Option<Timeline>) {
+        let outputs: Vec<(&'static str, Vec<u8>)> = self
+            .outputs
+            .into_iter()
+            .map(|(parser, _, output)| (parser.name, output))
+            .collect();
+        let timeline = self.timeline.map(|mut timeline| {
+            for (name, output) in outputs.iter() {
+                timeline.add(name, None, &mut output.as_slice()).unwrap();
+            }
+            timeline
+        });
+        (outputs, timeline)
+    }
+}
+
+fn push_line(output: &mut Vec<u8>, path: &str, result: io::Result<JsonValue>) {
+    let mut line = JsonValue::new_object();
+    line["Path"] = path.into();
+    match result {
+        Ok(result) => {
+            for (key, value) in result.entries() {
+                line[key] = value.clone();
+            }
+        }
+        Err(e) => line["Error"] = e.to_string().into(),
+    }
+    output.extend_from_slice(line.dump().as_bytes());
+    output.push(b'\n');
+}
+
+fn matches_any(patterns: &[Pattern], file_name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|p| p.matches_with(file_name, MATCH_OPTIONS))
+}
+
+pub fn filetime(time: u64) -> Option<String> {
+    if time == 0 {
+        return None;
+    }
+    let secs = i64::try_from(time / 10_000_000).unwrap() - 11_644_473_600;
+    let nanos = u32::try_from(time % 10_000_000).unwrap() * 100;
+    DateTime::from_timestamp(secs, nanos).map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,95 @@
+use byteorder::{ReadBytesExt, LE};
+use json::JsonValue;
+use std::convert::TryInto;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use super::xpress::decompress_huffman;
+use super::{filetime, invalid};
+
+pub fn parse(data: &[u8]) -> io::Result<JsonValue> {
+    let data = if data.starts_with(b"MAM") {
+        decompress(data)?
+    } else {
+        data.to_vec()
+    };
+    let mut cur = Cursor::new(data.as_slice());
+    let version = cur.read_u32::<LE>()?;
+    let mut sig = [0u8; 4];
+    cur.read_exact(&mut sig)?;
+    if &sig != b"SCCA" {
+        return Err(invalid("No SCCA signature"));
+    }
+    cur.seek(SeekFrom::Start(0x10))?;
+    let mut name = [0u16; 30];
+    cur.read_u16_into::<LE>(&mut name)?;
+    let name_len = name.iter().position(|x| *x == 0).unwrap_or(name.len());
+    let hash = cur.read_u32::<LE>()?;
+    cur.seek(SeekFrom::Start(0x54))?;
+    let metrics_offset = cur.read_u32::<LE>()?;
+    let (run_times, count_offset) = match version {
+        17 => (1, 0x90),
+        23 => (1, 0x98),
+        26 => (8, 0xD0),
+        // Some Windows 10 files have an 8 byte shorter file information block.
+        30 | 31 if metrics_offset == 0x54 + 216 => (8, 0xC8),
+        30 | 31 => (8, 0xD0),
+        _ => return Err(invalid("Unsupported prefetch version")),
+    };
+    cur.seek(SeekFrom::Start(if version == 17 { 0x78 } else { 0x80 }))?;
+    let mut last_run_times = JsonValue::new_array();
+    for _ in 0..run_times {
+        if let Some(time) = filetime(cur.read_u64::<LE>()?) {
+            last_run_times.push(time).unwrap();
+        }
+    }
+    cur.seek(SeekFrom::Start(count_offset))?;
+    let run_count = cur.read_u32::<LE>()?;
+    let mut result = JsonValue::new_object();
+    result["ExecutableName"] = String::from_utf16_lossy(&name[..name_len]).into();
+    result["PrefetchHash"] = format!("{:08X}", hash).into();
+    result["Version"] = version.into();
+    result["RunCount"] = run_count.into();
+    result["LastRunTimes"] = last_run_times;
+    Ok(result)
+}
+
+fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut cur = Cursor::new(data);
+    cur.seek(SeekFrom::Start(3))?;
+    let format = cur.read_u8()?;
+    if format & 0x0F != 4 {
+        return Err(invalid("Unsupported MAM compression format"));
+    }
+    let size = cur.read_u32::<LE>()?;
+    if format & 0x80 != 0 {
+        // Skip the CRC32 of the compressed data
+        cur.seek(SeekFrom::Current(4))?;
+    }
+    let start = cur.position().try_into().unwrap();
+    decompress_huffman(&data[start..], size.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prefetch() {
+        let mut data = vec![0u8; 0x100];
+        data[0..4].copy_from_slice(&30u32.to_le_bytes());
+        data[4..8].copy_from_slice(b"SCCA");
+        for (i, c) in "CMD.EXE".encode_utf16().enumerate() {
+            data[0x10 + i * 2..0x12 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        data[0x4C..0x50].copy_from_slice(&0x0A1B2C3Du32.to_le_bytes());
+        data[0x54..0x58].copy_from_slice(&0x130u32.to_le_bytes());
+        data[0x80..0x88].copy_from_slice(&132539328000000000u64.to_le_bytes());
+        data[0xD0..0xD4].copy_from_slice(&42u32.to_le_bytes());
+        let result = parse(&data).unwrap();
+        assert_eq!(result["ExecutableName"], "CMD.EXE");
+        assert_eq!(result["PrefetchHash"], "0A1B2C3D");
+        assert_eq!(result["RunCount"], 42);
+        assert_eq!(result["LastRunTimes"][0], "2021-01-01T00:00:00Z");
+        assert_eq!(result["LastRunTimes"].len(), 1);
+    }
+}
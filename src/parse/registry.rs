@@ -0,0 +1,431 @@
+use byteorder::{ByteOrder, LE};
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::io;
+
+use super::{filetime, invalid};
+
+const HBIN_START: usize = 4096;
+
+pub struct Hive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Hive<'a> {
+    pub fn new(data: &'a [u8]) -> io::Result<Hive<'a>> {
+        if !data.starts_with(b"regf") || data.len() < HBIN_START {
+            return Err(invalid("No regf signature"));
+        }
+        Ok(Hive { data })
+    }
+    pub fn root(&self) -> io::Result<Key<'a>> {
+        self.key(LE::read_u32(&self.data[0x24..0x28]))
+    }
+    fn cell(&self, offset: u32) -> io::Result<&'a [u8]> {
+        let pos = HBIN_START + usize::try_from(offset).unwrap();
+        let size = self
+            .data
+            .get(pos..pos + 4)
+            .map(LE::read_i32)
+            .ok_or_else(|| invalid("Cell offset out of range"))?;
+        let len = usize::try_from(size.unsigned_abs()).unwrap();
+        if len < 4 {
+            return Err(invalid("Invalid cell size"));
+        }
+        self.data
+            .get(pos + 4..pos + len)
+            .ok_or_else(|| invalid("Cell out of range"))
+    }
+    fn key(&self, offset: u32) -> io::Result<Key<'a>> {
+        let cell = self.cell(offset)?;
+        if cell.len() < 0x4C || &cell[0..2] != b"nk" {
+            return Err(invalid("No nk signature"));
+        }
+        Ok(Key {
+            hive: Hive { data: self.data },
+            cell,
+        })
+    }
+    fn subkey_offsets(&self, list: u32, depth: u8, offsets: &mut Vec<u32>) -> io::Result<()> {
+        let cell = self.cell(list)?;
+        if cell.len() < 4 || depth > 8 {
+            return Err(invalid("Invalid subkey list"));
+        }
+        let count = usize::from(LE::read_u16(&cell[2..4]));
+        let (stride, nested) = match &cell[0..2] {
+            b"lf" | b"lh" => (8, false),
+            b"li" => (4, false),
+            b"ri" => (4, true),
+            _ => return Err(invalid("Unknown subkey list")),
+        };
+        for i in 0..count {
+            let pos = 4 + i * stride;
+            let offset = cell
+                .get(pos..pos + 4)
+                .map(LE::read_u32)
+                .ok_or_else(|| invalid("Subkey list out of range"))?;
+            if nested {
+                self.subkey_offsets(offset, depth + 1, offsets)?;
+            } else {
+                offsets.push(offset);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Key<'a> {
+    hive: Hive<'a>,
+    cell: &'a [u8],
+}
+
+impl<'a> Key<'a> {
+    pub fn name(&self) -> String {
+        let len = usize::from(LE::read_u16(&self.cell[0x48..0x4A]));
+        let compressed = LE::read_u16(&self.cell[2..4]) & 0x20 != 0;
+        decode_name(self.cell.get(0x4C..0x4C + len).unwrap_or(&[]), compressed)
+    }
+    pub fn last_written(&self) -> u64 {
+        LE::read_u64(&self.cell[4..12])
+    }
+    pub fn subkeys(&self) -> io::Result<Vec<Key<'a>>> {
+        let count = LE::read_u32(&self.cell[0x14..0x18]);
+        let mut offsets = Vec::new();
+        if count > 0 {
+            let list = LE::read_u32(&self.cell[0x1C..0x20]);
+            self.hive.subkey_offsets(list, 0, &mut offsets)?;
+        }
+        offsets.into_iter().map(|x| self.hive.key(x)).collect()
+    }
+    pub fn subkey(&self, name: &str) -> io::Result<Option<Key<'a>>> {
+        for key in self.subkeys()? {
+            if key.name().eq_ignore_ascii_case(name) {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+    pub fn path(&self, path: &str) -> io::Result<Option<Key<'a>>> {
+        let mut key = Key {
+            hive: Hive {
+                data: self.hive.data,
+            },
+            cell: self.cell,
+        };
+        for name in path.split('\\') {
+            match key.subkey(name)? {
+                Some(k) => key = k,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(key))
+    }
+    pub fn values(&self) -> io::Result<Vec<Value>> {
+        let count = usize::try_from(LE::read_u32(&self.cell[0x24..0x28])).unwrap();
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let list = self.hive.cell(LE::read_u32(&self.cell[0x28..0x2C]))?;
+        let mut values = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = list
+                .get(i * 4..i * 4 + 4)
+                .map(LE::read_u32)
+                .ok_or_else(|| invalid("Value list out of range"))?;
+            values.push(self.value_at(offset)?);
+        }
+        Ok(values)
+    }
+    pub fn value(&self, name: &str) -> io::Result<Option<Value>> {
+        Ok(self
+            .values()?
+            .into_iter()
+            .find(|v| v.name.eq_ignore_ascii_case(name)))
+    }
+    fn value_at(&self, offset: u32) -> io::Result<Value> {
+        let cell = self.hive.cell(offset)?;
+        if cell.len() < 0x14 || &cell[0..2] != b"vk" {
+            return Err(invalid("No vk signature"));
+        }
+        let name_len = usize::from(LE::read_u16(&cell[2..4]));
+        let size = LE::read_u32(&cell[4..8]);
+        let data_offset = LE::read_u32(&cell[8..12]);
+        let value_type = LE::read_u32(&cell[12..16]);
+        let compressed = LE::read_u16(&cell[16..18]) & 1 != 0;
+        let name = decode_name(cell.get(0x14..0x14 + name_len).unwrap_or(&[]), compressed);
+        let len = usize::try_from(size & 0x7FFF_FFFF).unwrap();
+        let data = if size & 0x8000_0000 != 0 {
+            // Small values are stored in the offset field itself
+            cell[8..12][..len.min(4)].to_vec()
+        } else {
+            let data = self.hive.cell(data_offset)?;
+            if data.starts_with(b"db") && len > data.len() {
+                self.big_data(data, len)?
+            } else {
+                data.get(..len)
+                    .ok_or_else(|| invalid("Value data out of range"))?
+                    .to_vec()
+            }
+        };
+        Ok(Value {
+            name,
+            value_type,
+            data,
+        })
+    }
+    fn big_data(&self, cell: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        let count = usize::from(LE::read_u16(&cell[2..4]));
+        let list = self.hive.cell(LE::read_u32(&cell[4..8]))?;
+        let mut data = Vec::with_capacity(len);
+        for i in 0..count {
+            let offset = list
+                .get(i * 4..i * 4 + 4)
+                .map(LE::read_u32)
+                .ok_or_else(|| invalid("Segment list out of range"))?;
+            let segment = self.hive.cell(offset)?;
+            let remaining = len - data.len();
+            data.extend_from_slice(&segment[..segment.len().min(remaining).min(16344)]);
+        }
+        Ok(data)
+    }
+}
+
+pub struct Value {
+    pub name: String,
+    pub value_type: u32,
+    pub data: Vec<u8>,
+}
+
+impl Value {
+    pub fn to_json(&self) -> JsonValue {
+        match self.value_type {
+            1 | 2 => utf16(&self.data).into(),
+            7 => utf16(&self.data)
+                .split('\0')
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>()
+                .into(),
+            4 if self.data.len() >= 4 => LE::read_u32(&self.data).into(),
+            11 if self.data.len() >= 8 => LE::read_u64(&self.data).into(),
+            _ => hex(&self.data).into(),
+        }
+    }
+}
+
+fn decode_name(bytes: &[u8], compressed: bool) -> String {
+    if compressed {
+        bytes.iter().map(|b| char::from(*b)).collect()
+    } else {
+        utf16(bytes)
+    }
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let chars: Vec<u16> = bytes.chunks_exact(2).map(LE::read_u16).collect();
+    let len = chars.iter().position(|x| *x == 0).unwrap_or(chars.len());
+    String::from_utf16_lossy(&chars[..len])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+const AUTORUN_KEYS: [&str; 6] = [
+    r#"Microsoft\Windows\CurrentVersion\Run"#,
+    r#"Microsoft\Windows\CurrentVersion\RunOnce"#,
+    r#"Wow6432Node\Microsoft\Windows\CurrentVersion\Run"#,
+    r#"Wow6432Node\Microsoft\Windows\CurrentVersion\RunOnce"#,
+    r#"Software\Microsoft\Windows\CurrentVersion\Run"#,
+    r#"Software\Microsoft\Windows\CurrentVersion\RunOnce"#,
+];
+
+const SERVICE_VALUES: [&str; 5] = ["DisplayName", "ImagePath", "Start", "Type", "ObjectName"];
+
+pub fn triage(data: &[u8]) -> io::Result<JsonValue> {
+    let hive = Hive::new(data)?;
+    let root = hive.root()?;
+    let mut result = JsonValue::new_object();
+    let mut run = JsonValue::new_array();
+    for path in AUTORUN_KEYS.iter() {
+        if let Some(key) = root.path(path)? {
+            run.push(key_values(path, &key)?).unwrap();
+        }
+    }
+    if !run.is_empty() {
+        result["Run"] = run;
+    }
+    let typed_paths = r#"Software\Microsoft\Windows\CurrentVersion\Explorer\TypedPaths"#;
+    if let Some(key) = root.path(typed_paths)? {
+        result["TypedPaths"] = key_values(typed_paths, &key)?;
+    }
+    let recent_docs = r#"Software\Microsoft\Windows\CurrentVersion\Explorer\RecentDocs"#;
+    if let Some(key) = root.path(recent_docs)? {
+        result["RecentDocs"] = recent(&key)?;
+    }
+    if let Some(key) = root.subkey("MountedDevices")? {
+        let mut devices = JsonValue::new_object();
+        for value in key.values()? {
+            devices[value.name.as_str()] = mounted_device(&value.data).into();
+        }
+        result["MountedDevices"] = devices;
+    }
+    if let Some(services) = current_control_set(&root)? {
+        if let Some(services) = services.subkey("Services")? {
+            result["Services"] = JsonValue::new_array();
+            for service in services.subkeys()? {
+                let mut entry = JsonValue::new_object();
+                entry["Name"] = service.name().into();
+                entry["LastWritten"] = filetime(service.last_written()).into();
+                for name in SERVICE_VALUES.iter() {
+                    if let Some(value) = service.value(name)? {
+                        entry[*name] = value.to_json();
+                    }
+                }
+                result["Services"].push(entry).unwrap();
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn key_values(path: &str, key: &Key) -> io::Result<JsonValue> {
+    let mut values = JsonValue::new_object();
+    for value in key.values()? {
+        values[value.name.as_str()] = value.to_json();
+    }
+    let mut result = JsonValue::new_object();
+    result["Key"] = path.into();
+    result["LastWritten"] = filetime(key.last_written()).into();
+    result["Values"] = values;
+    Ok(result)
+}
+
+// RecentDocs values start with the UTF-16 file name, MRUListEx holds the order.
+fn recent(key: &Key) -> io::Result<JsonValue> {
+    let values = key.values()?;
+    let mut result = JsonValue::new_array();
+    if let Some(mru) = values.iter().find(|v| v.name == "MRUListEx") {
+        for idx in mru.data.chunks_exact(4).map(LE::read_u32) {
+            if idx == u32::MAX {
+                break;
+            }
+            if let Some(value) = values.iter().find(|v| v.name == idx.to_string()) {
+                result.push(utf16(&value.data)).unwrap();
+            }
+        }
+    }
+    let mut entry = key_values("RecentDocs", key)?;
+    entry.remove("Values");
+    entry["Documents"] = result;
+    Ok(entry)
+}
+
+fn mounted_device(data: &[u8]) -> String {
+    if data.len() > 12 && data.len().is_multiple_of(2) {
+        utf16(data)
+    } else {
+        hex(data)
+    }
+}
+
+fn current_control_set<'a>(root: &Key<'a>) -> io::Result<Option<Key<'a>>> {
+    let current = match root.subkey("Select")? {
+        Some(select) => select.value("Current")?,
+        None => return Ok(None),
+    };
+    match current.and_then(|v| v.data.get(0..4).map(LE::read_u32)) {
+        Some(n) => root.subkey(&format!("ControlSet{:03}", n)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    struct Builder {
+        data: Vec<u8>,
+    }
+
+    impl Builder {
+        fn cell(&mut self, content: &[u8]) -> u32 {
+            let offset = (self.data.len() - HBIN_START).try_into().unwrap();
+            let size = (content.len() + 4).next_multiple_of(8);
+            self.data
+                .extend_from_slice(&(-i32::try_from(size).unwrap()).to_le_bytes());
+            self.data.extend_from_slice(content);
+            self.data
+                .resize(self.data.len() + size - 4 - content.len(), 0);
+            offset
+        }
+        fn key(&mut self, name: &str, subkeys: &[u32], values: &[u32]) -> u32 {
+            let mut nk = vec![0u8; 0x4C];
+            nk[0..2].copy_from_slice(b"nk");
+            LE::write_u16(&mut nk[2..4], 0x20);
+            LE::write_u64(&mut nk[4..12], 132539328000000000);
+            if !subkeys.is_empty() {
+                let mut list = b"li".to_vec();
+                list.extend_from_slice(&u16::try_from(subkeys.len()).unwrap().to_le_bytes());
+                subkeys
+                    .iter()
+                    .for_each(|x| list.extend_from_slice(&x.to_le_bytes()));
+                let list = self.cell(&list);
+                LE::write_u32(&mut nk[0x14..0x18], subkeys.len().try_into().unwrap());
+                LE::write_u32(&mut nk[0x1C..0x20], list);
+            }
+            if !values.is_empty() {
+                let mut list = Vec::new();
+                values
+                    .iter()
+                    .for_each(|x| list.extend_from_slice(&x.to_le_bytes()));
+                let list = self.cell(&list);
+                LE::write_u32(&mut nk[0x24..0x28], values.len().try_into().unwrap());
+                LE::write_u32(&mut nk[0x28..0x2C], list);
+            }
+            LE::write_u16(&mut nk[0x48..0x4A], name.len().try_into().unwrap());
+            nk.extend_from_slice(name.as_bytes());
+            self.cell(&nk)
+        }
+        fn string_value(&mut self, name: &str, data: &str) -> u32 {
+            let mut bytes: Vec<u8> = data.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+            bytes.extend_from_slice(&[0, 0]);
+            let data_offset = self.cell(&bytes);
+            let mut vk = vec![0u8; 0x14];
+            vk[0..2].copy_from_slice(b"vk");
+            LE::write_u16(&mut vk[2..4], name.len().try_into().unwrap());
+            LE::write_u32(&mut vk[4..8], bytes.len().try_into().unwrap());
+            LE::write_u32(&mut vk[8..12], data_offset);
+            LE::write_u32(&mut vk[12..16], 1);
+            LE::write_u16(&mut vk[16..18], 1);
+            vk.extend_from_slice(name.as_bytes());
+            self.cell(&vk)
+        }
+    }
+
+    #[test]
+    fn test_triage_run_key() {
+        let mut builder = Builder {
+            data: vec![0u8; HBIN_START],
+        };
+        builder.data[0..4].copy_from_slice(b"regf");
+        let value = builder.string_value("Updater", r#"C:\evil.exe"#);
+        let run = builder.key("Run", &[], &[value]);
+        let mut parent = run;
+        for name in ["CurrentVersion", "Windows", "Microsoft", "Software"].iter() {
+            parent = builder.key(name, &[parent], &[]);
+        }
+        let root = builder.key("ROOT", &[parent], &[]);
+        LE::write_u32(&mut builder.data[0x24..0x28], root);
+
+        let result = triage(&builder.data).unwrap();
+        assert_eq!(result["Run"].len(), 1);
+        let run = &result["Run"][0];
+        assert_eq!(
+            run["Key"],
+            r#"Software\Microsoft\Windows\CurrentVersion\Run"#
+        );
+        assert_eq!(run["Values"]["Updater"], r#"C:\evil.exe"#);
+        assert_eq!(run["LastWritten"], "2021-01-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,228 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use json::JsonValue;
+use std::io::{self, BufRead, Write};
+
+use super::mft::{join, TIMES};
+
+struct Event {
+    time: DateTime<FixedOffset>,
+    source: &'static str,
+    kind: String,
+    path: String,
+    description: String,
+}
+
+type Extract = fn(&JsonValue, &mut dyn FnMut(&JsonValue, &str, String));
+
+// Maps each parser output to the timestamps it contributes.
+const EXTRACTORS: [(&str, Extract); 4] = [
+    ("prefetch", |line, add| {
+        for time in line["LastRunTimes"].members() {
+            add(time, "Executed", text(&line["ExecutableName"]));
+        }
+    }),
+    ("lnk", |line, add| {
+        let target = text(&line["TargetPath"]);
+        add(&line["TargetCreated"], "Target created", target.clone());
+        add(&line["TargetModified"], "Target modified", target.clone());
+        add(&line["TargetAccessed"], "Target accessed", target);
+    }),
+    ("jump-lists", |line, add| {
+        for entry in line["Entries"].members() {
+            add(&entry["LastModified"], "Opened", text(&entry["Path"]));
+        }
+    }),
+    ("registry-triage", |line, add| {
+        for key in line["Run"].members() {
+            add(&key["LastWritten"], "Key written", text(&key["Key"]));
+        }
+        for service in line["Services"].members() {
+            let name = format!("Service {}", text(&service["Name"]));
+            add(&service["LastWritten"], "Key written", name);
+        }
+    }),
+];
+
+// The same for what the converters write, a line per MFT entry name or USN record.
+const CONVERTED: [(&str, Extract); 2] = [
+    ("mft", |line, add| {
+        let mut name = match line["ParentPath"].as_str() {
+            Some(dir) => join(dir, &text(&line["Name"])),
+            None => text(&line["Name"]),
+        };
+        if line["InUse"] == false {
+            name = name + " (deleted)";
+        }
+        let reasons: Vec<&str> = line["Timestomped"]
+            .members()
+            .filter_map(|x| x.as_str())
+            .collect();
+        if !reasons.is_empty() {
+            name = format!("{} (timestomped: {})", name, reasons.join(", "));
+        }
+        // Times that are the same go in one event, like the MACB columns of mactime
+        for kind in ["SI", "FN"].iter() {
+            let mut times: Vec<(&JsonValue, Vec<&str>)> = Vec::new();
+            for time_name in TIMES.iter() {
+                let time = &line[format!("{}{}", kind, time_name)];
+                match times.iter_mut().find(|(x, _)| *x == time) {
+                    Some((_, names)) => names.push(time_name),
+                    None => times.push((time, vec![time_name])),
+                }
+            }
+            for (time, names) in times {
+                add(
+                    time,
+                    &format!("{} {}", kind, names.join(", ")),
+                    name.clone(),
+                );
+            }
+        }
+    }),
+    ("usnjrnl", |line, add| {
+        let reasons: Vec<&str> = line["Reasons"]
+            .members()
+            .filter_map(|x| x.as_str())
+            .collect();
+        let name = format!("{} (entry {})", text(&line["Name"]), line["Entry"]);
+        add(&line["Time"], &format!("USN {}", reasons.join(", ")), name);
+    }),
+];
+
+// Merges parser outputs into one timeline, sorted by time when it's written.
+pub struct Timeline {
+    events: Vec<Event>,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline { events: Vec::new() }
+    }
+    // Adds the events in the JSONL output of a parser. That's the summary with a line
+    // per parsed file, or with `output` set, what a converter wrote to that file in the
+    // archive (like parsed\mft-C.jsonl) which is then the path of the events.
+    pub fn add(
+        &mut self,
+        name: &str,
+        output: Option<&str>,
+        lines: &mut dyn BufRead,
+    ) -> io::Result<()> {
+        let extractors = if output.is_some() {
+            &CONVERTED[..]
+        } else {
+            &EXTRACTORS[..]
+        };
+        let (source, extract) = match extractors.iter().find(|(x, _)| *x == name) {
+            Some(extractor) => extractor,
+            None => return Ok(()),
+        };
+        for line in lines.lines() {
+            let line = match json::parse(&line?) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+            let path = output.map_or_else(|| text(&line["Path"]), String::from);
+            let events = &mut self.events;
+            extract(&line, &mut |time, kind, description| {
+                if let Some(time) = time.as_str().and_then(|x| x.parse().ok()) {
+                    events.push(Event {
+                        time,
+                        source,
+                        kind: String::from(kind),
+                        path: path.clone(),
+                        description,
+                    });
+                }
+            });
+        }
+        Ok(())
+    }
+    pub fn write(mut self, jsonl: &mut dyn Write, csv: &mut dyn Write) -> io::Result<()> {
+        self.events.sort_by_key(|x| x.time);
+        csv.write_all(b"Time,Source,Event,Description,Path\r\n")?;
+        for event in self.events {
+            let time = event.time.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+            let mut line = JsonValue::new_object();
+            line["Time"] = time.as_str().into();
+            line["Source"] = event.source.into();
+            line["Event"] = event.kind.as_str().into();
+            line["Description"] = event.description.as_str().into();
+            line["Path"] = event.path.as_str().into();
+            jsonl.write_all(line.dump().as_bytes())?;
+            jsonl.write_all(b"\n")?;
+            let fields = [
+                time.as_str(),
+                event.source,
+                &event.kind,
+                &event.description,
+                &event.path,
+            ];
+            let fields: Vec<String> = fields.iter().map(|x| csv_field(x)).collect();
+            csv.write_all(fields.join(",").as_bytes())?;
+            csv.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+fn text(value: &JsonValue) -> String {
+    String::from(value.as_str().unwrap_or(""))
+}
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let prefetch = br#"{"Path":"C\\a.pf","ExecutableName":"A.EXE","LastRunTimes":["2021-01-02T00:00:00Z","2021-01-01T00:00:00Z"]}"#;
+        let lnk = br#"{"Path":"C\\b.lnk","TargetPath":"C:\\b, c.txt","TargetModified":"2021-01-01T12:00:00Z"}"#;
+        let mft = br#"{"ParentPath":"C:\\Temp","Name":"evil.exe","InUse":false,"SICreated":"2020-12-01T00:00:00Z","SIModified":"2020-12-01T00:00:00Z","FNCreated":"2021-01-01T06:00:00Z","Timestomped":["SICreatedBeforeFN"]}"#;
+        let usnjrnl = br#"{"Time":"2021-01-01T06:00:00Z","Entry":42,"Name":"evil.exe","Reasons":["FileCreate"]}"#;
+        let mut timeline = Timeline::new();
+        timeline.add("prefetch", None, &mut &prefetch[..]).unwrap();
+        timeline.add("lnk", None, &mut &lnk[..]).unwrap();
+        timeline
+            .add("mft", Some("parsed\\mft-C.jsonl"), &mut &mft[..])
+            .unwrap();
+        timeline
+            .add(
+                "usnjrnl",
+                Some("parsed\\usnjrnl-C.jsonl"),
+                &mut &usnjrnl[..],
+            )
+            .unwrap();
+        // Only the converted output of these has times
+        timeline.add("mft", None, &mut &mft[..]).unwrap();
+        let (mut jsonl, mut csv) = (Vec::new(), Vec::new());
+        timeline.write(&mut jsonl, &mut csv).unwrap();
+        let lines: Vec<JsonValue> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|x| json::parse(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0]["Event"], "SI Created, Modified");
+        assert_eq!(
+            lines[0]["Description"],
+            "C:\\Temp\\evil.exe (deleted) (timestomped: SICreatedBeforeFN)"
+        );
+        assert_eq!(lines[0]["Path"], "parsed\\mft-C.jsonl");
+        assert_eq!(lines[1]["Description"], "A.EXE");
+        assert_eq!(lines[2]["Event"], "FN Created");
+        assert_eq!(lines[3]["Event"], "USN FileCreate");
+        assert_eq!(lines[3]["Description"], "evil.exe (entry 42)");
+        assert_eq!(lines[4]["Source"], "lnk");
+        assert_eq!(lines[5]["Time"], "2021-01-02T00:00:00Z");
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains(r#","C:\b, c.txt","#));
+    }
+}
@@ -0,0 +1,172 @@
+use byteorder::{ByteOrder, LE};
+use json::JsonValue;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use super::filetime;
+
+// Records are padded to this, and the unused end of each page is zero filled.
+const ALIGNMENT: usize = 8;
+const HEADER_SIZE: usize = 0x3C;
+// Anything longer is garbage, names are at most 255 UTF-16 characters
+const MAX_RECORD: usize = 0x1000;
+
+const REASONS: [(u32, &str); 23] = [
+    (0x1, "DataOverwrite"),
+    (0x2, "DataExtend"),
+    (0x4, "DataTruncation"),
+    (0x10, "NamedDataOverwrite"),
+    (0x20, "NamedDataExtend"),
+    (0x40, "NamedDataTruncation"),
+    (0x100, "FileCreate"),
+    (0x200, "FileDelete"),
+    (0x400, "EaChange"),
+    (0x800, "SecurityChange"),
+    (0x1000, "RenameOldName"),
+    (0x2000, "RenameNewName"),
+    (0x4000, "IndexableChange"),
+    (0x8000, "BasicInfoChange"),
+    (0x10000, "HardLinkChange"),
+    (0x20000, "CompressionChange"),
+    (0x40000, "EncryptionChange"),
+    (0x80000, "ObjectIdChange"),
+    (0x100000, "ReparsePointChange"),
+    (0x200000, "StreamChange"),
+    (0x400000, "TransactedChange"),
+    (0x800000, "IntegrityChange"),
+    (0x80000000, "Close"),
+];
+
+// Writes a JSONL line for every USN_RECORD_V2 in the $J stream of the USN journal.
+// Records of other versions are counted but skipped, zeroed space between them is
+// stepped over.
+pub fn convert(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<JsonValue> {
+    let mut buf = vec![0u8; MAX_RECORD];
+    let mut records = 0u64;
+    let mut skipped = 0u64;
+    let mut first = JsonValue::Null;
+    let mut last = JsonValue::Null;
+    loop {
+        match input.read_exact(&mut buf[..ALIGNMENT]) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let len = usize::try_from(LE::read_u32(&buf[0..4])).unwrap();
+        if !(HEADER_SIZE..=MAX_RECORD).contains(&len) || len % ALIGNMENT != 0 {
+            continue;
+        }
+        match input.read_exact(&mut buf[ALIGNMENT..len]) {
+            Ok(()) => (),
+            // The journal ends in the middle of the last record
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if LE::read_u16(&buf[4..6]) != 2 {
+            skipped = skipped + 1;
+            continue;
+        }
+        let line = parse_record(&buf[..len]);
+        if first.is_null() {
+            first = line["Usn"].clone();
+        }
+        last = line["Usn"].clone();
+        output.write_all(line.dump().as_bytes())?;
+        output.write_all(b"\n")?;
+        records = records + 1;
+    }
+    let mut result = JsonValue::new_object();
+    result["Records"] = records.into();
+    result["Skipped"] = skipped.into();
+    result["FirstUsn"] = first;
+    result["LastUsn"] = last;
+    Ok(result)
+}
+
+fn parse_record(record: &[u8]) -> JsonValue {
+    let file = LE::read_u64(&record[0x08..0x10]);
+    let parent = LE::read_u64(&record[0x10..0x18]);
+    let reason = LE::read_u32(&record[0x28..0x2C]);
+    let reasons: Vec<&str> = REASONS
+        .iter()
+        .filter(|(x, _)| reason & x != 0)
+        .map(|(_, x)| *x)
+        .collect();
+    let name_len = usize::from(LE::read_u16(&record[0x38..0x3A]));
+    let name_offset = usize::from(LE::read_u16(&record[0x3A..0x3C]));
+    let name: Vec<u16> = record
+        .get(name_offset..name_offset + name_len)
+        .unwrap_or(&[])
+        .chunks_exact(2)
+        .map(LE::read_u16)
+        .collect();
+    let mut line = JsonValue::new_object();
+    line["Usn"] = LE::read_u64(&record[0x18..0x20]).into();
+    line["Time"] = filetime(LE::read_u64(&record[0x20..0x28])).into();
+    line["Entry"] = (file & 0xFFFF_FFFF_FFFF).into();
+    line["Sequence"] = (file >> 48).into();
+    line["ParentEntry"] = (parent & 0xFFFF_FFFF_FFFF).into();
+    line["ParentSequence"] = (parent >> 48).into();
+    line["Name"] = String::from_utf16_lossy(&name).into();
+    line["Reasons"] = reasons.into();
+    line["Attributes"] = LE::read_u32(&record[0x34..0x38]).into();
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str;
+
+    fn record(usn: u64, reason: u32, name: &str) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_SIZE];
+        buf[4..6].copy_from_slice(&2u16.to_le_bytes());
+        buf[0x08..0x10].copy_from_slice(&(42u64 | 3 << 48).to_le_bytes());
+        buf[0x10..0x18].copy_from_slice(&(5u64 | 5 << 48).to_le_bytes());
+        buf[0x18..0x20].copy_from_slice(&usn.to_le_bytes());
+        buf[0x20..0x28].copy_from_slice(&132539328000000000u64.to_le_bytes());
+        buf[0x28..0x2C].copy_from_slice(&reason.to_le_bytes());
+        buf[0x34..0x38].copy_from_slice(&0x20u32.to_le_bytes());
+        name.encode_utf16()
+            .for_each(|c| buf.extend_from_slice(&c.to_le_bytes()));
+        let name_len = u16::try_from(buf.len() - HEADER_SIZE).unwrap();
+        buf[0x38..0x3A].copy_from_slice(&name_len.to_le_bytes());
+        buf[0x3A..0x3C].copy_from_slice(&0x3Cu16.to_le_bytes());
+        buf.resize(buf.len().next_multiple_of(ALIGNMENT), 0);
+        let len = u32::try_from(buf.len()).unwrap();
+        buf[0..4].copy_from_slice(&len.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_convert() {
+        let mut data = vec![0u8; 64];
+        data.extend(record(64, 0x100, "new.txt"));
+        let mut v3 = record(0, 0, "v3.txt");
+        v3[4..6].copy_from_slice(&3u16.to_le_bytes());
+        data.extend(v3);
+        data.extend(vec![0u8; 16]);
+        let usn = u64::try_from(data.len()).unwrap();
+        data.extend(record(usn, 0x80000200, "new.txt"));
+        // A record cut off at the end of the stream
+        data.extend(&record(0, 0, "cut.txt")[..0x40]);
+
+        let mut output = Vec::new();
+        let result = convert(&mut data.as_slice(), &mut output).unwrap();
+        assert_eq!(result["Records"], 2);
+        assert_eq!(result["Skipped"], 1);
+        assert_eq!(result["FirstUsn"], 64);
+        assert_eq!(result["LastUsn"], usn);
+
+        let lines: Vec<&str> = str::from_utf8(&output).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        let line = json::parse(lines[1]).unwrap();
+        assert_eq!(line["Name"], "new.txt");
+        assert_eq!(line["Entry"], 42);
+        assert_eq!(line["Sequence"], 3);
+        assert_eq!(line["ParentEntry"], 5);
+        assert_eq!(line["Time"], "2021-01-01T00:00:00Z");
+        assert_eq!(line["Reasons"][0], "FileDelete");
+        assert_eq!(line["Reasons"][1], "Close");
+    }
+}
@@ -0,0 +1,247 @@
+use byteorder::{ByteOrder, LE};
+use std::convert::{TryFrom, TryInto};
+use std::io;
+
+use super::invalid;
+
+const BLOCK_SIZE: usize = 65536;
+
+pub fn decompress_huffman(input: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+    while output.len() < size {
+        let lengths = input
+            .get(pos..pos + 256)
+            .ok_or_else(|| invalid("Truncated Huffman table"))?;
+        let table = decoding_table(lengths)?;
+        let mut bits = BitReader::new(input, pos + 256);
+        let block_end = size.min(output.len() + BLOCK_SIZE);
+        while output.len() < block_end {
+            let (symbol, len) = table[usize::try_from(bits.peek(15)).unwrap()];
+            if len == 0 {
+                return Err(invalid("Invalid Huffman code"));
+            }
+            bits.skip(len.into());
+            if symbol < 256 {
+                output.push(symbol.try_into().unwrap());
+                continue;
+            }
+            let symbol = symbol - 256;
+            let mut match_len = usize::from(symbol % 16);
+            let offset_bits = u32::from(symbol / 16);
+            if match_len == 15 {
+                match_len = bits.read_byte().into();
+                if match_len == 255 {
+                    match_len = bits.read_u16().into();
+                    if match_len == 0 {
+                        match_len = bits.read_u32().try_into().unwrap();
+                    }
+                    if match_len < 15 {
+                        return Err(invalid("Invalid match length"));
+                    }
+                    match_len = match_len - 15;
+                }
+                match_len = match_len + 15;
+            }
+            match_len = match_len + 3;
+            let offset = usize::try_from(bits.peek(offset_bits) + (1 << offset_bits)).unwrap();
+            bits.skip(offset_bits);
+            if offset > output.len() {
+                return Err(invalid("Match offset out of range"));
+            }
+            for _ in 0..match_len.min(size - output.len()) {
+                output.push(output[output.len() - offset]);
+            }
+        }
+        pos = bits.pos;
+    }
+    Ok(output)
+}
+
+pub fn decompress_plain(input: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+    let mut flags = 0u32;
+    let mut flag_count = 0;
+    let mut half_byte = None;
+    let byte_at = |pos: usize| {
+        input
+            .get(pos)
+            .copied()
+            .ok_or_else(|| invalid("Truncated Xpress input"))
+    };
+    while output.len() < size {
+        if flag_count == 0 {
+            if pos + 4 > input.len() {
+                break;
+            }
+            flags = LE::read_u32(&input[pos..pos + 4]);
+            pos = pos + 4;
+            flag_count = 32;
+        }
+        flag_count = flag_count - 1;
+        if flags & (1 << flag_count) == 0 {
+            if pos >= input.len() {
+                break;
+            }
+            output.push(input[pos]);
+            pos = pos + 1;
+            continue;
+        }
+        if pos + 2 > input.len() {
+            break;
+        }
+        let match_bytes = usize::from(LE::read_u16(&input[pos..pos + 2]));
+        pos = pos + 2;
+        let mut match_len = match_bytes % 8;
+        let offset = match_bytes / 8 + 1;
+        if match_len == 7 {
+            // Extra lengths share a byte between two matches, one nibble each
+            match_len = match half_byte.take() {
+                None => {
+                    half_byte = Some(pos);
+                    pos = pos + 1;
+                    usize::from(byte_at(pos - 1)? % 16)
+                }
+                Some(half) => usize::from(byte_at(half)? / 16),
+            };
+            if match_len == 15 {
+                match_len = byte_at(pos)?.into();
+                pos = pos + 1;
+                if match_len == 255 {
+                    match_len =
+                        usize::from(u16::from(byte_at(pos)?) | u16::from(byte_at(pos + 1)?) << 8);
+                    pos = pos + 2;
+                    if match_len == 0 {
+                        let bytes = input
+                            .get(pos..pos + 4)
+                            .ok_or_else(|| invalid("Truncated Xpress input"))?;
+                        match_len = usize::try_from(LE::read_u32(bytes)).unwrap();
+                        pos = pos + 4;
+                    }
+                    if match_len < 15 + 7 {
+                        return Err(invalid("Invalid match length"));
+                    }
+                    match_len = match_len - 22;
+                }
+                match_len = match_len + 15;
+            }
+            match_len = match_len + 7;
+        }
+        match_len = match_len + 3;
+        if offset > output.len() {
+            return Err(invalid("Match offset out of range"));
+        }
+        for _ in 0..match_len.min(size - output.len()) {
+            output.push(output[output.len() - offset]);
+        }
+    }
+    Ok(output)
+}
+
+fn decoding_table(lengths: &[u8]) -> io::Result<Vec<(u16, u8)>> {
+    let mut table = Vec::with_capacity(1 << 15);
+    for len in 1..16u8 {
+        for symbol in 0..512u16 {
+            let byte = lengths[usize::from(symbol / 2)];
+            let symbol_len = if symbol % 2 == 0 {
+                byte & 15
+            } else {
+                byte >> 4
+            };
+            if symbol_len == len {
+                let count = 1 << (15 - len);
+                if table.len() + count > 1 << 15 {
+                    return Err(invalid("Invalid Huffman table"));
+                }
+                table.resize(table.len() + count, (symbol, len));
+            }
+        }
+    }
+    table.resize(1 << 15, (0, 0));
+    Ok(table)
+}
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    next: u32,
+    extra: i32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(input: &'a [u8], pos: usize) -> BitReader<'a> {
+        let mut reader = BitReader {
+            input,
+            pos,
+            next: 0,
+            extra: 16,
+        };
+        reader.next = u32::from(reader.read_u16()) << 16 | u32::from(reader.read_u16());
+        reader
+    }
+    fn peek(&self, n: u32) -> u32 {
+        if n == 0 {
+            0
+        } else {
+            self.next >> (32 - n)
+        }
+    }
+    fn skip(&mut self, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.next = self.next << n;
+        self.extra = self.extra - i32::try_from(n).unwrap();
+        if self.extra < 0 {
+            self.next = self.next | u32::from(self.read_u16()) << -self.extra;
+            self.extra = self.extra + 16;
+        }
+    }
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.input.get(self.pos).copied().unwrap_or(0);
+        self.pos = self.pos + 1;
+        byte
+    }
+    fn read_u16(&mut self) -> u16 {
+        u16::from(self.read_byte()) | u16::from(self.read_byte()) << 8
+    }
+    fn read_u32(&mut self) -> u32 {
+        u32::from(self.read_u16()) | u32::from(self.read_u16()) << 16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_literals() {
+        // Every literal has an 8 bit code equal to its value.
+        let mut input = vec![0x88u8; 128];
+        input.resize(256, 0);
+        input.extend_from_slice(&[b'b', b'a', b'd', b'c', 0, 0, 0, 0]);
+        assert_eq!(decompress_huffman(&input, 4).unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn test_decompress_plain() {
+        // Three literals followed by a match of length 4 at offset 2
+        let flags = 1u32 << 28;
+        let mut input = flags.to_le_bytes().to_vec();
+        input.extend_from_slice(b"abc");
+        input.extend_from_slice(&((1u16 << 3) | 1).to_le_bytes());
+        assert_eq!(decompress_plain(&input, 7).unwrap(), b"abcbcbc");
+    }
+
+    #[test]
+    fn test_decompress_match() {
+        // 'a' = 0, 'b' = 10, match (length 3, 1 offset bit) = 11
+        let mut input = vec![0u8; 256];
+        input[48] = 0x10;
+        input[49] = 0x02;
+        input[136] = 0x02;
+        input.extend_from_slice(&[0x00, 0x58, 0, 0, 0, 0]);
+        assert_eq!(decompress_huffman(&input, 5).unwrap(), b"ababa");
+    }
+}
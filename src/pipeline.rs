@@ -0,0 +1,428 @@
+use flate2::{write::GzEncoder, Compression};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::stats::COMPRESSION;
+
+const BLOCK_SIZE: usize = 1024 * 1024;
+const READ_AHEAD_BLOCKS: usize = 4;
+const MAX_POOLED: usize = 64;
+// Smaller upload chunks would mostly be request overhead
+const MIN_UPLOAD_CHUNK: usize = 64 * 1024;
+
+pub const DEFAULT_CHUNK_SIZE: usize = BLOCK_SIZE;
+
+// The size of the blocks read ahead and compressed, the optimum depends on the storage.
+static CHUNK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_CHUNK_SIZE);
+
+pub fn set_chunk_size(bytes: usize) {
+    CHUNK_SIZE.store(bytes.max(4096), Ordering::Relaxed);
+}
+
+fn chunk_size() -> usize {
+    CHUNK_SIZE.load(Ordering::Relaxed)
+}
+
+// Zero means no limit. Otherwise the queues, thread counts and upload chunks below
+// are sized so the blocks in flight stay within the limit.
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_memory_limit(bytes: usize) {
+    MEMORY_LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+// The largest file that may be held in memory as a whole, e.g. for parsing.
+pub fn max_buffered() -> Option<usize> {
+    Budget::current().max_buffered()
+}
+
+// The size of the chunks an HTTP upload holds on to, to resend after a failure.
+pub fn upload_chunk_size(default: usize) -> usize {
+    Budget::current().upload_chunk_size(default)
+}
+
+fn read_ahead_blocks() -> usize {
+    Budget::current().read_ahead_blocks()
+}
+
+fn compression_threads() -> usize {
+    let threads = thread::available_parallelism().map_or(2, |x| x.get());
+    Budget::current().compression_threads(threads)
+}
+
+// How the memory limit is split, a quarter each goes to files held as a whole,
+// read-ahead, compression and upload chunks.
+#[derive(Clone, Copy)]
+struct Budget {
+    limit: usize,
+    chunk_size: usize,
+}
+
+impl Budget {
+    fn current() -> Budget {
+        Budget {
+            limit: MEMORY_LIMIT.load(Ordering::Relaxed),
+            chunk_size: chunk_size(),
+        }
+    }
+
+    fn max_buffered(self) -> Option<usize> {
+        match self.limit {
+            0 => None,
+            limit => Some(limit / 4),
+        }
+    }
+
+    fn read_ahead_blocks(self) -> usize {
+        match self.limit {
+            0 => READ_AHEAD_BLOCKS,
+            limit => (limit / 4 / self.chunk_size).clamp(1, READ_AHEAD_BLOCKS),
+        }
+    }
+
+    // Every compression thread has at most four blocks queued, compressing or
+    // waiting to be written.
+    fn compression_threads(self, threads: usize) -> usize {
+        match self.limit {
+            0 => threads,
+            limit => (limit / 4 / (4 * self.chunk_size)).clamp(1, threads),
+        }
+    }
+
+    fn upload_chunk_size(self, default: usize) -> usize {
+        match self.limit {
+            0 => default,
+            limit => (limit / 4).clamp(MIN_UPLOAD_CHUNK.min(default), default),
+        }
+    }
+}
+
+// Buffers that are done with go back here so copying tens of thousands of files
+// doesn't allocate a new block for every read and compression job.
+static POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+// Returns an empty buffer with at least the requested capacity.
+pub fn take_buffer(capacity: usize) -> Vec<u8> {
+    let mut pool = POOL.lock().unwrap();
+    let fit = pool
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.capacity() >= capacity)
+        .min_by_key(|(_, x)| x.capacity())
+        .map(|(idx, _)| idx);
+    match fit {
+        Some(idx) => pool.swap_remove(idx),
+        None => Vec::with_capacity(capacity),
+    }
+}
+
+pub fn return_buffer(mut buf: Vec<u8>) {
+    let mut pool = POOL.lock().unwrap();
+    // Buffers that grew to hold a whole hive or similar are freed instead
+    let max_capacity = 4 * chunk_size();
+    if buf.capacity() > 0 && buf.capacity() <= max_capacity && pool.len() < MAX_POOLED {
+        buf.clear();
+        pool.push(buf);
+    }
+}
+
+// Reads the inner reader on a separate thread so device reads overlap with the
+// hashing and compression of the previous blocks.
+pub struct ReadAhead {
+    blocks: Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl ReadAhead {
+    pub fn new<R: Read + Send + 'static>(mut inner: R) -> ReadAhead {
+        let (sender, blocks) = sync_channel(read_ahead_blocks());
+        let chunk_size = chunk_size();
+        thread::spawn(move || loop {
+            let mut block = take_buffer(chunk_size);
+            block.resize(chunk_size, 0);
+            let result = read_block(&mut inner, &mut block).map(|n| {
+                block.truncate(n);
+                block
+            });
+            let last = result.as_ref().map_or(true, |x| x.is_empty());
+            if sender.send(result).is_err() || last {
+                break;
+            }
+        });
+        ReadAhead {
+            blocks,
+            current: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.current.len() && !self.done {
+            let next = match self.blocks.recv() {
+                Ok(block) => block?,
+                Err(_) => Vec::new(),
+            };
+            return_buffer(mem::replace(&mut self.current, next));
+            self.pos = 0;
+            self.done = self.current.is_empty();
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos = self.pos + n;
+        Ok(n)
+    }
+}
+
+// Checks 4K at a time without an early exit in the inner loop so it vectorizes,
+// unused MFT regions and wiped journal space are mostly zeroes.
+pub fn is_zero(data: &[u8]) -> bool {
+    let mut chunks = data.chunks_exact(4096);
+    let zero = chunks
+        .by_ref()
+        .all(|x| x.iter().fold(0, |acc, byte| acc | byte) == 0);
+    zero && chunks.remainder().iter().all(|x| *x == 0)
+}
+
+fn read_block<R: Read>(inner: &mut R, block: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < block.len() {
+        match inner.read(&mut block[read..]) {
+            Ok(0) => break,
+            Ok(n) => read = read + n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Compresses blocks of the stream on worker threads as separate gzip members,
+/// a writer thread puts them back in order. Concatenated members are a valid gzip file.
+pub struct ParallelGzEncoder<W: Write + Send + 'static> {
+    buf: Vec<u8>,
+    chunk_size: usize,
+    seq: u64,
+    jobs: Option<SyncSender<(u64, Vec<u8>)>>,
+    workers: Vec<JoinHandle<()>>,
+    writer: Option<JoinHandle<io::Result<W>>>,
+}
+
+impl<W: Write + Send + 'static> ParallelGzEncoder<W> {
+    pub fn new(mut inner: W, level: Compression) -> ParallelGzEncoder<W> {
+        let threads = compression_threads();
+        let chunk_size = chunk_size();
+        let (jobs, job_receiver) = sync_channel::<(u64, Vec<u8>)>(threads * 2);
+        let (results, result_receiver) = sync_channel::<(u64, Vec<u8>)>(threads * 2);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        // Every full block of zeroes compresses to the same gzip member
+        let zero_member = Arc::new(OnceLock::new());
+        let workers = (0..threads)
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let results = results.clone();
+                let zero_member = zero_member.clone();
+                thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (seq, block) = match job {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let start = Instant::now();
+                    let data = if block.len() == chunk_size && is_zero(&block) {
+                        let member: &Vec<u8> = zero_member.get_or_init(|| compress(&block, level));
+                        let mut data = take_buffer(member.len());
+                        data.extend_from_slice(member);
+                        data
+                    } else {
+                        compress(&block, level)
+                    };
+                    COMPRESSION.add(u64::try_from(block.len()).unwrap(), start.elapsed());
+                    return_buffer(block);
+                    if results.send((seq, data)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        let writer = thread::spawn(move || {
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (seq, data) in result_receiver {
+                pending.insert(seq, data);
+                while let Some(data) = pending.remove(&next) {
+                    inner.write_all(&data)?;
+                    return_buffer(data);
+                    next = next + 1;
+                }
+            }
+            inner.flush()?;
+            Ok(inner)
+        });
+        ParallelGzEncoder {
+            buf: take_buffer(chunk_size),
+            chunk_size,
+            seq: 0,
+            jobs: Some(jobs),
+            workers,
+            writer: Some(writer),
+        }
+    }
+
+    fn send_block(&mut self) -> io::Result<()> {
+        let block = mem::replace(&mut self.buf, take_buffer(self.chunk_size));
+        let sent = match &self.jobs {
+            Some(jobs) => jobs.send((self.seq, block)).is_ok(),
+            None => false,
+        };
+        self.seq = self.seq + 1;
+        if sent {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Compression pipeline stopped",
+            ))
+        }
+    }
+
+    /// Compresses the remaining data and waits for everything to be written.
+    pub fn finish(&mut self) -> io::Result<W> {
+        if !self.buf.is_empty() || self.seq == 0 {
+            self.send_block()?;
+        }
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+        match self.writer.take() {
+            Some(writer) => writer.join().unwrap(),
+            None => Err(io::Error::other("Compression pipeline already finished")),
+        }
+    }
+}
+
+fn compress(block: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(take_buffer(block.len() / 2), level);
+    encoder.write_all(block).unwrap();
+    encoder.finish().unwrap()
+}
+
+impl<W: Write + Send + 'static> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.chunk_size {
+            self.send_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+
+    fn test_data() -> Vec<u8> {
+        (0..3 * BLOCK_SIZE + 12345)
+            .map(|x| (x % 251) as u8 ^ (x / 4096) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let data = test_data();
+        let mut output = Vec::new();
+        ReadAhead::new(io::Cursor::new(data.clone()))
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_buffer_reuse() {
+        let mut buf = take_buffer(4 * BLOCK_SIZE);
+        buf.extend_from_slice(b"data");
+        return_buffer(buf);
+        let buf = take_buffer(4 * BLOCK_SIZE);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 4 * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let budget = Budget {
+            limit: 8 * BLOCK_SIZE,
+            chunk_size: BLOCK_SIZE,
+        };
+        assert_eq!(budget.read_ahead_blocks(), 2);
+        assert_eq!(budget.compression_threads(8), 1);
+        assert_eq!(budget.max_buffered(), Some(2 * BLOCK_SIZE));
+        assert_eq!(budget.upload_chunk_size(8 * BLOCK_SIZE), 2 * BLOCK_SIZE);
+        let tiny = Budget {
+            limit: 4096,
+            ..budget
+        };
+        assert_eq!(tiny.upload_chunk_size(8 * BLOCK_SIZE), MIN_UPLOAD_CHUNK);
+        let unlimited = Budget { limit: 0, ..budget };
+        assert_eq!(unlimited.read_ahead_blocks(), READ_AHEAD_BLOCKS);
+        assert_eq!(unlimited.compression_threads(8), 8);
+        assert_eq!(unlimited.max_buffered(), None);
+        assert_eq!(unlimited.upload_chunk_size(8 * BLOCK_SIZE), 8 * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_is_zero() {
+        let mut data = vec![0u8; 3 * 4096 + 100];
+        assert!(is_zero(&data));
+        assert!(is_zero(&[]));
+        data[5000] = 1;
+        assert!(!is_zero(&data));
+        data[5000] = 0;
+        data[3 * 4096 + 99] = 1;
+        assert!(!is_zero(&data));
+    }
+
+    #[test]
+    fn test_parallel_gz_zeroes() {
+        let mut data = vec![0u8; 3 * BLOCK_SIZE];
+        data[BLOCK_SIZE + 10] = 1;
+        let mut encoder = ParallelGzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut output = Vec::new();
+        MultiGzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_parallel_gz() {
+        let data = test_data();
+        let mut encoder = ParallelGzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut output = Vec::new();
+        MultiGzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, data);
+    }
+}
@@ -0,0 +1,65 @@
+use ed25519_dalek::VerifyingKey;
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::signing;
+use crate::tls;
+
+// Fetches a collection profile and its signature (<url>.sig) and returns the
+// arguments to collect with. A profile is a YAML file like:
+//
+// args:
+//   - --registry
+//   - --parse
+//   - prefetch
+pub fn fetch(url: &str, key: &VerifyingKey, pin: Option<&str>) -> Result<Vec<String>, String> {
+    let agent = pin.map_or_else(ureq::agent, tls::pinned_agent);
+    let get = |url: &str| {
+        agent
+            .get(url)
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())
+    };
+    let profile = get(url)?;
+    let signature = get(&format!("{}.sig", url))?;
+    signing::verify_detached(key, profile.as_bytes(), signature.trim())?;
+    parse(&profile)
+}
+
+fn parse(profile: &str) -> Result<Vec<String>, String> {
+    let docs = YamlLoader::load_from_str(profile).map_err(|e| e.to_string())?;
+    let doc = docs.first().ok_or("Empty profile")?;
+    let mut args = Vec::new();
+    let list = doc["args"].as_vec().ok_or("The profile has no args")?;
+    for arg in list.iter() {
+        let arg = match arg {
+            Yaml::String(x) | Yaml::Real(x) => x.clone(),
+            Yaml::Integer(x) => x.to_string(),
+            _ => return Err(String::from("Profile arguments must be strings")),
+        };
+        // A profile can't point to another profile
+        if arg.starts_with("--config") {
+            return Err(format!("Profiles can't set {}", arg));
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let profile =
+            "args:\n  - --registry\n  - --enrich-rate\n  - 10\n  - -p\n  - 'C:\\Temp\\*'\n";
+        assert_eq!(
+            parse(profile).unwrap(),
+            vec!["--registry", "--enrich-rate", "10", "-p", "C:\\Temp\\*"]
+        );
+        assert!(parse("args:\n  - --config-url\n  - http://x\n").is_err());
+        assert!(parse("paths: []\n").is_err());
+    }
+}
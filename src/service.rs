@@ -0,0 +1,195 @@
+use std::env;
+use std::ffi::OsString;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use windows_service::service::{
+    ServiceAccess, ServiceAction, ServiceActionType, ServiceControl, ServiceControlAccept,
+    ServiceErrorControl, ServiceExitCode, ServiceFailureActions, ServiceFailureResetPeriod,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::cleanup;
+use crate::collector::Outcome;
+use crate::control;
+use crate::log;
+
+const SERVICE_NAME: &str = "squirrel";
+// How often the SCM hears that stopping is still in progress.
+const STOP_CHECKPOINT: Duration = Duration::from_secs(10);
+
+const USAGE: &str = "Usage: squirrel service install COMMAND [ARGS]\n       \
+                     squirrel service uninstall\n\n\
+                     Runs e.g. `squirrel agent --server URL` as a Windows service.";
+
+// dispatch in lib.rs, which runs the command line the service was installed with.
+type Dispatch = fn(&[String]) -> Outcome;
+
+// The service main is called by the SCM through a plain function, so the command
+// it runs is passed along in a static.
+static COMMAND: OnceLock<(Vec<String>, Dispatch)> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+pub fn run(args: &[String], dispatch: Dispatch) {
+    let result = match args.first().map(|x| x.as_str()) {
+        Some("install") if args.len() > 1 => install(&args[1..]),
+        Some("uninstall") => uninstall(),
+        // Only the SCM starts the service this way
+        Some("run") if args.len() > 1 => {
+            let mut command = vec![String::from("squirrel")];
+            command.extend(args[1..].iter().cloned());
+            COMMAND.set((command, dispatch)).unwrap();
+            service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        }
+        _ => return println!("{}", USAGE),
+    };
+    if let Err(e) = result {
+        panic!("Service {} failed: {}", args[0], e);
+    }
+}
+
+fn install(command: &[String]) -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let mut launch_arguments = vec![OsString::from("service"), OsString::from("run")];
+    launch_arguments.extend(command.iter().map(OsString::from));
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("Squirrel"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: env::current_exe().unwrap(),
+        launch_arguments,
+        dependencies: vec![],
+        // LocalSystem, which has the backup and debug privileges the collection needs
+        account_name: None,
+        account_password: None,
+    };
+    let service =
+        manager.create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
+    service.set_description("Forensic artifact collection")?;
+    let restart = ServiceAction {
+        action_type: ServiceActionType::Restart,
+        delay: Duration::from_secs(60),
+    };
+    service.update_failure_actions(ServiceFailureActions {
+        reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(24 * 60 * 60)),
+        reboot_msg: None,
+        command: None,
+        actions: Some(vec![restart.clone(), restart.clone(), restart]),
+    })?;
+    // A command that panics stops the service with an error, which restarts it as well
+    service.set_failure_actions_on_non_crash_failures(true)?;
+    service.start::<&str>(&[])?;
+    log::info(format!(
+        "Installed and started the {} service",
+        SERVICE_NAME
+    ));
+    Ok(())
+}
+
+fn uninstall() -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+    log::info(format!("Uninstalled the {} service", SERVICE_NAME));
+    Ok(())
+}
+
+fn service_main(_args: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        log::error(format!("Service failed: {}", e));
+    }
+}
+
+// The command runs on a worker thread. The handler sends None when the service is
+// stopped and the worker whether the command succeeded once it returns.
+fn run_service() -> windows_service::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let stop = sender.clone();
+    let handler = move |event| match event {
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            // The agent stops polling and a running collection finishes its archive
+            // with what it has so far, the service waits for that below
+            control::stop();
+            stop.send(None).ok();
+            ServiceControlHandlerResult::NoError
+        }
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    let status = service_control_handler::register(SERVICE_NAME, handler)?;
+    status.set_service_status(service_status(
+        ServiceState::Running,
+        ServiceExitCode::NO_ERROR,
+        0,
+    ))?;
+
+    let (command, dispatch) = COMMAND.get().unwrap();
+    let worker = thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| dispatch(command)));
+        sender.send(Some(result.is_ok())).ok();
+    });
+    let mut result = receiver.recv().unwrap();
+    let mut checkpoint = 0;
+    while result.is_none() {
+        checkpoint = checkpoint + 1;
+        status.set_service_status(service_status(
+            ServiceState::StopPending,
+            ServiceExitCode::NO_ERROR,
+            checkpoint,
+        ))?;
+        result = match receiver.recv_timeout(STOP_CHECKPOINT) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(false),
+        };
+    }
+    worker.join().ok();
+    // Whatever the command left registered, like shadow copies or the working dir
+    cleanup::run_all();
+    let exit_code = match result {
+        Some(false) => ServiceExitCode::ServiceSpecific(1),
+        _ => ServiceExitCode::NO_ERROR,
+    };
+    status.set_service_status(service_status(ServiceState::Stopped, exit_code, 0))
+}
+
+fn service_status(
+    state: ServiceState,
+    exit_code: ServiceExitCode,
+    checkpoint: u32,
+) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: if state == ServiceState::Running {
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+        } else {
+            ServiceControlAccept::empty()
+        },
+        exit_code,
+        checkpoint,
+        wait_hint: if state == ServiceState::StopPending {
+            2 * STOP_CHECKPOINT
+        } else {
+            Duration::default()
+        },
+        process_id: None,
+    }
+}
@@ -0,0 +1,75 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use json::JsonValue;
+use std::convert::TryFrom;
+
+// Anything fetched from a server that decides what gets collected (tasks, profiles)
+// carries a hex Ed25519 signature. Only what is signed with the pinned key is used,
+// so whoever controls the server or the network can't pick the files.
+pub fn parse_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes = decode_hex(key).ok_or("The public key must be hex encoded")?;
+    let bytes = <[u8; 32]>::try_from(bytes.as_slice())
+        .map_err(|_| String::from("The public key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
+// Tasks are wrapped as {"payload": "<JSON>", "signature": "<hex>"}.
+pub fn verify(key: &VerifyingKey, signed: &JsonValue) -> Result<JsonValue, String> {
+    let payload = signed["payload"].as_str().ok_or("Unsigned payload")?;
+    let signature = signed["signature"].as_str().ok_or("Unsigned payload")?;
+    verify_detached(key, payload.as_bytes(), signature)?;
+    json::parse(payload).map_err(|e| e.to_string())
+}
+
+// Profiles come with the signature in a separate file.
+pub fn verify_detached(key: &VerifyingKey, data: &[u8], signature: &str) -> Result<(), String> {
+    let signature = decode_hex(signature).ok_or("The signature must be hex encoded")?;
+    let signature = Signature::from_slice(&signature).map_err(|e| e.to_string())?;
+    key.verify_strict(data, &signature)
+        .map_err(|_| String::from("Invalid signature"))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn test_verify() {
+        let signing = SigningKey::from_bytes(&[7u8; 32]);
+        let key_hex: String = signing
+            .verifying_key()
+            .as_bytes()
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect();
+        let key = parse_key(&key_hex).unwrap();
+        let payload = r#"{"id": "42", "args": ["-r"]}"#;
+        let signature: String = signing
+            .sign(payload.as_bytes())
+            .to_bytes()
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect();
+
+        let mut signed = JsonValue::new_object();
+        signed["payload"] = payload.into();
+        signed["signature"] = signature.as_str().into();
+        assert_eq!(verify(&key, &signed).unwrap()["id"], "42");
+
+        signed["payload"] = r#"{"id": "42", "args": ["-p", "C:\\secret"]}"#.into();
+        assert!(verify(&key, &signed).is_err());
+        signed.remove("signature");
+        assert!(verify(&key, &signed).is_err());
+        assert!(parse_key("abc").is_err());
+    }
+}
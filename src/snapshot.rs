@@ -1,9 +1,247 @@
+//! Volume Shadow Copies, created and deleted with WMI and exposed through a directory symlink so
+//! locked files (registry hives, event logs) can be read like any other file.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::fs;
+use std::io;
 use std::os::windows::fs::symlink_dir;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::str;
 
-pub fn create(volume: &str) -> String {
+use crate::{cleanup, log};
+
+/// Deletes the shadow copy when dropped, also when collection panics or is interrupted
+/// with Ctrl-C, unless it should be kept.
+pub struct Snapshot {
+    pub shadow_id: String,
+    pub device_id: String,
+    cleanup: Option<u64>,
+}
+
+impl Snapshot {
+    pub fn create(volume: &str, keep: bool) -> io::Result<Snapshot> {
+        let shadow_id = create(volume)?;
+        let mut snapshot = Snapshot {
+            shadow_id: shadow_id.clone(),
+            device_id: String::new(),
+            cleanup: None,
+        };
+        if !keep {
+            snapshot.cleanup = Some(cleanup::register(move || delete_or_warn(&shadow_id)));
+        }
+        snapshot.device_id = get_device_object(&snapshot.shadow_id)?;
+        Ok(snapshot)
+    }
+
+    /// Uses a shadow copy that already existed, it's never deleted.
+    pub fn reuse(copy: &ShadowCopy) -> Snapshot {
+        Snapshot {
+            shadow_id: copy.id.clone(),
+            device_id: copy.device_id.clone(),
+            cleanup: None,
+        }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Some(delete) = self.cleanup.and_then(cleanup::take) {
+            delete();
+        }
+    }
+}
+
+fn delete_or_warn(shadow_id: &str) {
+    if let Err(e) = delete(shadow_id) {
+        log::warn(format!(
+            "Failed to delete shadow copy {}, remove it with \
+             vssadmin delete shadows /shadow={}: {}",
+            shadow_id, shadow_id, e
+        ));
+    }
+}
+
+/// A shadow copy that already existed, e.g. made by System Restore or a backup.
+#[derive(Debug, PartialEq)]
+pub struct ShadowCopy {
+    pub id: String,
+    pub device_id: String,
+    /// The drive it's a copy of, like `C:\`.
+    pub drive: String,
+    /// When it was made, in UTC like `20210601T143005`.
+    pub time: String,
+}
+
+/// Lists the shadow copies of volumes with a drive letter, oldest first.
+pub fn list() -> io::Result<Vec<ShadowCopy>> {
+    let command = "$drives = @{}; Get-CimInstance Win32_Volume | \
+         ForEach-Object { $drives[$_.DeviceID] = $_.DriveLetter }; \
+         ConvertTo-Json @(Get-CimInstance Win32_ShadowCopy | Sort-Object InstallDate | \
+         ForEach-Object { @{ ID = $_.ID; DeviceObject = $_.DeviceObject; \
+         Drive = $drives[$_.VolumeName]; \
+         Time = $_.InstallDate.ToUniversalTime().ToString('yyyyMMddTHHmmss') } })";
+    parse_list(&powershell(command)?)
+}
+
+/// The newest shadow copy of the drive (`C:\`) if it's at most `max_age` old.
+pub fn find_recent(drive: &str, max_age: Duration) -> io::Result<Option<ShadowCopy>> {
+    Ok(newest(list()?, drive, Utc::now().naive_utc(), max_age))
+}
+
+fn newest(
+    copies: Vec<ShadowCopy>,
+    drive: &str,
+    now: NaiveDateTime,
+    max_age: Duration,
+) -> Option<ShadowCopy> {
+    copies.into_iter().rev().find(|x| {
+        x.drive.eq_ignore_ascii_case(drive)
+            && NaiveDateTime::parse_from_str(&x.time, "%Y%m%dT%H%M%S")
+                .is_ok_and(|time| now - time <= max_age)
+    })
+}
+
+fn parse_list(output: &str) -> io::Result<Vec<ShadowCopy>> {
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let list = json::parse(output).map_err(|e| failed(e.to_string()))?;
+    Ok(list
+        .members()
+        .filter_map(|x| {
+            Some(ShadowCopy {
+                id: String::from(x["ID"].as_str()?),
+                device_id: String::from(x["DeviceObject"].as_str()?),
+                drive: format!("{}\\", x["Drive"].as_str()?),
+                time: String::from(x["Time"].as_str()?),
+            })
+        })
+        .collect())
+}
+
+/// Removes the symlink to the shadow copy when dropped, or on Ctrl-C.
+pub struct Mount {
+    pub path: PathBuf,
+    cleanup: u64,
+}
+
+impl Drop for Mount {
+    fn drop(&mut self) {
+        if let Some(remove) = cleanup::take(self.cleanup) {
+            remove();
+        }
+    }
+}
+
+fn remove_mount(path: &Path) {
+    if let Err(e) = fs::remove_dir(path) {
+        log::warn(format!("Failed to remove {:?}: {}", path, e));
+    }
+}
+
+// VSS needs at least this much room for the differences of a new shadow copy.
+const MIN_STORAGE: u64 = 320 * 1024 * 1024;
+
+/// Checks the shadow storage of the drive has room for a new shadow copy, and grows
+/// it to `resize` bytes if it doesn't and that's allowed. A full storage is only
+/// warned about, VSS may still make room by removing older shadow copies.
+pub fn check_storage(drive: &str, resize: Option<u64>) -> io::Result<()> {
+    let command = format!(
+        "{} ConvertTo-Json @($storage | Select-Object UsedSpace, MaxSpace)",
+        storage_command(drive)
+    );
+    let (used, max) = match parse_storage(&powershell(&command)?)? {
+        Some(x) => x,
+        // Created along with the first shadow copy
+        None => return Ok(()),
+    };
+    if max.saturating_sub(used) >= MIN_STORAGE {
+        return Ok(());
+    }
+    let mb = |x: u64| x / (1024 * 1024);
+    match resize {
+        Some(size) if size > max => {
+            log::info(format!(
+                "Growing the shadow storage of {} from {} MB to {} MB",
+                drive,
+                mb(max),
+                mb(size)
+            ));
+            let command = format!(
+                "{} $storage | Set-CimInstance -Property @{{ MaxSpace = [UInt64]{} }}",
+                storage_command(drive),
+                size
+            );
+            powershell(&command).map(|_| ())
+        }
+        _ => {
+            log::warn(format!(
+                "The shadow storage of {} is almost full ({} of {} MB used), creating a \
+                 snapshot may fail or remove older shadow copies. Grow it with --vss-resize \
+                 or vssadmin resize shadowstorage.",
+                drive,
+                mb(used),
+                mb(max)
+            ));
+            Ok(())
+        }
+    }
+}
+
+// Sets $storage to the Win32_ShadowStorage of the drive.
+fn storage_command(drive: &str) -> String {
+    format!(
+        "$volume = (Get-CimInstance Win32_Volume | \
+         Where-Object {{ $_.DriveLetter -eq \"{}\" }}).DeviceID; \
+         $storage = Get-CimInstance Win32_ShadowStorage | \
+         Where-Object {{ $_.Volume.DeviceID -eq $volume }};",
+        &drive[0..2]
+    )
+}
+
+fn parse_storage(output: &str) -> io::Result<Option<(u64, u64)>> {
+    if output.trim().is_empty() {
+        return Ok(None);
+    }
+    let list = json::parse(output).map_err(|e| failed(e.to_string()))?;
+    let storage = &list[0];
+    Ok(storage["UsedSpace"]
+        .as_u64()
+        .zip(storage["MaxSpace"].as_u64()))
+}
+
+fn powershell(command: &str) -> io::Result<String> {
+    let output = Command::new("powershell")
+        .arg("-Command")
+        .arg(command)
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.is_empty() {
+        return Err(failed(stderr.into_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// The meaning of the ReturnValue of Win32_ShadowCopy.Create.
+fn create_error(return_value: u32) -> &'static str {
+    match return_value {
+        1 => "access denied",
+        2 => "invalid argument",
+        3 => "volume not found",
+        4 => "volume not supported",
+        5 => "unsupported shadow copy context",
+        6 => "insufficient storage",
+        7 => "volume is in use",
+        8 => "maximum number of shadow copies reached",
+        9 => "another shadow copy operation is already in progress",
+        10 => "the shadow copy provider vetoed the operation",
+        11 => "the shadow copy provider isn't registered",
+        12 => "shadow copy provider failure",
+        _ => "unknown error",
+    }
+}
+
+pub fn create(volume: &str) -> io::Result<String> {
     let command = format!(
         "ConvertTo-Json (Invoke-CimMethod -ClassName Win32_ShadowCopy -MethodName Create \
          -Arguments @{{Volume = \"{}\"}})",
@@ -12,41 +250,89 @@ pub fn create(volume: &str) -> String {
     let output = Command::new("powershell")
         .arg("-Command")
         .arg(command)
-        .output()
-        .expect("Failed to execute PowerShell");
-    let stdout = str::from_utf8(&output.stdout).expect("Failed to parse stdout as UTF-8");
-    let stderr = String::from_utf8(output.stderr).expect("Failed to parse stderr as UTF-8");
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
     match json::parse(&stdout) {
         Ok(result) => {
-            let return_value = result["ReturnValue"].as_number().expect("No ReturnValue");
-            if return_value == 0 {
-                let shadow_id = result["ShadowID"].as_str().expect("No ShadowID");
-                return shadow_id.to_string();
-            } else {
-                panic!(
-                    "Snapshot creation failed, return_value: {}, stderr: {}",
-                    return_value, stderr
-                )
+            let return_value = result["ReturnValue"].as_u32();
+            match (return_value, result["ShadowID"].as_str()) {
+                (Some(0), Some(shadow_id)) => Ok(shadow_id.to_string()),
+                (Some(x), _) => Err(failed(format!(
+                    "Snapshot creation failed: {} ({}), stderr: {}",
+                    create_error(x),
+                    x,
+                    stderr
+                ))),
+                _ => Err(failed(format!(
+                    "Snapshot creation failed, stderr: {}",
+                    stderr
+                ))),
             }
         }
-        Err(_) => panic!("Snapshot creation failed, stderr: {}", stderr),
+        Err(_) => Err(failed(format!(
+            "Snapshot creation failed, stderr: {}",
+            stderr
+        ))),
     }
 }
 
-pub fn delete(shadow_id: &str) {
+fn failed(msg: String) -> io::Error {
+    io::Error::other(msg)
+}
+
+/// Deletes the shadow copy through WMI and checks it's gone. vssadmin, which is
+/// missing or blocked on many hardened servers, is only tried when that fails.
+pub fn delete(shadow_id: &str) -> io::Result<()> {
+    let command = format!(
+        "Get-CimInstance Win32_ShadowCopy | Where-Object {{ $_.ID -eq \"{}\" }} | \
+         Remove-CimInstance",
+        shadow_id
+    );
+    let output = Command::new("powershell")
+        .arg("-Command")
+        .arg(command)
+        .output()?;
+    if !exists(shadow_id)? {
+        return Ok(());
+    }
+    log::warn(format!(
+        "Failed to delete shadow copy {} through WMI, trying vssadmin: {}",
+        shadow_id,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ));
     let args = [
         "delete",
         "shadows",
         "/quiet",
         &format!("/shadow={}", shadow_id),
     ];
-    Command::new("vssadmin")
-        .args(&args)
-        .output()
-        .expect("Failed to execute vssadmin");
+    Command::new("vssadmin").args(args).output()?;
+    if exists(shadow_id)? {
+        return Err(failed(String::from("The shadow copy still exists")));
+    }
+    Ok(())
+}
+
+// Whether WMI still lists the shadow copy. Failing goes by the exit status, stderr
+// also gets warnings and progress output.
+fn exists(shadow_id: &str) -> io::Result<bool> {
+    let command = format!(
+        "$ErrorActionPreference = 'Stop'; \
+         @(Get-CimInstance Win32_ShadowCopy | Where-Object {{ $_.ID -eq \"{}\" }}).Count",
+        shadow_id
+    );
+    let output = Command::new("powershell")
+        .arg("-Command")
+        .arg(command)
+        .output()?;
+    if !output.status.success() {
+        return Err(failed(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim() != "0")
 }
 
-pub fn get_device_object(shadow_id: &str) -> String {
+pub fn get_device_object(shadow_id: &str) -> io::Result<String> {
     let command = format!(
         "(Get-CimInstance Win32_ShadowCopy | \
          Where-Object {{ $_.ID -eq \"{}\"}}).DeviceObject",
@@ -55,22 +341,85 @@ pub fn get_device_object(shadow_id: &str) -> String {
     let output = Command::new("powershell")
         .arg("-Command")
         .arg(command)
-        .output()
-        .expect("Failed to execute PowerShell");
-    let stderr = str::from_utf8(&output.stderr).expect("Failed to parse stderr as UTF-8");
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
     if !stderr.is_empty() {
-        panic!("{}", stderr)
+        return Err(failed(stderr.into_owned()));
     }
-    let out = str::from_utf8(&output.stdout)
-        .expect("Failed to parse stdout as UTF-8")
-        .trim_end();
-    String::from(out)
+    let out = String::from_utf8_lossy(&output.stdout);
+    Ok(String::from(out.trim_end()))
 }
 
-pub fn mount(device_id: &str, mount_point: &Path) {
+/// The root of a shadow copy's device, to read from it without a mount point.
+pub fn device_root(device_id: &str) -> PathBuf {
+    PathBuf::from(format!("{}\\", device_id))
+}
+
+pub fn mount(device_id: &str, mount_point: &Path) -> io::Result<Mount> {
     let devid = format!("{}\\", device_id);
-    symlink_dir(&devid, mount_point).expect(&format!(
-        "Failed to create symlink: {} {:?}",
-        devid, mount_point
-    ));
+    symlink_dir(&devid, mount_point).map_err(|e| {
+        failed(format!(
+            "Failed to create symlink: {} {:?}: {}",
+            devid, mount_point, e
+        ))
+    })?;
+    let path = mount_point.to_path_buf();
+    Ok(Mount {
+        path: path.clone(),
+        cleanup: cleanup::register(move || remove_mount(&path)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        let output = r#"[
+            {"ID": "{1A2B}", "Drive": "C:", "Time": "20210601T143005",
+             "DeviceObject": "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1"},
+            {"ID": "{3C4D}", "Drive": null, "Time": "20210602T090000",
+             "DeviceObject": "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy2"}
+        ]"#;
+        assert_eq!(
+            parse_list(output).unwrap(),
+            vec![ShadowCopy {
+                id: String::from("{1A2B}"),
+                device_id: String::from(r#"\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy1"#),
+                drive: String::from("C:\\"),
+                time: String::from("20210601T143005"),
+            }]
+        );
+        assert!(parse_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_storage() {
+        let output = r#"[{"UsedSpace": 1073741824, "MaxSpace": 18446744073709551615}]"#;
+        assert_eq!(parse_storage(output).unwrap(), Some((1073741824, u64::MAX)));
+        assert_eq!(parse_storage("").unwrap(), None);
+        assert_eq!(create_error(6), "insufficient storage");
+    }
+
+    #[test]
+    fn test_newest() {
+        let copy = |id: &str, drive: &str, time: &str| ShadowCopy {
+            id: String::from(id),
+            device_id: String::new(),
+            drive: String::from(drive),
+            time: String::from(time),
+        };
+        let copies = || {
+            vec![
+                copy("{1}", "C:\\", "20210601T080000"),
+                copy("{2}", "C:\\", "20210601T120000"),
+                copy("{3}", "D:\\", "20210601T130000"),
+            ]
+        };
+        let now = NaiveDateTime::parse_from_str("20210601T140000", "%Y%m%dT%H%M%S").unwrap();
+        let found = newest(copies(), "c:\\", now, Duration::hours(3));
+        assert_eq!(found.unwrap().id, "{2}");
+        assert!(newest(copies(), "C:\\", now, Duration::hours(1)).is_none());
+    }
 }
@@ -0,0 +1,162 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub struct Counter {
+    name: &'static str,
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Counter {
+        Counter {
+            name,
+            bytes: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+        }
+    }
+    pub fn add(&self, bytes: u64, elapsed: Duration) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+    pub fn reset(&self) {
+        self.bytes.store(0, Ordering::Relaxed);
+        self.nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+pub static DEVICE_READ: Counter = Counter::new("Device read");
+// Summed over the compression threads, so it can exceed the wall clock time
+pub static COMPRESSION: Counter = Counter::new("Compression");
+pub static UPLOAD: Counter = Counter::new("Upload");
+
+pub static COUNTERS: [&Counter; 3] = [&DEVICE_READ, &COMPRESSION, &UPLOAD];
+
+// Adds the bytes read and the time spent reading to the counter.
+pub struct Timed<R> {
+    inner: R,
+    counter: &'static Counter,
+}
+
+impl<R> Timed<R> {
+    pub fn new(inner: R, counter: &'static Counter) -> Timed<R> {
+        Timed { inner, counter }
+    }
+}
+
+impl<R: Read> Read for Timed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let n = self.inner.read(buf)?;
+        self.counter.add(u64::try_from(n).unwrap(), start.elapsed());
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for Timed<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub fn report(counters: &[&Counter], total: Duration) -> String {
+    let mut report = header("Phase");
+    for counter in counters.iter() {
+        report.push_str(&line(counter.name, counter.bytes(), counter.elapsed()));
+    }
+    report.push_str(&format!(
+        "{:<16} {:>10} {:>10.1} {:>10}\n",
+        "Total",
+        "",
+        total.as_secs_f64(),
+        ""
+    ));
+    report
+}
+
+// Bytes read and time spent per artifact class (the flag a path pattern belongs to).
+#[derive(Default)]
+pub struct Throughput {
+    classes: Vec<(&'static str, u64, Duration)>,
+}
+
+impl Throughput {
+    pub fn add(&mut self, class: &'static str, bytes: u64, elapsed: Duration) {
+        match self.classes.iter_mut().find(|(x, _, _)| *x == class) {
+            Some((_, total_bytes, total_elapsed)) => {
+                *total_bytes = *total_bytes + bytes;
+                *total_elapsed = *total_elapsed + elapsed;
+            }
+            None => self.classes.push((class, bytes, elapsed)),
+        }
+    }
+    pub fn report(&self) -> String {
+        let mut report = header("Artifact");
+        for (class, bytes, elapsed) in self.classes.iter() {
+            report.push_str(&line(class, *bytes, *elapsed));
+        }
+        report
+    }
+}
+
+fn header(name: &str) -> String {
+    format!(
+        "{:<16} {:>10} {:>10} {:>10}\n",
+        name, "MB", "Seconds", "MB/s"
+    )
+}
+
+fn line(name: &str, bytes: u64, elapsed: Duration) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { mb / secs } else { 0.0 };
+    format!("{:<16} {:>10.1} {:>10.1} {:>10.1}\n", name, mb, secs, rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TEST: Counter = Counter::new("Test");
+
+    #[test]
+    fn test_timed() {
+        let data = vec![1u8; 3 * 1024 * 1024];
+        let mut output = Vec::new();
+        Timed::new(data.as_slice(), &TEST)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(TEST.bytes(), 3 * 1024 * 1024);
+        let report = report(&[&TEST], Duration::from_secs(2));
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[1].starts_with("Test"));
+        assert!(lines[1].contains("3.0"));
+        assert!(lines[2].contains("2.0"));
+        TEST.reset();
+        assert_eq!(TEST.bytes(), 0);
+        assert_eq!(TEST.elapsed(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_throughput() {
+        let mut throughput = Throughput::default();
+        throughput.add("registry", 1024 * 1024, Duration::from_secs(1));
+        throughput.add("mft", 4 * 1024 * 1024, Duration::from_secs(1));
+        throughput.add("registry", 1024 * 1024, Duration::from_secs(1));
+        let report = throughput.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("registry"));
+        assert!(lines[1].ends_with("1.0"));
+        assert!(lines[2].ends_with("4.0"));
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+use crate::log;
+
+// Collection events are sent as CEF messages in syslog (RFC 5424) lines, one
+// connection for the whole run.
+struct Syslog {
+    stream: Box<dyn Write + Send>,
+    host: String,
+}
+
+static SYSLOG: Mutex<Option<Syslog>> = Mutex::new(None);
+
+// Connects to tcp://HOST:PORT or tls://HOST:PORT.
+pub fn connect(url: &str) -> io::Result<()> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Expected tcp:// or tls://");
+    let (scheme, addr) = url.split_once("://").ok_or_else(invalid)?;
+    let tcp = TcpStream::connect(addr)?;
+    let stream: Box<dyn Write + Send> = match scheme {
+        "tcp" => Box::new(tcp),
+        "tls" => {
+            let name = addr.rsplit_once(':').map_or(addr, |x| x.0);
+            let name = ServerName::try_from(String::from(name))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let conn = ClientConnection::new(Arc::new(config), name).map_err(io::Error::other)?;
+            Box::new(StreamOwned::new(conn, tcp))
+        }
+        _ => return Err(invalid()),
+    };
+    let host = env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("-"));
+    *SYSLOG.lock().unwrap() = Some(Syslog { stream, host });
+    Ok(())
+}
+
+// Does nothing unless connected, after a failed send the rest of the events are dropped.
+pub fn send(id: &str, name: &str, severity: u8, extension: &[(&str, String)]) {
+    let mut syslog = SYSLOG.lock().unwrap();
+    if let Some(x) = syslog.as_mut() {
+        let line = format_event(&x.host, Utc::now(), id, name, severity, extension);
+        if let Err(e) = x
+            .stream
+            .write_all(line.as_bytes())
+            .and_then(|_| x.stream.flush())
+        {
+            log::warn(format!("Failed to send to syslog: {}", e));
+            *syslog = None;
+        }
+    }
+}
+
+fn format_event(
+    host: &str,
+    time: DateTime<Utc>,
+    id: &str,
+    name: &str,
+    severity: u8,
+    extension: &[(&str, String)],
+) -> String {
+    // Facility local0, warning for the CEF severities that are at least medium
+    let pri = 16 * 8 + if severity >= 4 { 4 } else { 6 };
+    let extension: Vec<String> = extension
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, escape_extension(value)))
+        .collect();
+    format!(
+        "<{}>1 {} {} squirrel - - - CEF:0|squirrel|squirrel|{}|{}|{}|{}|{}\n",
+        pri,
+        time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        host,
+        env!("CARGO_PKG_VERSION"),
+        escape_header(id),
+        escape_header(name),
+        severity,
+        extension.join(" ")
+    )
+}
+
+fn escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn escape_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_event() {
+        let time = DateTime::parse_from_rfc3339("2021-06-01T14:30:05Z").unwrap();
+        let line = format_event(
+            "WS01",
+            time.with_timezone(&Utc),
+            "error",
+            "Collection error",
+            5,
+            &[
+                ("filePath", String::from(r#"C\Windows\a=b"#)),
+                ("msg", String::from("Access denied")),
+            ],
+        );
+        assert_eq!(
+            line,
+            format!(
+                "<132>1 2021-06-01T14:30:05.000Z WS01 squirrel - - - \
+                 CEF:0|squirrel|squirrel|{}|error|Collection error|5|\
+                 filePath=C\\\\Windows\\\\a\\=b msg=Access denied\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+}
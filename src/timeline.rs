@@ -0,0 +1,172 @@
+use flate2::read::MultiGzDecoder;
+use getopts::Options;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::Archive;
+
+use crate::log;
+use crate::parse::timeline::Timeline;
+use crate::parse::{Parse, PARSERS};
+
+fn set_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Show this help information.");
+    opts.optopt(
+        "o",
+        "output",
+        "Write timeline.csv and timeline.jsonl to DIR, instead of the parsed directory \
+         of a --dir collection or the current directory for an archive.",
+        "DIR",
+    );
+    opts
+}
+
+// Merges the parser outputs of a collection (an archive or --dir directory) into one
+// timeline sorted by time. The MFT and USN journal are included when they were
+// converted, with --parse mft or usnjrnl.
+pub fn run(args: &[String]) {
+    let opts = set_opts();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => panic!("{:?}", f),
+    };
+    if matches.opt_present("help") || matches.free.len() != 1 {
+        let usage = "Usage: squirrel timeline [options] COLLECTION";
+        return print!("{}", opts.usage(usage));
+    }
+    let collection = Path::new(&matches.free[0]);
+    let output = match matches.opt_str("output") {
+        Some(dir) => PathBuf::from(dir),
+        None if collection.is_dir() => collection.join("parsed"),
+        None => PathBuf::from("."),
+    };
+    let timeline = read_outputs(collection).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read the parser outputs of {}: {}",
+            collection.display(),
+            e
+        )
+    });
+    let create = |name: &str| {
+        let path = output.join(name);
+        let file = File::create(&path)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", path.display(), e));
+        BufWriter::new(file)
+    };
+    let mut jsonl = create("timeline.jsonl");
+    let mut csv = create("timeline.csv");
+    timeline
+        .write(&mut jsonl, &mut csv)
+        .and_then(|_| jsonl.flush())
+        .and_then(|_| csv.flush())
+        .expect("Failed to write the timeline");
+    log::info(format!("Wrote the timeline to {}", output.display()));
+}
+
+fn read_outputs(collection: &Path) -> io::Result<Timeline> {
+    let mut timeline = Timeline::new();
+    if collection.is_dir() {
+        let parsed = collection.join("parsed");
+        let mut paths = Vec::new();
+        list_files(&parsed, &mut paths)?;
+        for path in paths {
+            let name = path.strip_prefix(&parsed).unwrap().to_string_lossy();
+            let file = BufReader::new(File::open(&path)?);
+            add_output(&mut timeline, &name, file)?;
+        }
+    } else {
+        let file = MultiGzDecoder::new(BufReader::new(File::open(collection)?));
+        let mut archive = Archive::new(file);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let path = entry.path()?.to_string_lossy().replace('/', "\\");
+            if let Some(name) = path.strip_prefix("parsed\\") {
+                let name = String::from(name);
+                add_output(&mut timeline, &name, BufReader::new(entry))?;
+            }
+        }
+    }
+    Ok(timeline)
+}
+
+// Shadow copy drives are nested, like parsed\mft-VSS\20210601T143005\C.jsonl.
+fn list_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Adds a file from the parsed directory, `name` being its path in there. That's
+// <parser>.jsonl for the summaries and <parser>-<drive>.<extension> for what the
+// converters wrote.
+fn add_output<R: Read>(
+    timeline: &mut Timeline,
+    name: &str,
+    mut data: BufReader<R>,
+) -> io::Result<()> {
+    let name = name.replace('/', "\\");
+    if let Some(parser) = PARSERS.iter().find(|x| name == format!("{}.jsonl", x.name)) {
+        return timeline.add(parser.name, None, &mut data);
+    }
+    let converter = PARSERS
+        .iter()
+        .find(|x| !matches!(x.parse, Parse::Json(_)) && name.starts_with(&format!("{}-", x.name)));
+    match converter {
+        Some(parser) => {
+            let output = format!("parsed\\{}", name);
+            timeline.add(parser.name, Some(&output), &mut data)
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_read_outputs() {
+        let root = env::temp_dir().join("squirrel_test_timeline");
+        let parsed = root.join("parsed");
+        fs::create_dir_all(parsed.join("mft-VSS").join("20210601T143005")).unwrap();
+        let prefetch = r#"{"Path":"C\\a.pf","ExecutableName":"A.EXE","LastRunTimes":["2021-01-02T00:00:00Z"]}"#;
+        fs::write(parsed.join("prefetch.jsonl"), prefetch).unwrap();
+        let mft = r#"{"ParentPath":"C:\\","Name":"a.txt","InUse":true,"SICreated":"2021-01-01T00:00:00Z"}"#;
+        fs::write(parsed.join("mft-C.jsonl"), mft).unwrap();
+        let shadow = parsed
+            .join("mft-VSS")
+            .join("20210601T143005")
+            .join("C.jsonl");
+        fs::write(shadow, mft).unwrap();
+        // The summary of the mft parser has no times of its own
+        fs::write(parsed.join("mft.jsonl"), r#"{"Path":"C\\MFT","Entries":1}"#).unwrap();
+
+        let timeline = read_outputs(&root).unwrap();
+        let (mut jsonl, mut csv) = (Vec::new(), Vec::new());
+        timeline.write(&mut jsonl, &mut csv).unwrap();
+        fs::remove_dir_all(root).unwrap();
+        let mut lines: Vec<json::JsonValue> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|x| json::parse(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2]["Description"], "A.EXE");
+        lines.truncate(2);
+        lines.sort_by_key(|x| x["Path"].to_string());
+        assert_eq!(lines[0]["Path"], "parsed\\mft-C.jsonl");
+        assert_eq!(lines[0]["Description"], "C:\\a.txt");
+        assert_eq!(
+            lines[1]["Path"],
+            "parsed\\mft-VSS\\20210601T143005\\C.jsonl"
+        );
+    }
+}
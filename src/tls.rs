@@ -0,0 +1,139 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{
+    ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+
+// Only accepts the server certificate with the pinned SHA-256 (of its DER encoding)
+// instead of anything a public CA signed, so an intercepting proxy or a rogue CA
+// can't stand in for the server.
+#[derive(Debug)]
+struct Pinned {
+    sha256: String,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl Pinned {
+    fn new(sha256: &str) -> Pinned {
+        Pinned {
+            sha256: sha256.replace(':', "").to_lowercase(),
+            algorithms: ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if format!("{:x}", Sha256::digest(end_entity.as_ref())) == self.sha256 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::General(String::from(
+                "The server certificate doesn't match the pinned hash",
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+// An HTTP agent that only talks to the server with the pinned certificate.
+pub fn pinned_agent(sha256: &str) -> ureq::Agent {
+    let config = client_config(Some(sha256), None).unwrap();
+    ureq::AgentBuilder::new()
+        .tls_config(Arc::new(config))
+        .build()
+}
+
+// Trusts only the server with the pinned certificate if there is one (the public CAs
+// otherwise) and logs in with the client certificate and key (PEM files) if given.
+pub fn client_config(
+    pin: Option<&str>,
+    client_cert: Option<(&Path, &Path)>,
+) -> Result<ClientConfig, String> {
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match pin {
+        Some(sha256) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned::new(sha256))),
+        None => builder.with_root_certificates(RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        }),
+    };
+    let (cert, key) = match client_cert {
+        Some(x) => x,
+        None => return Ok(builder.with_no_client_auth()),
+    };
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|x| x.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read {:?}: {}", cert, e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Failed to read {:?}: {}", key, e))?;
+    builder
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_pinned() {
+        let cert = CertificateDer::from(b"abc".to_vec());
+        let name = ServerName::try_from("example.com").unwrap();
+        let pinned = Pinned::new(
+            "BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD",
+        );
+        assert!(pinned
+            .verify_server_cert(&cert, &[], &name, &[], UnixTime::now())
+            .is_ok());
+        let other = CertificateDer::from(b"abd".to_vec());
+        assert!(pinned
+            .verify_server_cert(&other, &[], &name, &[], UnixTime::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_client_config() {
+        assert!(client_config(None, None).is_ok());
+        assert!(client_config(Some("ba7816bf"), None).is_ok());
+        let missing = Path::new("missing.pem");
+        let error = client_config(None, Some((missing, missing))).unwrap_err();
+        assert!(error.starts_with("Failed to read \"missing.pem\""));
+    }
+}